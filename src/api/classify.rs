@@ -0,0 +1,269 @@
+use reqwest::Url;
+use select::document::Document;
+use select::predicate::{Attr, Class, Name, Or};
+
+/// What a response actually turned out to be. The site answers most
+/// interstitials with `200 OK`, so the status code alone can't tell them
+/// apart from real pages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PageKind {
+    Content,
+    Captcha,
+    Blocked,
+    LoginWall,
+    NotFound,
+}
+
+// Regular pages embed a reCAPTCHA quote form and "Login / Register" links,
+// so markers are matched against the title, the final URL and a few phrases
+// that only appear on the interstitials themselves. Profile titles carry the
+// company's name, which may well be "Security Check Inc.", so titles must
+// equal a marker once the site's " | ..." suffix is cut off. Not-found
+// phrases are only looked for in headings: a company's story may say anything.
+const CAPTCHA_TITLES: &[&str] = &[
+    "captcha",
+    "are you a robot",
+    "are you a human",
+    "verify you are human",
+    "security check",
+    "just a moment",
+    "attention required",
+];
+const CAPTCHA_PHRASES: &[&str] = &[
+    "please complete the security check",
+    "please verify you are a human",
+];
+/// Elements of the interstitials of anti-bot services, by id and by class.
+const CAPTCHA_IDS: &[&str] = &["distil_r_captcha"];
+const CAPTCHA_CLASSES: &[&str] = &["cf-challenge"];
+const BLOCKED_TITLES: &[&str] = &[
+    "access denied",
+    "forbidden",
+    "403 forbidden",
+    "request rejected",
+    "request blocked",
+    "you have been blocked",
+];
+const BLOCKED_PHRASES: &[&str] = &[
+    "the requested url was rejected",
+    "you don't have permission to access",
+    "request unsuccessful. incapsula incident id",
+    "your ip address has been blocked",
+];
+const LOGIN_TITLES: &[&str] = &["login", "log in", "sign in"];
+const LOGIN_PATHS: &[&str] = &["/login.html", "/login/", "/signin"];
+const NOT_FOUND_TITLES: &[&str] = &[
+    "page not found",
+    "company not found",
+    "404",
+    "404 not found",
+    "404 - page not found",
+];
+const NOT_FOUND_PHRASES: &[&str] = &[
+    "this company profile is no longer available",
+    "the page you requested could not be found",
+    "company not found",
+];
+
+pub fn classify(url: &Url, page: &Document) -> PageKind {
+    let path = url.path().to_lowercase();
+    if LOGIN_PATHS.iter().any(|login| path.starts_with(login)) {
+        return PageKind::LoginWall;
+    }
+
+    let title = page
+        .find(Name("title"))
+        .next()
+        .map(|n| n.text().to_lowercase())
+        .unwrap_or_default();
+    let title = title
+        .rsplit_once(" | ")
+        .map_or(title.as_str(), |(head, _)| head)
+        .trim()
+        .trim_end_matches(['.', '!', '?'])
+        .trim();
+    let body = page
+        .find(Name("body"))
+        .next()
+        .map(|n| n.text().to_lowercase())
+        .unwrap_or_default();
+
+    let headings: Vec<String> = page
+        .find(Or(Name("h1"), Or(Name("h2"), Name("h3"))))
+        .map(|n| n.text().to_lowercase())
+        .collect();
+
+    let in_title = |markers: &[&str]| markers.contains(&title);
+    let in_body = |markers: &[&str]| markers.iter().any(|m| body.contains(m));
+    let in_headings = |markers: &[&str]| {
+        headings
+            .iter()
+            .any(|heading| markers.iter().any(|m| heading.contains(m)))
+    };
+    let has_element = || {
+        CAPTCHA_IDS
+            .iter()
+            .any(|id| page.find(Attr("id", *id)).next().is_some())
+            || CAPTCHA_CLASSES
+                .iter()
+                .any(|class| page.find(Class(*class)).next().is_some())
+    };
+
+    if in_title(CAPTCHA_TITLES) || in_body(CAPTCHA_PHRASES) || has_element() {
+        PageKind::Captcha
+    } else if in_title(BLOCKED_TITLES) || in_body(BLOCKED_PHRASES) {
+        PageKind::Blocked
+    } else if in_title(LOGIN_TITLES) {
+        PageKind::LoginWall
+    } else if in_title(NOT_FOUND_TITLES) || in_headings(NOT_FOUND_PHRASES) {
+        PageKind::NotFound
+    } else {
+        PageKind::Content
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::classify::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    fn company_url() -> Url {
+        url("http://www.thebluebook.com/iProView/922369")
+    }
+
+    #[test]
+    fn test_real_pages_are_content() {
+        let pages = [
+            include_str!("../../test-data/company.html"),
+            include_str!("../../test-data/locations.html"),
+            include_str!("../../test-data/contacts.html"),
+            include_str!("../../test-data/search.html"),
+            include_str!("../../test-data/category-17.html"),
+            include_str!("../../test-data/subcategory-17-642.html"),
+        ];
+        for page in pages.iter() {
+            let page = Document::from(*page);
+            assert_eq!(classify(&company_url(), &page), PageKind::Content)
+        }
+    }
+
+    #[test]
+    fn test_captcha() {
+        let page = Document::from(
+            "<html><head><title>Just a moment...</title></head>\
+             <body><div class=\"g-recaptcha\"></div></body></html>",
+        );
+        assert_eq!(classify(&company_url(), &page), PageKind::Captcha)
+    }
+
+    #[test]
+    fn test_captcha_elements() {
+        let pages = [
+            "<div id=\"distil_r_captcha\"><iframe></iframe></div>",
+            "<form class=\"challenge-form cf-challenge\" action=\"/\"></form>",
+        ];
+        for body in pages.iter() {
+            let page = Document::from(
+                format!(
+                    "<html><head><title>ProView</title></head><body>{}</body></html>",
+                    body
+                )
+                .as_str(),
+            );
+            assert_eq!(
+                classify(&company_url(), &page),
+                PageKind::Captcha,
+                "{}",
+                body
+            )
+        }
+    }
+
+    #[test]
+    fn test_blocked() {
+        let page = Document::from(
+            "<html><head><title>Request Rejected</title></head>\
+             <body>The requested URL was rejected. Please consult with your administrator.\
+             </body></html>",
+        );
+        assert_eq!(classify(&company_url(), &page), PageKind::Blocked)
+    }
+
+    #[test]
+    fn test_login_wall() {
+        let page = Document::from("<html><head><title>Home</title></head></html>");
+        let login = url("https://www.thebluebook.com/login.html?redir=www.thebluebook.com/");
+        assert_eq!(classify(&login, &page), PageKind::LoginWall);
+
+        let page = Document::from("<html><head><title>Login | The Blue Book</title></head></html>");
+        assert_eq!(classify(&company_url(), &page), PageKind::LoginWall)
+    }
+
+    #[test]
+    fn test_company_names_with_markers_are_content() {
+        let names = [
+            "Security Check Inc.",
+            "Forbidden Fence Co.",
+            "Access Denied Locksmiths",
+            "404 Construction LLC",
+            "Log Inn Construction",
+            "Login",
+            "Page Not Found Signs & Graphics",
+        ];
+        for name in names.iter() {
+            let page = Document::from(
+                format!(
+                    "<html><head><title>{} - Brooklyn, New York | ProView</title></head>\
+                     <body><div id=\"proviewStory\">About us</div></body></html>",
+                    name
+                )
+                .as_str(),
+            );
+            assert_eq!(
+                classify(&company_url(), &page),
+                PageKind::Content,
+                "{}",
+                name
+            )
+        }
+    }
+
+    #[test]
+    fn test_interstitial_titles() {
+        let titles = [
+            ("Attention Required! | Cloudflare", PageKind::Captcha),
+            ("Security Check", PageKind::Captcha),
+            ("403 Forbidden", PageKind::Blocked),
+            ("Access Denied", PageKind::Blocked),
+            ("Log In | The Blue Book", PageKind::LoginWall),
+            ("404 - Page Not Found | The Blue Book", PageKind::NotFound),
+        ];
+        for (title, kind) in titles.iter() {
+            let page = Document::from(
+                format!("<html><head><title>{}</title></head></html>", title).as_str(),
+            );
+            assert_eq!(classify(&company_url(), &page), *kind, "{}", title)
+        }
+    }
+
+    #[test]
+    fn test_not_found() {
+        let page = Document::from(
+            "<html><head><title>ProView</title></head>\
+             <body><h1>This company profile is no longer available.</h1></body></html>",
+        );
+        assert_eq!(classify(&company_url(), &page), PageKind::NotFound)
+    }
+    #[test]
+    fn test_story_mentioning_not_found_is_content() {
+        let page = Document::from(
+            "<html><head><title>Skyview A/C Corp. - Brooklyn, New York | ProView</title></head>\
+             <body><h1>Skyview A/C Corp.</h1><div id=\"proviewStory\">Tired of hearing \
+             \"company not found\" when you need a repair? We answer 24/7.</div></body></html>",
+        );
+        assert_eq!(classify(&company_url(), &page), PageKind::Content)
+    }
+}
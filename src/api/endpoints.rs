@@ -0,0 +1,126 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_BASE_URL: &str = "http://www.thebluebook.com/";
+// Relative to the base URL, so a mirror may live under a path of its own.
+pub const SEARCH_CLASSES_PATH: &str = "products/bluesearchtechnology/search-companies.html";
+pub const COMPANY_SEARCH_PATH: &str = "search.html";
+pub const COMPANY_PAGE_PATH: &str = "iProView/";
+pub const CATEGORIES_SEARCH_PATH: &str = "ajax/profile/proViewCSIClassByDiv/";
+
+/// Where each kind of page lives. Every endpoint can be moved on its own, but
+/// usually they all follow one base URL, see [`Endpoints::for_base`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Endpoints {
+    pub search_classes: Url,
+    pub company_search: Url,
+    /// Company pages are `<company_page><id>`, so this ends with a slash.
+    pub company_page: Url,
+    pub categories_search: Url,
+}
+
+impl Endpoints {
+    /// The site's usual layout under `base`. Without a trailing slash the
+    /// last segment of `base` is replaced rather than extended.
+    pub fn for_base(base: &Url) -> Result<Self, url::ParseError> {
+        Ok(Endpoints {
+            search_classes: base.join(SEARCH_CLASSES_PATH)?,
+            company_search: base.join(COMPANY_SEARCH_PATH)?,
+            company_page: base.join(COMPANY_PAGE_PATH)?,
+            categories_search: base.join(CATEGORIES_SEARCH_PATH)?,
+        })
+    }
+
+    /// Switches every endpoint to HTTPS, keeping hosts and paths.
+    pub fn with_https(mut self) -> Self {
+        for url in self.urls_mut() {
+            // Only fails for URLs that can't have a scheme like https, which
+            // endpoints never are.
+            let _ = url.set_scheme("https");
+        }
+        self
+    }
+
+    /// Moves every endpoint to another host, e.g. a staging mirror.
+    pub fn with_host(mut self, host: &str) -> Result<Self, url::ParseError> {
+        for url in self.urls_mut() {
+            url.set_host(Some(host))?;
+        }
+        Ok(self)
+    }
+
+    /// The site's home page, where a visitor would start.
+    pub fn home(&self) -> Url {
+        self.company_page
+            .join("..")
+            .expect("The company page has a parent")
+    }
+
+    pub fn company(&self, company_id: i32) -> Url {
+        self.company_page
+            .join(&company_id.to_string())
+            .expect("A company id is a valid path segment")
+    }
+
+    pub fn locations(&self, company_id: i32) -> Url {
+        self.company_page
+            .join(&format!("{}/locations-contacts/", company_id))
+            .expect("A company id is a valid path segment")
+    }
+
+    fn urls_mut(&mut self) -> Vec<&mut Url> {
+        vec![
+            &mut self.search_classes,
+            &mut self.company_search,
+            &mut self.company_page,
+            &mut self.categories_search,
+        ]
+    }
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Endpoints::for_base(&Url::parse(DEFAULT_BASE_URL).unwrap()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::endpoints::*;
+
+    #[test]
+    fn test_default_endpoints() {
+        let endpoints = Endpoints::default();
+        assert_eq!(
+            endpoints.company(400516).as_str(),
+            "http://www.thebluebook.com/iProView/400516"
+        );
+        assert_eq!(
+            endpoints.locations(400516).as_str(),
+            "http://www.thebluebook.com/iProView/400516/locations-contacts/"
+        );
+        assert_eq!(
+            endpoints.categories_search.as_str(),
+            "http://www.thebluebook.com/ajax/profile/proViewCSIClassByDiv/"
+        );
+    }
+
+    #[test]
+    fn test_moved_endpoints() {
+        let endpoints = Endpoints::default()
+            .with_https()
+            .with_host("staging.thebluebook.com")
+            .unwrap();
+        assert_eq!(
+            endpoints.company_search.as_str(),
+            "https://staging.thebluebook.com/search.html"
+        );
+
+        let mirror = Url::parse("http://localhost:8080/bluebook/").unwrap();
+        let endpoints = Endpoints::for_base(&mirror).unwrap();
+        assert_eq!(
+            endpoints.company(1).as_str(),
+            "http://localhost:8080/bluebook/iProView/1"
+        );
+    }
+}
@@ -1,20 +1,108 @@
+use reqwest::StatusCode;
 use std::fmt;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum Error {
-    ReqwestError(reqwest::Error),
+    /// The request or the HTTP client couldn't be built.
+    InvalidRequest(reqwest::Error),
+    /// The proxy address isn't usable.
+    InvalidProxy {
+        proxy: String,
+        source: reqwest::Error,
+    },
+    /// Sending the request or reading the response body failed.
+    Request { url: String, source: reqwest::Error },
+    /// The response couldn't be written to or read from the archive.
+    Archive { url: String, source: std::io::Error },
+    /// The session's cookie jar couldn't be loaded or saved.
+    CookieJar {
+        path: String,
+        source: std::io::Error,
+    },
+    /// A fixture backend has no page for this request.
+    Fixture {
+        path: String,
+        source: std::io::Error,
+    },
+    /// Replaying an archive that has no response for this request.
+    NotArchived { url: String },
+    /// The server answered with a non-success status.
+    Status { url: String, status: StatusCode },
+    /// The site wants a captcha solved before serving the page.
+    Captcha { url: String },
+    /// The site refused to serve this client, e.g. an "access denied" page.
+    Blocked { url: String },
+    /// Too many requests, the server may say when to come back.
+    RateLimited {
+        url: String,
+        retry_after: Option<Duration>,
+    },
+    /// The request ended up on the login page.
+    LoginRequired { url: String },
+    /// The page doesn't exist (anymore), including "not found" pages served
+    /// with `200 OK`.
+    NotFound { url: String },
+}
+
+impl Error {
+    /// The site is refusing this client: back off or change identity before
+    /// trying again.
+    pub fn is_block(&self) -> bool {
+        matches!(
+            self,
+            Error::Captcha { .. }
+                | Error::Blocked { .. }
+                | Error::RateLimited { .. }
+                | Error::LoginRequired { .. }
+        )
+    }
+
+    /// Retrying won't help, the requested page is gone.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Error::NotFound { .. })
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Error: {:?}", self)
+        match self {
+            Error::InvalidRequest(_) => write!(f, "cannot build request"),
+            Error::InvalidProxy { proxy, .. } => write!(f, "invalid proxy {}", proxy),
+            Error::Request { url, .. } => write!(f, "request to {} failed", url),
+            Error::Archive { url, .. } => write!(f, "archive I/O failed for {}", url),
+            Error::CookieJar { path, .. } => write!(f, "cookie jar I/O failed for {}", path),
+            Error::Fixture { path, .. } => write!(f, "cannot read fixture {}", path),
+            Error::NotArchived { url } => write!(f, "{} is not in the archive", url),
+            Error::Status { url, status } => write!(f, "{} responded with {}", url, status),
+            Error::Captcha { url } => write!(f, "{} answered with a captcha", url),
+            Error::Blocked { url } => write!(f, "{} refused access", url),
+            Error::RateLimited {
+                url,
+                retry_after: Some(retry_after),
+            } => write!(
+                f,
+                "{} is rate limited, retry after {}s",
+                url,
+                retry_after.as_secs()
+            ),
+            Error::RateLimited { url, .. } => write!(f, "{} is rate limited", url),
+            Error::LoginRequired { url } => write!(f, "{} requires a login", url),
+            Error::NotFound { url } => write!(f, "{} not found", url),
+        }
     }
 }
 
-impl std::error::Error for Error {}
-
-impl From<reqwest::Error> for Error {
-    fn from(e: reqwest::Error) -> Self {
-        Error::ReqwestError(e)
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidRequest(source) => Some(source),
+            Error::InvalidProxy { source, .. } => Some(source),
+            Error::Request { source, .. } => Some(source),
+            Error::Archive { source, .. } => Some(source),
+            Error::CookieJar { source, .. } => Some(source),
+            Error::Fixture { source, .. } => Some(source),
+            _ => None,
+        }
     }
 }
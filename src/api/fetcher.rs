@@ -0,0 +1,288 @@
+use crate::api::{CookieJar, Error, HeaderProfile, PreparedRequest, SessionConfig};
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::header::{LOCATION, SET_COOKIE};
+use reqwest::{Method, StatusCode, Url};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use tokio::sync::Mutex as AsyncMutex;
+
+/// A response before any interpretation: the `api` functions classify it and
+/// parse the body the same way whatever backend produced it.
+#[derive(Debug, Clone)]
+pub struct RawResponse {
+    /// Where the response came from, after redirects.
+    pub url: Url,
+    pub status: StatusCode,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    /// Method and headers actually sent, for archiving. The method turns
+    /// to GET when a redirect drops the form, headers are empty for offline
+    /// backends.
+    pub method: Method,
+    pub request_headers: Vec<(String, String)>,
+    /// The session the request was sent in, to pass to
+    /// [`Fetcher::renew_session`]. 0 for backends without sessions.
+    pub session: usize,
+}
+
+impl RawResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Answers page requests, from the network or from somewhere offline.
+#[async_trait]
+pub trait Fetcher: Send + Sync {
+    async fn fetch(&self, request: &PreparedRequest) -> Result<RawResponse, Error>;
+
+    /// Starts the browser session over, after the site stopped accepting
+    /// `session`, the one of the response that hit the login wall. A session
+    /// that was renewed since isn't renewed again. Returns whether there was
+    /// a session to renew, backends without one keep the default.
+    async fn renew_session(&self, _session: usize) -> Result<bool, Error> {
+        Ok(false)
+    }
+}
+
+/// Redirects to follow before giving up and returning the redirect itself.
+const MAX_REDIRECTS: usize = 10;
+
+/// The real site, over reqwest. One fetcher is one browser session: the
+/// same cookie jar and the same header profile for every request, starting
+/// with the warm-up pages.
+pub struct HttpFetcher {
+    http: reqwest::Client,
+    profile: HeaderProfile,
+    session: SessionConfig,
+    jar: Mutex<CookieJar>,
+    /// Whether the warm-up pages were visited, locked while visiting them so
+    /// concurrent requests wait for the session instead of starting their
+    /// own.
+    warmed_up: AsyncMutex<bool>,
+    /// How many times the session was renewed, so workers that hit the
+    /// login wall together start it over only once.
+    generation: AtomicUsize,
+}
+
+impl HttpFetcher {
+    /// `http` must not follow redirects itself, the fetcher does so to see
+    /// the cookies set along the way. Loads the saved cookie jar, unless it
+    /// has been idle for too long.
+    pub fn new(
+        http: reqwest::Client,
+        profile: HeaderProfile,
+        session: SessionConfig,
+    ) -> Result<Self, Error> {
+        let mut jar = match &session.cookie_jar {
+            Some(path) => CookieJar::load(path).map_err(|source| Error::CookieJar {
+                path: path.display().to_string(),
+                source,
+            })?,
+            None => CookieJar::default(),
+        };
+        if let Some(max_idle) = session.max_idle {
+            if jar.is_stale(max_idle, Utc::now()) {
+                jar.clear();
+            }
+        }
+
+        Ok(HttpFetcher {
+            http,
+            profile,
+            session,
+            jar: Mutex::new(jar),
+            warmed_up: AsyncMutex::new(false),
+            generation: AtomicUsize::new(0),
+        })
+    }
+
+    async fn warm_up(&self, warmed_up: &mut bool) -> Result<(), Error> {
+        let mut referer: Option<&Url> = None;
+        for url in &self.session.warm_up {
+            let headers: Vec<_> = referer
+                .map(|referer| ("Referer", referer.to_string()))
+                .into_iter()
+                .collect();
+            // Only the cookies matter, whatever the pages turn out to be.
+            self.send(Method::GET, url.clone(), &headers, None).await?;
+            referer = Some(url);
+        }
+        *warmed_up = true;
+        Ok(())
+    }
+
+    /// Sends one request with the profile's headers and the session's
+    /// cookies, following redirects.
+    async fn send(
+        &self,
+        mut method: Method,
+        mut url: Url,
+        own_headers: &[(&'static str, String)],
+        mut form: Option<String>,
+    ) -> Result<RawResponse, Error> {
+        let session = self.generation.load(Ordering::SeqCst);
+        let mut redirects = 0;
+        loop {
+            let mut builder = self.http.request(method.clone(), url.clone());
+            // Headers of the request itself, like `Accept` of an AJAX call,
+            // replace the profile's.
+            for (name, value) in self.profile.headers() {
+                if !own_headers
+                    .iter()
+                    .any(|(own, _)| own.eq_ignore_ascii_case(name))
+                {
+                    builder = builder.header(name, value);
+                }
+            }
+            for (name, value) in own_headers {
+                builder = builder.header(*name, value.as_str());
+            }
+            if let Some(cookies) = self.jar.lock().unwrap().header(&url, Utc::now()) {
+                builder = builder.header("Cookie", cookies);
+            }
+            if let Some(form) = &form {
+                builder = builder
+                    .header("Content-Type", "application/x-www-form-urlencoded")
+                    .body(form.clone());
+            }
+            let http_request = builder.build().map_err(Error::InvalidRequest)?;
+            let request_headers = header_pairs(http_request.headers());
+
+            let response =
+                self.http
+                    .execute(http_request)
+                    .await
+                    .map_err(|source| Error::Request {
+                        url: url.to_string(),
+                        source,
+                    })?;
+            let status = response.status();
+            let headers = header_pairs(response.headers());
+            self.store_cookies(&url, &headers)?;
+
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| url.join(location).ok());
+            match location {
+                Some(next) if status.is_redirection() && redirects < MAX_REDIRECTS => {
+                    if status == StatusCode::SEE_OTHER
+                        || (method == Method::POST
+                            && (status == StatusCode::MOVED_PERMANENTLY
+                                || status == StatusCode::FOUND))
+                    {
+                        method = Method::GET;
+                        form = None;
+                    }
+                    url = next;
+                    redirects += 1;
+                }
+                _ => {
+                    let body = response.text().await.map_err(|source| Error::Request {
+                        url: url.to_string(),
+                        source,
+                    })?;
+                    return Ok(RawResponse {
+                        url,
+                        status,
+                        headers,
+                        body,
+                        method,
+                        request_headers,
+                        session,
+                    });
+                }
+            }
+        }
+    }
+
+    fn store_cookies(&self, url: &Url, headers: &[(String, String)]) -> Result<(), Error> {
+        let set_cookies = headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(SET_COOKIE.as_str()))
+            .map(|(_, value)| value.as_str());
+        let mut jar = self.jar.lock().unwrap();
+        let now = Utc::now();
+        jar.last_used = Some(now);
+        if jar.store(url, set_cookies, now) {
+            self.save(&jar)?;
+        }
+        Ok(())
+    }
+
+    fn save(&self, jar: &CookieJar) -> Result<(), Error> {
+        match &self.session.cookie_jar {
+            Some(path) => jar.save(path).map_err(|source| Error::CookieJar {
+                path: path.display().to_string(),
+                source,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl Fetcher for HttpFetcher {
+    async fn fetch(&self, request: &PreparedRequest) -> Result<RawResponse, Error> {
+        {
+            let mut warmed_up = self.warmed_up.lock().await;
+            if !*warmed_up {
+                self.warm_up(&mut warmed_up).await?;
+            }
+        }
+        self.send(
+            request.method.clone(),
+            request.url.clone(),
+            &request.headers,
+            request.form.clone(),
+        )
+        .await
+    }
+
+    async fn renew_session(&self, session: usize) -> Result<bool, Error> {
+        if self.generation.load(Ordering::SeqCst) != session {
+            return Ok(true);
+        }
+        let mut warmed_up = self.warmed_up.lock().await;
+        if self.generation.load(Ordering::SeqCst) != session {
+            // Renewed by another worker while this one waited.
+            return Ok(true);
+        }
+        {
+            let mut jar = self.jar.lock().unwrap();
+            jar.clear();
+            self.save(&jar)?;
+        }
+        self.warm_up(&mut warmed_up).await?;
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        Ok(true)
+    }
+}
+
+/// Keeps when the session was last used, for the idle check of the next
+/// run.
+impl Drop for HttpFetcher {
+    fn drop(&mut self) {
+        if let Ok(jar) = self.jar.lock() {
+            let _ = self.save(&jar);
+        }
+    }
+}
+
+fn header_pairs(headers: &reqwest::header::HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,138 @@
+use crate::api::{Error, Fetcher, PageRequest, PreparedRequest, RawResponse};
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use std::path::{Path, PathBuf};
+
+/// Serves pages from HTML files in a directory, like `test-data/`. Each
+/// request tries a file specific to it first and then a generic one, except
+/// for categories: a division or heading without a file of its own is an
+/// error, so a company's categories are exactly the ones recorded for it.
+///
+/// | request                  | files                                   |
+/// |--------------------------|-----------------------------------------|
+/// | classes                  | `classes.html`                          |
+/// | search, keyword search   | `search-<page>.html`, `search.html`     |
+/// | company                  | `company-<id>.html`, `company.html`     |
+/// | locations                | `locations-<id>.html`, `locations.html` |
+/// | categories of a division | `category-<div>.html`                   |
+/// | categories of a heading  | `subcategory-<div>-<head>.html`         |
+pub struct FixtureFetcher {
+    dir: PathBuf,
+}
+
+impl FixtureFetcher {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        FixtureFetcher {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+}
+
+pub(crate) fn fixture_names(request: &PageRequest) -> Vec<String> {
+    match request {
+        PageRequest::Classes => vec!["classes.html".to_string()],
+        PageRequest::Search { page_number, .. }
+        | PageRequest::KeywordSearch { page_number, .. } => vec![
+            format!("search-{}.html", page_number),
+            "search.html".to_string(),
+        ],
+        PageRequest::Company(id) => {
+            vec![format!("company-{}.html", id), "company.html".to_string()]
+        }
+        PageRequest::Locations(id) => vec![
+            format!("locations-{}.html", id),
+            "locations.html".to_string(),
+        ],
+        PageRequest::Categories {
+            div_id,
+            head_id: None,
+            ..
+        } => vec![format!("category-{}.html", div_id)],
+        PageRequest::Categories {
+            div_id,
+            head_id: Some(head_id),
+            ..
+        } => vec![format!("subcategory-{}-{}.html", div_id, head_id)],
+    }
+}
+
+#[async_trait]
+impl Fetcher for FixtureFetcher {
+    async fn fetch(&self, request: &PreparedRequest) -> Result<RawResponse, Error> {
+        let names = fixture_names(&request.page);
+        let path = names
+            .iter()
+            .map(|name| self.dir.join(name))
+            .find(|path| path.is_file())
+            .unwrap_or_else(|| self.dir.join(names.last().unwrap()));
+        let body = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|source| Error::Fixture {
+                path: path.display().to_string(),
+                source,
+            })?;
+
+        Ok(RawResponse {
+            url: request.url.clone(),
+            status: StatusCode::OK,
+            headers: vec![("Content-Type".to_string(), "text/html".to_string())],
+            body,
+            method: request.method.clone(),
+            request_headers: Vec::new(),
+            session: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::fixture::*;
+    use crate::api::Endpoints;
+    use std::io;
+
+    #[tokio::test]
+    async fn test_specific_fixture_first() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("fixtures");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("company.html"), "generic").unwrap();
+        std::fs::write(dir.join("company-1.html"), "specific").unwrap();
+        let fetcher = FixtureFetcher::new(&dir);
+        let endpoints = Endpoints::default();
+
+        let specific = fetcher
+            .fetch(&PageRequest::Company(1).prepare(&endpoints))
+            .await
+            .unwrap();
+        assert_eq!(specific.body, "specific");
+        let generic = fetcher
+            .fetch(&PageRequest::Company(2).prepare(&endpoints))
+            .await
+            .unwrap();
+        assert_eq!(generic.body, "generic");
+        match fetcher
+            .fetch(&PageRequest::Locations(1).prepare(&endpoints))
+            .await
+        {
+            Err(Error::Fixture { path, source }) => {
+                assert!(path.ends_with("locations.html"));
+                assert_eq!(source.kind(), io::ErrorKind::NotFound)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unknown_division_is_an_error() {
+        let fetcher = FixtureFetcher::new("test-data");
+        let request = PageRequest::Categories {
+            company_id: 922369,
+            div_id: "99".to_string(),
+            head_id: None,
+        };
+        match fetcher.fetch(&request.prepare(&Endpoints::default())).await {
+            Err(Error::Fixture { path, .. }) => assert!(path.ends_with("category-99.html")),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
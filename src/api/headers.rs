@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+
+const BUILTIN_PROFILES: &str = include_str!("header_profiles.toml");
+
+/// The headers one browser sends with every page load. A session keeps the
+/// same profile throughout, so its cookies never change hands between
+/// browsers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeaderProfile {
+    pub name: String,
+    /// How often the profile is picked relative to the others.
+    pub weight: u32,
+    pub user_agent: String,
+    pub accept: String,
+    pub accept_language: String,
+    pub accept_encoding: String,
+    /// Client hints, only sent by Chromium based browsers.
+    #[serde(default)]
+    pub sec_ch_ua: Option<String>,
+    #[serde(default)]
+    pub sec_ch_ua_mobile: Option<String>,
+    #[serde(default)]
+    pub sec_ch_ua_platform: Option<String>,
+}
+
+impl HeaderProfile {
+    pub fn headers(&self) -> Vec<(&'static str, &str)> {
+        let mut headers = vec![
+            ("User-Agent", self.user_agent.as_str()),
+            ("Accept", self.accept.as_str()),
+            ("Accept-Language", self.accept_language.as_str()),
+            ("Accept-Encoding", self.accept_encoding.as_str()),
+        ];
+        let hints = [
+            ("sec-ch-ua", &self.sec_ch_ua),
+            ("sec-ch-ua-mobile", &self.sec_ch_ua_mobile),
+            ("sec-ch-ua-platform", &self.sec_ch_ua_platform),
+        ];
+        for (name, value) in hints.iter() {
+            if let Some(value) = value {
+                headers.push((name, value.as_str()));
+            }
+        }
+        headers
+    }
+}
+
+/// The most common browser of the built-in set.
+impl Default for HeaderProfile {
+    fn default() -> Self {
+        HeaderProfiles::builtin().profiles.remove(0)
+    }
+}
+
+/// A set of profiles to pick from, as `[[profile]]` tables of TOML.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeaderProfiles {
+    #[serde(rename = "profile")]
+    profiles: Vec<HeaderProfile>,
+}
+
+impl HeaderProfiles {
+    /// The set compiled in from `header_profiles.toml`.
+    pub fn builtin() -> Self {
+        HeaderProfiles::from_toml(BUILTIN_PROFILES).expect("Built-in header profiles are valid")
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.profiles.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    pub fn named(&self, name: &str) -> Option<&HeaderProfile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// Picks a profile by weight. The same seed always picks the same
+    /// profile from the same set. `None` if no profile has any weight.
+    pub fn choose(&self, seed: u64) -> Option<&HeaderProfile> {
+        let total: u64 = self.profiles.iter().map(|p| u64::from(p.weight)).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut ticket = splitmix64(seed) % total;
+        for profile in &self.profiles {
+            let weight = u64::from(profile.weight);
+            if ticket < weight {
+                return Some(profile);
+            }
+            ticket -= weight;
+        }
+        unreachable!("ticket is below the total weight")
+    }
+}
+
+/// Spreads consecutive seeds over the whole range, and unlike the standard
+/// library hashers is guaranteed to stay the same between releases.
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::headers::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_choose_is_weighted_and_seeded() {
+        let profiles = HeaderProfiles::builtin();
+        assert_eq!(profiles.choose(42), profiles.choose(42));
+
+        let mut picks: HashMap<&str, u32> = HashMap::new();
+        for seed in 0..10_000 {
+            *picks
+                .entry(&profiles.choose(seed).unwrap().name)
+                .or_default() += 1;
+        }
+        // Weights 45 and 10 out of 100.
+        assert!((4_000..5_000).contains(&picks["chrome-windows"]));
+        assert!((700..1_300).contains(&picks["firefox-windows"]));
+
+        let mut unweighted = profiles.clone();
+        for profile in &mut unweighted.profiles {
+            profile.weight = 0;
+        }
+        assert_eq!(unweighted.choose(42), None);
+    }
+
+    #[test]
+    fn test_client_hints_only_for_chromium() {
+        let profiles = HeaderProfiles::builtin();
+        let names = |profile: &str| -> Vec<&str> {
+            profiles
+                .named(profile)
+                .unwrap()
+                .headers()
+                .iter()
+                .map(|(name, _)| *name)
+                .collect()
+        };
+        assert!(names("chrome-windows").contains(&"sec-ch-ua-platform"));
+        assert!(!names("firefox-windows").contains(&"sec-ch-ua"));
+    }
+    #[test]
+    fn test_only_decodable_encodings() {
+        for profile in &HeaderProfiles::builtin().profiles {
+            for encoding in profile.accept_encoding.split(',') {
+                assert!(
+                    ["gzip", "br"].contains(&encoding.trim()),
+                    "{}: {}",
+                    profile.name,
+                    encoding
+                );
+            }
+        }
+    }
+}
@@ -1,30 +1,311 @@
+use crate::api::classify::{classify, PageKind};
+pub use crate::api::endpoints::Endpoints;
+#[cfg(test)]
+pub(crate) use crate::api::endpoints::{
+    CATEGORIES_SEARCH_PATH, COMPANY_PAGE_PATH, COMPANY_SEARCH_PATH, SEARCH_CLASSES_PATH,
+};
 pub use crate::api::error::Error;
-use reqwest::{Client, Proxy};
+pub use crate::api::fetcher::{Fetcher, HttpFetcher, RawResponse};
+#[cfg(test)]
+pub(crate) use crate::api::fixture::fixture_names;
+pub use crate::api::fixture::FixtureFetcher;
+pub use crate::api::headers::{HeaderProfile, HeaderProfiles};
+pub use crate::api::replay::{RecordingFetcher, ReplayFetcher};
+pub use crate::api::session::{CookieJar, SessionConfig};
+use crate::archive::{ArchiveReader, Archiver};
+use reqwest::header::RETRY_AFTER;
+use reqwest::redirect::Policy;
+use reqwest::{Method, Proxy, StatusCode, Url};
 use select::document::Document;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
 
+mod classify;
+mod endpoints;
 pub mod error;
-
-const SEARCH_CLASSES_URL: &str =
-    "http://www.thebluebook.com/products/bluesearchtechnology/search-companies.html";
-const COMPANY_SEARCH_URL: &str = "http://www.thebluebook.com/search.html";
-const COMPANY_PAGE_URL: &str = "http://www.thebluebook.com/iProView";
-const CATEGORIES_SEARCH_URL: &str = "http://www.thebluebook.com/ajax/profile/proViewCSIClassByDiv/";
+mod fetcher;
+mod fixture;
+mod headers;
+mod replay;
+mod session;
 
 pub type ApiResult = Result<Document, Error>;
 
-async fn get_random_user_agent() -> &'static str {
-    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/60.0.3112.113 Safari/537.36"
+/// Which kind of page a request is for, recorded along with archived
+/// responses so they can be found again without parsing URLs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageType {
+    Classes,
+    Search,
+    Company,
+    Locations,
+    Categories,
+}
+
+/// Everything the scraper ever asks the site for. Backends turn these into
+/// responses, from the network or from files.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageRequest {
+    Classes,
+    Search {
+        class_id: i32,
+        page_number: i32,
+        city: String,
+    },
+    KeywordSearch {
+        term: String,
+        within: Option<String>,
+        page_number: i32,
+        city: String,
+    },
+    Company(i32),
+    Locations(i32),
+    Categories {
+        company_id: i32,
+        div_id: String,
+        head_id: Option<String>,
+    },
+}
+
+impl PageRequest {
+    pub fn page_type(&self) -> PageType {
+        match self {
+            PageRequest::Classes => PageType::Classes,
+            PageRequest::Search { .. } | PageRequest::KeywordSearch { .. } => PageType::Search,
+            PageRequest::Company(_) => PageType::Company,
+            PageRequest::Locations(_) => PageType::Locations,
+            PageRequest::Categories { .. } => PageType::Categories,
+        }
+    }
+
+    pub fn company_id(&self) -> Option<i32> {
+        match self {
+            PageRequest::Company(id) | PageRequest::Locations(id) => Some(*id),
+            PageRequest::Categories { company_id, .. } => Some(*company_id),
+            _ => None,
+        }
+    }
+
+    /// Resolves the request against the site's `endpoints`.
+    pub fn prepare(&self, endpoints: &Endpoints) -> PreparedRequest {
+        let mut method = Method::GET;
+        let mut form = None;
+        let mut headers = Vec::new();
+
+        let url = match self {
+            PageRequest::Classes => endpoints.search_classes.clone(),
+            PageRequest::Search {
+                class_id,
+                page_number,
+                city,
+            } => {
+                let mut url = endpoints.company_search.clone();
+                url.query_pairs_mut()
+                    .append_pair("class", &class_id.to_string())
+                    .append_pair("region", "1")
+                    .append_pair("page", &page_number.to_string())
+                    .append_pair("city", city)
+                    .append_pair("geographicalarea", "New+York+City")
+                    .append_pair("searchsrc", "index")
+                    .append_pair("regionLabel", city);
+                url
+            }
+            PageRequest::KeywordSearch {
+                term,
+                within,
+                page_number,
+                city,
+            } => {
+                let mut url = endpoints.company_search.clone();
+                {
+                    let mut query = url.query_pairs_mut();
+                    query
+                        .append_pair("page", &page_number.to_string())
+                        .append_pair("searchTerm", term)
+                        .append_pair("searchsrc", "index")
+                        .append_pair("regionLabel", city);
+                    if let Some(within) = within {
+                        query.append_pair("pSeachinp", within);
+                    }
+                }
+                url
+            }
+            PageRequest::Company(id) => endpoints.company(*id),
+            PageRequest::Locations(id) => endpoints.locations(*id),
+            PageRequest::Categories {
+                company_id,
+                div_id,
+                head_id,
+            } => {
+                let mut body = url::form_urlencoded::Serializer::new(String::new());
+                body.append_pair("qp", &company_id.to_string())
+                    .append_pair("id", div_id)
+                    .append_pair("v", "7");
+                if let Some(head_id) = head_id {
+                    body.append_pair("headingID", head_id);
+                }
+                method = Method::POST;
+                form = Some(body.finish());
+                headers.push(("X-Requested-With", "XMLHttpRequest".to_string()));
+                headers.push(("Accept", "text/html, */*; q=0.01".to_string()));
+                // The site's own script sends these from the company's page.
+                headers.push(("Referer", endpoints.company(*company_id).to_string()));
+                endpoints.categories_search.clone()
+            }
+        };
+
+        PreparedRequest {
+            page: self.clone(),
+            method,
+            url,
+            form,
+            headers,
+        }
+    }
 }
 
-pub async fn client_factory() -> Client {
-    let proxy =
-        Proxy::http("socks5://127.0.0.1:9050").expect("Cannot build proxy for reqwest client");
+/// A [`PageRequest`] resolved to what goes over the wire, so every backend
+/// sees the same URL and form.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedRequest {
+    pub page: PageRequest,
+    pub method: Method,
+    pub url: Url,
+    /// URL-encoded body of POST requests.
+    pub form: Option<String>,
+    /// Headers the site expects on this particular request.
+    pub headers: Vec<(&'static str, String)>,
+}
 
-    Client::builder()
-        .proxy(proxy)
-        .cookie_store(true)
-        .build()
-        .expect("Cannot build reqwest client")
+/// Handle to the backend that answers requests, cheap to clone and share.
+#[derive(Clone)]
+pub struct Client {
+    fetcher: Arc<dyn Fetcher>,
+    endpoints: Endpoints,
+}
+
+impl Client {
+    pub fn new(fetcher: impl Fetcher + 'static) -> Self {
+        Client {
+            fetcher: Arc::new(fetcher),
+            endpoints: Endpoints::default(),
+        }
+    }
+
+    /// Points requests at another copy of the site, e.g. a mirror or a mock
+    /// server.
+    pub fn with_endpoints(mut self, endpoints: Endpoints) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    /// Archives every exchange of the current backend.
+    pub fn with_archiver(self, archiver: Arc<Archiver>) -> Self {
+        Client {
+            fetcher: Arc::new(RecordingFetcher::new(self.fetcher, archiver)),
+            endpoints: self.endpoints,
+        }
+    }
+
+    /// A client that never touches the network and answers every request
+    /// with the latest archived response to it.
+    pub fn replay(reader: Arc<ArchiveReader>) -> Self {
+        Client::new(ReplayFetcher::new(reader))
+    }
+
+    /// A client serving pages from a directory of fixtures, see
+    /// [`FixtureFetcher`].
+    pub fn fixtures(dir: impl AsRef<Path>) -> Self {
+        Client::new(FixtureFetcher::new(dir))
+    }
+}
+
+pub const DEFAULT_PROXY: &str = "socks5://127.0.0.1:9050";
+
+/// A client for the real site, through `proxy` (e.g. [`DEFAULT_PROXY`] for a
+/// local Tor) or directly when there is none, passing for the browser of
+/// `profile` for the whole `session`.
+pub async fn client_factory(
+    proxy: Option<&str>,
+    profile: HeaderProfile,
+    session: SessionConfig,
+) -> Result<Client, Error> {
+    // The session keeps the cookies and follows redirects itself.
+    let mut builder = reqwest::Client::builder().redirect(Policy::none());
+    if let Some(proxy) = proxy {
+        let invalid_proxy = |source| Error::InvalidProxy {
+            proxy: proxy.to_string(),
+            source,
+        };
+        builder = builder.proxy(Proxy::all(proxy).map_err(invalid_proxy)?);
+    }
+    let http = builder.build().map_err(Error::InvalidRequest)?;
+
+    Ok(Client::new(HttpFetcher::new(http, profile, session)?))
+}
+
+/// Gets a page from the backend and parses it. Every error carries the URL
+/// it happened on, and pages that aren't what was asked for (captchas, block
+/// pages, login walls, soft 404s) are reported as errors of their own. A
+/// login wall usually means the session expired, so the request is tried
+/// once more in a new one first.
+async fn fetch(client: &Client, request: PageRequest) -> ApiResult {
+    let request = request.prepare(&client.endpoints);
+    let url = request.url.to_string();
+    let mut renewed = false;
+    let (response, page) = loop {
+        let started = Instant::now();
+        let response = client.fetcher.fetch(&request).await.map_err(|e| {
+            debug!(url = %url, error = %e, "request failed");
+            e
+        })?;
+        let page = Document::from(response.body.as_str());
+        let kind = classify(&response.url, &page);
+        debug!(
+            url = %response.url,
+            status = response.status.as_u16(),
+            bytes = response.body.len(),
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            page = ?kind,
+            "fetched"
+        );
+
+        match kind {
+            PageKind::Captcha => return Err(Error::Captcha { url }),
+            PageKind::Blocked => return Err(Error::Blocked { url }),
+            PageKind::LoginWall
+                if !renewed && client.fetcher.renew_session(response.session).await? =>
+            {
+                info!(url = %url, "session expired, renewed it");
+                renewed = true;
+            }
+            PageKind::LoginWall => return Err(Error::LoginRequired { url }),
+            PageKind::NotFound => return Err(Error::NotFound { url }),
+            PageKind::Content => break (response, page),
+        }
+    };
+
+    match response.status {
+        StatusCode::TOO_MANY_REQUESTS => {
+            let retry_after = response
+                .header(RETRY_AFTER.as_str())
+                .and_then(|value| value.trim().parse().ok())
+                .map(Duration::from_secs);
+            Err(Error::RateLimited { url, retry_after })
+        }
+        StatusCode::FORBIDDEN => Err(Error::Blocked { url }),
+        StatusCode::NOT_FOUND | StatusCode::GONE => Err(Error::NotFound { url }),
+        status if !status.is_success() => Err(Error::Status { url, status }),
+        _ => Ok(page),
+    }
+}
+
+pub async fn get_classes_page(client: &Client) -> ApiResult {
+    fetch(client, PageRequest::Classes).await
 }
 
 pub async fn get_search_page(
@@ -33,46 +314,36 @@ pub async fn get_search_page(
     page_number: i32,
     city: &str,
 ) -> ApiResult {
-    let response = client
-        .get(COMPANY_SEARCH_URL)
-        .query(&[("class", class_id), ("region", 1), ("page", page_number)])
-        .query(&[
-            ("city", city),
-            ("geographicalarea", "New+York+City"),
-            ("searchsrc", "index"),
-            ("regionLabel", city),
-        ])
-        .header("User-Agent", get_random_user_agent().await)
-        .send()
-        .await?
-        .error_for_status()?;
-
-    let page = Document::from(response.text().await?.as_str());
-    Ok(page)
+    let request = PageRequest::Search {
+        class_id,
+        page_number,
+        city: city.to_string(),
+    };
+    fetch(client, request).await
 }
 
-pub async fn get_company_page(client: &Client, company_id: i32) -> ApiResult {
-    let response = client
-        .get(format!("{}/{}", COMPANY_PAGE_URL, company_id).as_str())
-        .header("User-Agent", get_random_user_agent().await)
-        .send()
-        .await?
-        .error_for_status()?;
-
-    let page = Document::from(response.text().await?.as_str());
-    Ok(page)
+pub async fn get_keyword_search_page(
+    client: &Client,
+    term: &str,
+    within: Option<&str>,
+    page_number: i32,
+    city: &str,
+) -> ApiResult {
+    let request = PageRequest::KeywordSearch {
+        term: term.to_string(),
+        within: within.map(str::to_string),
+        page_number,
+        city: city.to_string(),
+    };
+    fetch(client, request).await
 }
 
-pub async fn get_locations_page(client: Client, company_id: i32) -> ApiResult {
-    let response = client
-        .get(format!("{}/{}/locations-contacts/", COMPANY_PAGE_URL, company_id).as_str())
-        .header("User-Agent", get_random_user_agent().await)
-        .send()
-        .await?
-        .error_for_status()?;
+pub async fn get_company_page(client: &Client, company_id: i32) -> ApiResult {
+    fetch(client, PageRequest::Company(company_id)).await
+}
 
-    let page = Document::from(response.text().await?.as_str());
-    Ok(page)
+pub async fn get_locations_page(client: &Client, company_id: i32) -> ApiResult {
+    fetch(client, PageRequest::Locations(company_id)).await
 }
 
 pub async fn get_categories_page(
@@ -81,65 +352,228 @@ pub async fn get_categories_page(
     div_id: &str,
     head_id: Option<&str>,
 ) -> ApiResult {
-    let company_id_str = company_id.to_string();
-    let mut params = vec![("qp", company_id_str.as_str()), ("id", div_id), ("v", "7")];
-
-    match head_id {
-        Some(id) => params.push(("headingID", id)),
-        None => (),
-    }
-
-    let request = client
-        .post(CATEGORIES_SEARCH_URL)
-        .form(&params)
-        .header("User-Agent", get_random_user_agent().await)
-        .header("X-Requested-With", "XMLHttpRequest")
-        .header("Referer", "http://www.thebluebook.com/iProView/400516")
-        .build()?;
-    let response = client.execute(request).await?.error_for_status()?;
-
-    let page = Document::from(response.text().await?.as_str());
-    Ok(page)
+    let request = PageRequest::Categories {
+        company_id,
+        div_id: div_id.to_string(),
+        head_id: head_id.map(str::to_string),
+    };
+    fetch(client, request).await
 }
 
 #[cfg(test)]
 mod tests {
     use crate::api::*;
+    use crate::mock::{Fault, MockServer};
+    use select::predicate::{Attr, Class, Name};
+
+    #[test]
+    fn test_page_requests() {
+        let endpoints = Endpoints::default();
+        let search = PageRequest::Search {
+            class_id: 4030,
+            page_number: 1,
+            city: "New York, NY".to_string(),
+        };
+        assert_eq!(
+            search.prepare(&endpoints).url.as_str(),
+            "http://www.thebluebook.com/search.html?class=4030&region=1&page=1\
+             &city=New+York%2C+NY&geographicalarea=New%2BYork%2BCity&searchsrc=index\
+             &regionLabel=New+York%2C+NY"
+        );
+        let keyword = PageRequest::KeywordSearch {
+            term: "curtain wall".to_string(),
+            within: None,
+            page_number: 1,
+            city: "New York, NY".to_string(),
+        };
+        assert_eq!(
+            keyword.prepare(&endpoints).url.as_str(),
+            "http://www.thebluebook.com/search.html?page=1&searchTerm=curtain+wall\
+             &searchsrc=index&regionLabel=New+York%2C+NY"
+        );
+        let categories = PageRequest::Categories {
+            company_id: 400516,
+            div_id: "17".to_string(),
+            head_id: Some("642".to_string()),
+        };
+        assert_eq!(categories.page_type(), PageType::Categories);
+        assert_eq!(categories.company_id(), Some(400516));
+        let categories = categories.prepare(&endpoints);
+        assert_eq!(categories.method, Method::POST);
+        assert_eq!(
+            categories.form.as_deref(),
+            Some("qp=400516&id=17&v=7&headingID=642")
+        );
+        assert!(categories.headers.contains(&(
+            "Referer",
+            "http://www.thebluebook.com/iProView/400516".to_string()
+        )));
+    }
+
+    async fn mock_client() -> (MockServer, Client) {
+        let server = MockServer::start().await;
+        let endpoints = Endpoints::for_base(&server.base_url()).unwrap();
+        let client = client_factory(None, HeaderProfile::default(), SessionConfig::default())
+            .await
+            .unwrap()
+            .with_endpoints(endpoints);
+        (server, client)
+    }
 
     #[tokio::test]
     async fn test_get_categories() {
-        let client = client_factory().await;
+        let (_server, client) = mock_client().await;
         let page = get_categories_page(&client, 400516, "17", None)
             .await
             .unwrap();
+        assert_eq!(page.find(Attr("data-headid", "642")).count(), 1);
     }
 
     #[tokio::test]
     async fn test_get_categories_with_heading_id() {
-        let mut client = client_factory().await;
+        let (server, client) = mock_client().await;
+        server.template(
+            "subcategory-17-642.html",
+            "<div><strong>21 01 10</strong> of {{company_id}}</div>",
+        );
 
-        let page = get_categories_page(&mut client, 400516, "17", Some("642"))
+        let page = get_categories_page(&client, 400516, "17", Some("642"))
             .await
             .unwrap();
+        assert_eq!(
+            page.find(Name("div")).next().unwrap().text(),
+            "21 01 10 of 400516"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_classes_page() {
+        let (_server, client) = mock_client().await;
+        let page = get_classes_page(&client).await.unwrap();
+        assert!(page.find(Name("a")).next().is_some());
     }
 
     #[tokio::test]
     async fn test_get_page() {
-        let client = client_factory().await;
+        let (server, client) = mock_client().await;
         let page = get_search_page(&client, 4030, 1, "New York, NY")
             .await
             .unwrap();
+        assert!(page.find(Class("cname")).next().is_some());
+        assert_eq!(server.hits("/search.html"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_keyword_page() {
+        let (_server, client) = mock_client().await;
+        let page = get_keyword_search_page(&client, "curtain wall", None, 1, "New York, NY")
+            .await
+            .unwrap();
+        assert!(page.find(Class("cname")).next().is_some());
     }
 
     #[tokio::test]
     async fn test_company_page() {
-        let client = client_factory().await;
+        let (_server, client) = mock_client().await;
         let page = get_company_page(&client, 400516).await.unwrap();
+        assert!(page.find(Attr("id", "proviewStory")).next().is_some());
     }
 
     #[tokio::test]
     async fn test_locations_page() {
-        let client = client_factory().await;
-        let page = get_locations_page(client, 400516).await.unwrap();
+        let (_server, client) = mock_client().await;
+        let page = get_locations_page(&client, 400516).await.unwrap();
+        assert!(page.find(Attr("id", "keyContactSection")).next().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_injected_faults() {
+        let (server, client) = mock_client().await;
+        server.push_fault("/iProView/", Fault::RateLimit { retry_after: 7 });
+        server.push_fault("/iProView/", Fault::Status(500));
+        server.push_fault("/iProView/", Fault::Captcha);
+        server.push_fault("/iProView/", Fault::Delay(Duration::from_millis(50)));
+
+        match get_company_page(&client, 400516).await {
+            Err(Error::RateLimited { retry_after, .. }) => {
+                assert_eq!(retry_after, Some(Duration::from_secs(7)))
+            }
+            other => panic!("expected rate limit, got {:?}", other.err()),
+        }
+        match get_company_page(&client, 400516).await {
+            Err(Error::Status { status, .. }) => assert_eq!(status.as_u16(), 500),
+            other => panic!("expected status error, got {:?}", other.err()),
+        }
+        let captcha = get_company_page(&client, 400516).await.unwrap_err();
+        assert!(captcha.is_block());
+        assert!(get_company_page(&client, 400516).await.is_ok());
+        assert_eq!(server.hits("/iProView/"), 4);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_company_is_not_found() {
+        let (server, client) = mock_client().await;
+        server.template(
+            "company-1.html",
+            "<html><head><title>Page Not Found</title></head></html>",
+        );
+        let error = get_company_page(&client, 1).await.unwrap_err();
+        assert!(error.is_not_found());
+    }
+
+    #[tokio::test]
+    async fn test_session_warm_up_and_renewal() {
+        let temp = tempfile::tempdir().unwrap();
+        let server = MockServer::start().await;
+        server.set_cookie("/warm-up", "PHPSESSID=abc; path=/");
+        let endpoints = Endpoints::for_base(&server.base_url()).unwrap();
+        let jar = temp.path().join("session-cookies.json");
+        let session = SessionConfig {
+            warm_up: vec![endpoints.home().join("warm-up.html").unwrap()],
+            cookie_jar: Some(jar.clone()),
+            max_idle: None,
+        };
+        let client = client_factory(None, HeaderProfile::default(), session.clone())
+            .await
+            .unwrap()
+            .with_endpoints(endpoints.clone());
+
+        get_company_page(&client, 400516).await.unwrap();
+        get_locations_page(&client, 400516).await.unwrap();
+        assert_eq!(server.hits("/warm-up"), 1);
+        assert_eq!(
+            server.cookies_sent("/iProView/"),
+            vec![Some("PHPSESSID=abc".to_string()); 2]
+        );
+
+        // Expired sessions end up on the login page.
+        server.push_fault("/iProView/", Fault::Redirect("/login.html".to_string()));
+        get_company_page(&client, 400516).await.unwrap();
+        assert_eq!(server.hits("/warm-up"), 2);
+        assert_eq!(server.hits("/login.html"), 1);
+
+        // Workers that find the session gone together renew it once.
+        let (first, second) = futures::join!(
+            client.fetcher.renew_session(1),
+            client.fetcher.renew_session(1)
+        );
+        assert!(first.unwrap() && second.unwrap());
+        assert_eq!(server.hits("/warm-up"), 3);
+        // So do those whose response predates a renewal that's done.
+        assert!(client.fetcher.renew_session(1).await.unwrap());
+        assert_eq!(server.hits("/warm-up"), 3);
+
+        // The next run picks up the saved session.
+        drop(client);
+        let client = client_factory(None, HeaderProfile::default(), session)
+            .await
+            .unwrap()
+            .with_endpoints(endpoints);
+        server.set_cookie("/warm-up", "PHPSESSID=def; path=/");
+        get_company_page(&client, 400516).await.unwrap();
+        assert_eq!(
+            server.cookies_sent("/warm-up").last().unwrap().as_deref(),
+            Some("PHPSESSID=abc")
+        );
     }
 }
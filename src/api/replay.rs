@@ -0,0 +1,172 @@
+use crate::api::{Error, Fetcher, PreparedRequest, RawResponse};
+use crate::archive::{ArchiveReader, Archiver, Exchange, Redirect};
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::StatusCode;
+use std::io;
+use std::sync::Arc;
+
+/// Passes requests on to another backend and archives every exchange.
+pub struct RecordingFetcher {
+    inner: Arc<dyn Fetcher>,
+    archiver: Arc<Archiver>,
+}
+
+impl RecordingFetcher {
+    pub fn new(inner: Arc<dyn Fetcher>, archiver: Arc<Archiver>) -> Self {
+        RecordingFetcher { inner, archiver }
+    }
+}
+
+#[async_trait]
+impl Fetcher for RecordingFetcher {
+    async fn fetch(&self, request: &PreparedRequest) -> Result<RawResponse, Error> {
+        let response = self.inner.fetch(request).await?;
+        let url = request.url.to_string();
+        let form = request.form.as_ref().map(String::as_bytes);
+        let final_url = response.url.to_string();
+        let redirect = if response.url != request.url {
+            Some(Redirect {
+                url: &final_url,
+                method: response.method.as_str(),
+                body: form.filter(|_| response.method == request.method),
+            })
+        } else {
+            None
+        };
+
+        let exchange = Exchange {
+            page_type: request.page.page_type(),
+            company_id: request.page.company_id(),
+            method: request.method.as_str(),
+            url: &url,
+            request_headers: response.request_headers.clone(),
+            request_body: form,
+            status: response.status.as_u16(),
+            reason: response.status.canonical_reason().unwrap_or(""),
+            response_headers: response.headers.clone(),
+            body: &response.body,
+            timestamp: Utc::now(),
+            redirect,
+        };
+        self.archiver
+            .record(&exchange)
+            .map_err(|source| Error::Archive { url, source })?;
+
+        Ok(response)
+    }
+
+    async fn renew_session(&self, session: usize) -> Result<bool, Error> {
+        self.inner.renew_session(session).await
+    }
+}
+
+/// Answers every request with the latest archived response to it and never
+/// touches the network.
+pub struct ReplayFetcher {
+    reader: Arc<ArchiveReader>,
+}
+
+impl ReplayFetcher {
+    pub fn new(reader: Arc<ArchiveReader>) -> Self {
+        ReplayFetcher { reader }
+    }
+}
+
+#[async_trait]
+impl Fetcher for ReplayFetcher {
+    async fn fetch(&self, request: &PreparedRequest) -> Result<RawResponse, Error> {
+        let url = &request.url;
+        let entry = self
+            .reader
+            .find(
+                request.page.page_type(),
+                request.page.company_id(),
+                url.as_str(),
+                request.form.as_deref(),
+            )
+            .ok_or_else(|| Error::NotArchived {
+                url: url.to_string(),
+            })?;
+        let archive_error = |source| Error::Archive {
+            url: url.to_string(),
+            source,
+        };
+
+        let response = self.reader.read(entry).map_err(archive_error)?;
+        let status = StatusCode::from_u16(response.status).map_err(|_| {
+            archive_error(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid status code",
+            ))
+        })?;
+
+        Ok(RawResponse {
+            url: url.clone(),
+            status,
+            headers: response.headers,
+            body: response.body,
+            method: request.method.clone(),
+            request_headers: Vec::new(),
+            session: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::replay::*;
+    use crate::api::{
+        client_factory, get_company_page, Client, Endpoints, HeaderProfile, PageType, SessionConfig,
+    };
+    use crate::archive::DEFAULT_MAX_FILE_SIZE;
+    use crate::mock::{Fault, MockServer};
+    use std::fs;
+
+    #[tokio::test]
+    async fn test_record_redirected_exchange() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("archive");
+        let server = MockServer::start().await;
+        server.set_cookie("/warm-up", "PHPSESSID=abc; path=/");
+        let endpoints = Endpoints::for_base(&server.base_url()).unwrap();
+        let session = SessionConfig {
+            warm_up: vec![endpoints.home().join("warm-up.html").unwrap()],
+            cookie_jar: None,
+            max_idle: None,
+        };
+        let archiver = Arc::new(Archiver::open(&dir, DEFAULT_MAX_FILE_SIZE).unwrap());
+        let client = client_factory(None, HeaderProfile::default(), session)
+            .await
+            .unwrap()
+            .with_endpoints(endpoints.clone())
+            .with_archiver(archiver);
+        server.push_fault(
+            "/iProView/922369",
+            Fault::Redirect("/iProView/400516".to_string()),
+        );
+        get_company_page(&client, 922369).await.unwrap();
+        assert_eq!(
+            server.cookies_sent("/iProView/400516"),
+            vec![Some("PHPSESSID=abc".to_string())]
+        );
+
+        // Indexed by the request as made, recorded as the last hop.
+        let reader = Arc::new(ArchiveReader::open(&dir).unwrap());
+        let entry = reader
+            .find(PageType::Company, Some(922369), "", None)
+            .unwrap();
+        assert!(entry.url.ends_with("/iProView/922369"));
+        let warc = fs::read_to_string(dir.join(&entry.file)).unwrap();
+        let request = &warc[entry.request_offset as usize..entry.response_offset as usize];
+        let response = &warc[entry.response_offset as usize..];
+        let target = format!("WARC-Target-URI: {}iProView/400516\r\n", server.base_url());
+        assert!(request.contains(&target));
+        assert!(response.contains(&target));
+        assert!(request.contains("GET /iProView/400516 HTTP/1.1\r\n"));
+        assert!(!warc.contains("PHPSESSID"));
+
+        let replay = Client::replay(reader);
+        get_company_page(&replay, 922369).await.unwrap();
+    }
+}
@@ -0,0 +1,328 @@
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+const MAX_COOKIE_AGE: i64 = 400 * 24 * 60 * 60;
+
+/// Suffixes under which anyone can register a name, so no site may set a
+/// cookie for all of them. Every single label is one as well.
+const PUBLIC_SUFFIXES: &[&str] = &[
+    "co.uk",
+    "org.uk",
+    "ac.uk",
+    "gov.uk",
+    "com.au",
+    "net.au",
+    "org.au",
+    "co.nz",
+    "co.jp",
+    "co.in",
+    "com.br",
+    "com.cn",
+    "com.mx",
+    "co.za",
+    "github.io",
+    "herokuapp.com",
+];
+
+/// How a browser session starts, and where it is kept between runs.
+#[derive(Debug, Clone, Default)]
+pub struct SessionConfig {
+    /// Pages to visit, in order, before the first real request and again
+    /// whenever the session has to be re-established.
+    pub warm_up: Vec<Url>,
+    /// Where the cookie jar is loaded from and saved to. Kept in memory only
+    /// when there is none.
+    pub cookie_jar: Option<PathBuf>,
+    /// A saved session unused for longer than this is treated as expired.
+    pub max_idle: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// Lowercase, without a leading dot.
+    pub domain: String,
+    /// Set without a `Domain` attribute, so only sent back to that host.
+    pub host_only: bool,
+    pub path: String,
+    pub secure: bool,
+    /// `None` for session cookies, which are kept anyway: the site's session
+    /// id is one.
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl Cookie {
+    /// Parses a `Set-Cookie` header received from `url`. `None` for headers
+    /// that are malformed or that the site isn't allowed to set.
+    pub fn parse(url: &Url, header: &str, now: DateTime<Utc>) -> Option<Cookie> {
+        let host = url.host_str()?.to_lowercase();
+        let mut attributes = header.split(';');
+        let (name, value) = split_pair(attributes.next()?)?;
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: value.trim_matches('"').to_string(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(url),
+            secure: false,
+            expires: None,
+        };
+        let mut max_age = None;
+        for attribute in attributes {
+            let (key, value) = split_pair(attribute).unwrap_or((attribute.trim(), ""));
+            match key.to_lowercase().as_str() {
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_lowercase();
+                    if is_public_suffix(&domain) || !domain_matches(&host, &domain) {
+                        return None;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "max-age" => max_age = value.parse::<i64>().ok(),
+                "expires" => {
+                    if let Some(expires) = parse_cookie_date(value) {
+                        cookie.expires = cookie.expires.or(Some(expires));
+                    }
+                }
+                _ => (),
+            }
+        }
+        // Max-Age wins over Expires. Browsers cap both at 400 days.
+        if let Some(seconds) = max_age {
+            cookie.expires = Some(now + Duration::seconds(seconds.clamp(0, MAX_COOKIE_AGE)));
+        }
+        Some(cookie)
+    }
+
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        matches!(self.expires, Some(expires) if expires <= now)
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let host = match url.host_str() {
+            Some(host) => host.to_lowercase(),
+            None => return false,
+        };
+        let host_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(&host, &self.domain)
+        };
+        host_ok && path_matches(url.path(), &self.path) && (!self.secure || url.scheme() == "https")
+    }
+}
+
+/// Cookies of one session, in the order they were first set.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+    /// When a request last went out with this jar.
+    #[serde(default)]
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+impl CookieJar {
+    /// Reads a saved jar, or starts an empty one when there is none.
+    pub fn load(path: &Path) -> io::Result<CookieJar> {
+        match File::open(path) {
+            Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(CookieJar::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the jar through a temporary file, so an interrupted save
+    /// leaves the previous one intact.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        serde_json::to_writer_pretty(BufWriter::new(File::create(&temp)?), self)?;
+        fs::rename(&temp, path)
+    }
+
+    /// Whether the session was left unused for longer than `max_idle`.
+    pub fn is_stale(&self, max_idle: Duration, now: DateTime<Utc>) -> bool {
+        matches!(self.last_used, Some(used) if now - used > max_idle)
+    }
+
+    pub fn clear(&mut self) {
+        self.cookies.clear();
+        self.last_used = None;
+    }
+
+    /// Takes in the `Set-Cookie` headers of a response from `url`. Returns
+    /// whether anything changed.
+    pub fn store<'a>(
+        &mut self,
+        url: &Url,
+        headers: impl IntoIterator<Item = &'a str>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let mut changed = false;
+        for header in headers {
+            let cookie = match Cookie::parse(url, header, now) {
+                Some(cookie) => cookie,
+                None => continue,
+            };
+            let existing = self.cookies.iter().position(|c| {
+                c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path
+            });
+            match (existing, cookie.is_expired(now)) {
+                (Some(index), true) => {
+                    self.cookies.remove(index);
+                }
+                (Some(index), false) => self.cookies[index] = cookie,
+                (None, true) => continue,
+                (None, false) => self.cookies.push(cookie),
+            }
+            changed = true;
+        }
+        changed
+    }
+
+    /// The `Cookie` header to send to `url`, if any cookie applies. Longer
+    /// paths go first, as browsers send them.
+    pub fn header(&self, url: &Url, now: DateTime<Utc>) -> Option<String> {
+        let mut cookies: Vec<&Cookie> = self
+            .cookies
+            .iter()
+            .filter(|cookie| !cookie.is_expired(now) && cookie.matches(url))
+            .collect();
+        if cookies.is_empty() {
+            return None;
+        }
+        cookies.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+        let pairs: Vec<String> = cookies
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect();
+        Some(pairs.join("; "))
+    }
+}
+
+fn split_pair(pair: &str) -> Option<(&str, &str)> {
+    let mut parts = pair.splitn(2, '=');
+    Some((parts.next()?.trim(), parts.next()?.trim()))
+}
+
+fn is_public_suffix(domain: &str) -> bool {
+    !domain.contains('.') || PUBLIC_SUFFIXES.contains(&domain)
+}
+
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
+/// The directory of the request path, the path a cookie gets without a
+/// `Path` attribute.
+fn default_path(url: &Url) -> String {
+    let path = url.path();
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(end) => path[..end].to_string(),
+    }
+}
+
+/// `Expires` dates come as RFC 1123 and in the older dashed form.
+fn parse_cookie_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc2822(value) {
+        return Some(date.with_timezone(&Utc));
+    }
+    NaiveDateTime::parse_from_str(value, "%a, %d-%b-%Y %H:%M:%S GMT")
+        .ok()
+        .map(|date| date.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::session::*;
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2020, 3, 1, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_cookie_matching() {
+        let page = Url::parse("http://www.thebluebook.com/iProView/400516/").unwrap();
+        let mut jar = CookieJar::default();
+        jar.store(
+            &page,
+            vec![
+                "PHPSESSID=abc; path=/; HttpOnly",
+                "visitor=1; Domain=.thebluebook.com; Path=/; Expires=Wed, 01 Apr 2020 00:00:00 GMT",
+                "profile=400516",
+                "elsewhere=1; Domain=example.com",
+                "gone=1; Expires=Sun, 01-Mar-2020 11:00:00 GMT",
+            ],
+            now(),
+        );
+
+        assert_eq!(
+            jar.header(&page, now()).unwrap(),
+            "profile=400516; PHPSESSID=abc; visitor=1"
+        );
+        let ajax = Url::parse("http://www.thebluebook.com/ajax/profile/").unwrap();
+        assert_eq!(
+            jar.header(&ajax, now()).unwrap(),
+            "PHPSESSID=abc; visitor=1"
+        );
+        let other = Url::parse("http://static.thebluebook.com/").unwrap();
+        assert_eq!(jar.header(&other, now()).unwrap(), "visitor=1");
+
+        let later = Utc.with_ymd_and_hms(2020, 4, 2, 0, 0, 0).unwrap();
+        assert_eq!(jar.header(&other, later), None);
+
+        assert!(jar.store(&page, vec!["PHPSESSID=; path=/; Max-Age=0"], now()));
+        assert_eq!(jar.header(&ajax, now()).unwrap(), "visitor=1");
+    }
+
+    #[test]
+    fn test_public_suffix_domains() {
+        let site = Url::parse("http://www.thebluebook.com/").unwrap();
+        assert!(Cookie::parse(&site, "a=1; Domain=com", now()).is_none());
+        assert!(Cookie::parse(&site, "a=1; Domain=.com", now()).is_none());
+        let uk = Url::parse("http://www.example.co.uk/").unwrap();
+        assert!(Cookie::parse(&uk, "a=1; Domain=.co.uk", now()).is_none());
+        let cookie = Cookie::parse(&uk, "a=1; Domain=.example.co.uk", now()).unwrap();
+        assert_eq!(cookie.domain, "example.co.uk");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("cookies.json");
+        let url = Url::parse("http://www.thebluebook.com/").unwrap();
+        let mut jar = CookieJar::default();
+        jar.store(&url, vec!["PHPSESSID=abc; path=/"], now());
+        jar.last_used = Some(now());
+        jar.save(&path).unwrap();
+
+        let loaded = CookieJar::load(&path).unwrap();
+        assert_eq!(loaded, jar);
+        assert!(!loaded.is_stale(Duration::minutes(30), now() + Duration::minutes(10)));
+        assert!(loaded.is_stale(Duration::minutes(30), now() + Duration::hours(1)));
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(CookieJar::load(&path).unwrap(), CookieJar::default());
+    }
+}
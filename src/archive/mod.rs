@@ -0,0 +1,406 @@
+use crate::api::PageType;
+pub use crate::archive::warc::HttpResponse;
+use crate::archive::warc::{
+    http_request_block, http_response_block, parse_http_response, read_record, record_id,
+    write_record, Record, RecordType,
+};
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+mod warc;
+
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 1024 * 1024 * 1024;
+pub const INDEX_FILE: &str = "index.jsonl";
+
+/// The last hop of a redirected request, which the records are about: its
+/// headers and body came from there. The index keeps the request as it was
+/// made, to be found by it again.
+pub struct Redirect<'a> {
+    pub url: &'a str,
+    pub method: &'a str,
+    pub body: Option<&'a [u8]>,
+}
+
+/// One request and the response to it, as seen by the `api` layer.
+pub struct Exchange<'a> {
+    pub page_type: PageType,
+    pub company_id: Option<i32>,
+    pub method: &'a str,
+    pub url: &'a str,
+    pub request_headers: Vec<(String, String)>,
+    pub request_body: Option<&'a [u8]>,
+    pub status: u16,
+    pub reason: &'a str,
+    pub response_headers: Vec<(String, String)>,
+    pub body: &'a str,
+    pub timestamp: DateTime<Utc>,
+    /// Set when redirects were followed.
+    pub redirect: Option<Redirect<'a>>,
+}
+
+/// Where a response record lives, one JSON line per fetched page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub company_id: Option<i32>,
+    pub page_type: PageType,
+    pub url: String,
+    /// Form body of POST requests, which tells apart category requests that
+    /// share a URL.
+    #[serde(default)]
+    pub form: Option<String>,
+    pub status: u16,
+    pub timestamp: DateTime<Utc>,
+    pub file: String,
+    pub request_offset: u64,
+    pub response_offset: u64,
+}
+
+struct WarcFile {
+    name: String,
+    writer: BufWriter<File>,
+    position: u64,
+}
+
+struct State {
+    current: Option<WarcFile>,
+    sequence: u32,
+    index: BufWriter<File>,
+}
+
+/// Appends every exchange to WARC files in `dir`, starting a new file once the
+/// current one grows past `max_file_size`. Bodies are stored decoded, so
+/// `Content-Encoding` and length headers of the original response are dropped.
+/// So are the cookies sent, the session's credentials have no place in it.
+pub struct Archiver {
+    dir: PathBuf,
+    max_file_size: u64,
+    started: String,
+    state: Mutex<State>,
+}
+
+impl Archiver {
+    pub fn open(dir: impl AsRef<Path>, max_file_size: u64) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(INDEX_FILE))?;
+
+        Ok(Archiver {
+            dir,
+            max_file_size,
+            started: Utc::now().format("%Y%m%d%H%M%S").to_string(),
+            state: Mutex::new(State {
+                current: None,
+                sequence: 0,
+                index: BufWriter::new(index),
+            }),
+        })
+    }
+
+    pub fn record(&self, exchange: &Exchange) -> io::Result<IndexEntry> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+
+        let rotate = match &state.current {
+            Some(file) => file.position >= self.max_file_size,
+            None => true,
+        };
+        if rotate {
+            let file = self.create_file(&mut state.sequence)?;
+            state.current = Some(file);
+        }
+        let file = state.current.as_mut().unwrap();
+
+        let request_id = record_id();
+        let (method, url, body) = match &exchange.redirect {
+            Some(hop) => (hop.method, hop.url, hop.body),
+            None => (exchange.method, exchange.url, exchange.request_body),
+        };
+        let (target, host) = match Url::parse(url) {
+            Ok(url) => {
+                let target = match url.query() {
+                    Some(query) => format!("{}?{}", url.path(), query),
+                    None => url.path().to_string(),
+                };
+                (target, url.host_str().map(|host| host.to_string()))
+            }
+            Err(_) => (url.to_string(), None),
+        };
+        let request_headers: Vec<(String, String)> = host
+            .map(|host| ("Host".to_string(), host))
+            .into_iter()
+            .chain(
+                exchange
+                    .request_headers
+                    .iter()
+                    .filter(|(name, _)| !name.eq_ignore_ascii_case("cookie"))
+                    .cloned(),
+            )
+            .collect();
+        let request_block = http_request_block(method, &target, &request_headers, body);
+        let request_offset = file.position;
+        file.position += write_record(
+            &mut file.writer,
+            &Record {
+                record_type: RecordType::Request,
+                id: request_id.clone(),
+                date: exchange.timestamp,
+                target_uri: Some(url),
+                concurrent_to: None,
+                content_type: "application/http; msgtype=request",
+                block: &request_block,
+            },
+        )?;
+
+        let response_headers: Vec<(String, String)> = exchange
+            .response_headers
+            .iter()
+            .filter(|(name, _)| {
+                let name = name.to_lowercase();
+                name != "content-encoding"
+                    && name != "content-length"
+                    && name != "transfer-encoding"
+            })
+            .cloned()
+            .chain(std::iter::once((
+                "Content-Length".to_string(),
+                exchange.body.len().to_string(),
+            )))
+            .collect();
+        let response_block = http_response_block(
+            exchange.status,
+            exchange.reason,
+            &response_headers,
+            exchange.body.as_bytes(),
+        );
+        let response_offset = file.position;
+        file.position += write_record(
+            &mut file.writer,
+            &Record {
+                record_type: RecordType::Response,
+                id: record_id(),
+                date: exchange.timestamp,
+                target_uri: Some(url),
+                concurrent_to: Some(&request_id),
+                content_type: "application/http; msgtype=response",
+                block: &response_block,
+            },
+        )?;
+        file.writer.flush()?;
+
+        let entry = IndexEntry {
+            company_id: exchange.company_id,
+            page_type: exchange.page_type,
+            url: exchange.url.to_string(),
+            form: exchange
+                .request_body
+                .map(|body| String::from_utf8_lossy(body).into_owned()),
+            status: exchange.status,
+            timestamp: exchange.timestamp,
+            file: file.name.clone(),
+            request_offset,
+            response_offset,
+        };
+        serde_json::to_writer(&mut state.index, &entry)?;
+        state.index.write_all(b"\n")?;
+        state.index.flush()?;
+
+        Ok(entry)
+    }
+
+    /// Never overwrites: a file left by another run in the same second just
+    /// moves the sequence number on.
+    fn create_file(&self, sequence: &mut u32) -> io::Result<WarcFile> {
+        let (name, file) = loop {
+            *sequence += 1;
+            let name = format!("bluebook-{}-{:05}.warc", self.started, sequence);
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(self.dir.join(&name))
+            {
+                Ok(file) => break (name, file),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        let mut writer = BufWriter::new(file);
+        let info = format!(
+            "software: {}/{}\r\nformat: WARC File Format 1.0\r\n",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        );
+        let position = write_record(
+            &mut writer,
+            &Record {
+                record_type: RecordType::WarcInfo,
+                id: record_id(),
+                date: Utc::now(),
+                target_uri: None,
+                concurrent_to: None,
+                content_type: "application/warc-fields",
+                block: info.as_bytes(),
+            },
+        )?;
+        Ok(WarcFile {
+            name,
+            writer,
+            position,
+        })
+    }
+}
+
+/// Looks archived responses up through the index written by [`Archiver`].
+pub struct ArchiveReader {
+    dir: PathBuf,
+    entries: Vec<IndexEntry>,
+}
+
+impl ArchiveReader {
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let index = BufReader::new(File::open(dir.join(INDEX_FILE))?);
+        let mut entries = Vec::new();
+        for line in index.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line)?);
+        }
+        Ok(ArchiveReader { dir, entries })
+    }
+
+    /// Companies whose profile page is in the archive, in ascending order.
+    pub fn company_ids(&self) -> Vec<i32> {
+        let mut ids: Vec<i32> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.page_type == PageType::Company)
+            .filter_map(|entry| entry.company_id)
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    /// The most recent record of a request. Company pages are matched by id
+    /// rather than URL so archives survive a change of host.
+    pub fn find(
+        &self,
+        page_type: PageType,
+        company_id: Option<i32>,
+        url: &str,
+        form: Option<&str>,
+    ) -> Option<&IndexEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| {
+                entry.page_type == page_type
+                    && entry.company_id == company_id
+                    && entry.form.as_deref() == form
+                    && (company_id.is_some() || entry.url == url)
+            })
+            .max_by_key(|entry| entry.timestamp)
+    }
+
+    pub fn read(&self, entry: &IndexEntry) -> io::Result<HttpResponse> {
+        let mut file = BufReader::new(File::open(self.dir.join(&entry.file))?);
+        file.seek(SeekFrom::Start(entry.response_offset))?;
+        let (_, block) = read_record(&mut file)?;
+        parse_http_response(&block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::PageType;
+    use crate::archive::*;
+
+    fn exchange(company_id: i32, body: &str) -> Exchange<'_> {
+        Exchange {
+            page_type: PageType::Company,
+            company_id: Some(company_id),
+            method: "GET",
+            url: "http://www.thebluebook.com/iProView/922369",
+            request_headers: vec![("User-Agent".to_string(), "test".to_string())],
+            request_body: None,
+            status: 200,
+            reason: "OK",
+            response_headers: vec![
+                ("Content-Type".to_string(), "text/html".to_string()),
+                ("Content-Encoding".to_string(), "gzip".to_string()),
+            ],
+            body,
+            timestamp: Utc::now(),
+            redirect: None,
+        }
+    }
+
+    #[test]
+    fn test_record_and_rotate() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("archive");
+        let archiver = Archiver::open(&dir, 1).unwrap();
+
+        let first = archiver
+            .record(&exchange(922369, "<html>one</html>"))
+            .unwrap();
+        let second = archiver
+            .record(&exchange(400516, "<html>two</html>"))
+            .unwrap();
+        assert_ne!(first.file, second.file);
+
+        let warc = fs::read_to_string(dir.join(&first.file)).unwrap();
+        let response = &warc[first.response_offset as usize..];
+        assert!(response.starts_with("WARC/1.0\r\nWARC-Type: response\r\n"));
+        assert!(response.contains("Content-Length: 16\r\n\r\n<html>one</html>"));
+        assert!(!response.contains("gzip"));
+        let request = &warc[first.request_offset as usize..first.response_offset as usize];
+        assert!(request.starts_with("WARC/1.0\r\nWARC-Type: request"));
+        assert!(request.contains("GET /iProView/922369 HTTP/1.1\r\nHost: www.thebluebook.com\r\n"));
+
+        let index = fs::read_to_string(dir.join(INDEX_FILE)).unwrap();
+        let entries: Vec<IndexEntry> = index
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries, vec![first, second]);
+    }
+
+    #[test]
+    fn test_read_archived_responses() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("archive-reader");
+        let archiver = Archiver::open(&dir, DEFAULT_MAX_FILE_SIZE).unwrap();
+        archiver
+            .record(&exchange(922369, "<html>old</html>"))
+            .unwrap();
+        archiver
+            .record(&exchange(400516, "<html>other</html>"))
+            .unwrap();
+        let mut newer = exchange(922369, "<html>new</html>");
+        newer.timestamp += chrono::Duration::seconds(1);
+        archiver.record(&newer).unwrap();
+
+        let reader = ArchiveReader::open(&dir).unwrap();
+        assert_eq!(reader.company_ids(), vec![400516, 922369]);
+
+        let entry = reader
+            .find(PageType::Company, Some(922369), "http://example.com/", None)
+            .unwrap();
+        let response = reader.read(entry).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, "<html>new</html>");
+        assert!(reader
+            .find(PageType::Locations, Some(922369), "", None)
+            .is_none());
+    }
+}
@@ -0,0 +1,250 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::io::{self, BufRead, Write};
+use uuid::Uuid;
+
+pub const WARC_VERSION: &str = "WARC/1.0";
+
+pub enum RecordType {
+    WarcInfo,
+    Request,
+    Response,
+}
+
+impl RecordType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RecordType::WarcInfo => "warcinfo",
+            RecordType::Request => "request",
+            RecordType::Response => "response",
+        }
+    }
+}
+
+pub struct Record<'a> {
+    pub record_type: RecordType,
+    pub id: String,
+    pub date: DateTime<Utc>,
+    pub target_uri: Option<&'a str>,
+    pub concurrent_to: Option<&'a str>,
+    pub content_type: &'a str,
+    pub block: &'a [u8],
+}
+
+pub fn record_id() -> String {
+    format!("<urn:uuid:{}>", Uuid::new_v4())
+}
+
+/// Writes one record and returns how many bytes it took.
+pub fn write_record(writer: &mut impl Write, record: &Record) -> io::Result<u64> {
+    let mut head = format!(
+        "{}\r\nWARC-Type: {}\r\nWARC-Record-ID: {}\r\nWARC-Date: {}\r\n",
+        WARC_VERSION,
+        record.record_type.as_str(),
+        record.id,
+        record.date.to_rfc3339_opts(SecondsFormat::Secs, true),
+    );
+    if let Some(uri) = record.target_uri {
+        head.push_str(&format!("WARC-Target-URI: {}\r\n", uri));
+    }
+    if let Some(id) = record.concurrent_to {
+        head.push_str(&format!("WARC-Concurrent-To: {}\r\n", id));
+    }
+    head.push_str(&format!(
+        "Content-Type: {}\r\nContent-Length: {}\r\n\r\n",
+        record.content_type,
+        record.block.len()
+    ));
+
+    writer.write_all(head.as_bytes())?;
+    writer.write_all(record.block)?;
+    writer.write_all(b"\r\n\r\n")?;
+    Ok((head.len() + record.block.len() + 4) as u64)
+}
+
+pub fn http_request_block(
+    method: &str,
+    target: &str,
+    headers: &[(String, String)],
+    body: Option<&[u8]>,
+) -> Vec<u8> {
+    let mut block = format!("{} {} HTTP/1.1\r\n", method, target).into_bytes();
+    write_headers(&mut block, headers);
+    if let Some(body) = body {
+        block.extend_from_slice(body);
+    }
+    block
+}
+
+pub fn http_response_block(
+    status: u16,
+    reason: &str,
+    headers: &[(String, String)],
+    body: &[u8],
+) -> Vec<u8> {
+    let mut block = format!("HTTP/1.1 {} {}\r\n", status, reason).into_bytes();
+    write_headers(&mut block, headers);
+    block.extend_from_slice(body);
+    block
+}
+
+fn write_headers(block: &mut Vec<u8>, headers: &[(String, String)]) {
+    for (name, value) in headers {
+        block.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+    }
+    block.extend_from_slice(b"\r\n");
+}
+
+/// A response read back from an archive, body decoded as it was when stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Fields,
+    pub body: String,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Header fields of a WARC record or an HTTP message, in order.
+pub type Fields = Vec<(String, String)>;
+
+/// Reads the record starting at the current position and returns its header
+/// fields and block.
+pub fn read_record(reader: &mut impl BufRead) -> io::Result<(Fields, Vec<u8>)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim_end() != WARC_VERSION {
+        return Err(invalid("not a WARC/1.0 record"));
+    }
+
+    let mut fields = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("truncated WARC record header"));
+        }
+        let field = line.trim_end();
+        if field.is_empty() {
+            break;
+        }
+        let (name, value) = split_field(field).ok_or_else(|| invalid("malformed WARC field"))?;
+        fields.push((name, value));
+    }
+
+    let length: usize = fields
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+        .and_then(|(_, value)| value.parse().ok())
+        .ok_or_else(|| invalid("WARC record without Content-Length"))?;
+    let mut block = vec![0; length];
+    reader.read_exact(&mut block)?;
+    Ok((fields, block))
+}
+
+pub fn parse_http_response(block: &[u8]) -> io::Result<HttpResponse> {
+    let split = block
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| invalid("HTTP response without header end"))?;
+    let head = String::from_utf8_lossy(&block[..split]);
+    let mut lines = head.split("\r\n");
+
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid("malformed HTTP status line"))?;
+    let headers = lines.filter_map(split_field).collect();
+    let body = String::from_utf8_lossy(&block[split + 4..]).into_owned();
+
+    Ok(HttpResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+fn split_field(line: &str) -> Option<(String, String)> {
+    let mut parts = line.splitn(2, ':');
+    let name = parts.next()?.trim();
+    let value = parts.next()?.trim();
+    Some((name.to_string(), value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archive::warc::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_write_record() {
+        let block = http_response_block(
+            200,
+            "OK",
+            &[("Content-Type".to_string(), "text/html".to_string())],
+            b"<html></html>",
+        );
+        let record = Record {
+            record_type: RecordType::Response,
+            id: "<urn:uuid:1>".to_string(),
+            date: Utc.with_ymd_and_hms(2020, 2, 20, 10, 0, 0).unwrap(),
+            target_uri: Some("http://www.thebluebook.com/iProView/922369"),
+            concurrent_to: Some("<urn:uuid:0>"),
+            content_type: "application/http; msgtype=response",
+            block: &block,
+        };
+
+        let mut out = Vec::new();
+        let written = write_record(&mut out, &record).unwrap();
+        assert_eq!(written, out.len() as u64);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "WARC/1.0\r\n\
+             WARC-Type: response\r\n\
+             WARC-Record-ID: <urn:uuid:1>\r\n\
+             WARC-Date: 2020-02-20T10:00:00Z\r\n\
+             WARC-Target-URI: http://www.thebluebook.com/iProView/922369\r\n\
+             WARC-Concurrent-To: <urn:uuid:0>\r\n\
+             Content-Type: application/http; msgtype=response\r\n\
+             Content-Length: 57\r\n\
+             \r\n\
+             HTTP/1.1 200 OK\r\n\
+             Content-Type: text/html\r\n\
+             \r\n\
+             <html></html>\r\n\r\n"
+        )
+    }
+
+    #[test]
+    fn test_read_back_response() {
+        let block = http_response_block(
+            404,
+            "Not Found",
+            &[("Content-Type".to_string(), "text/html".to_string())],
+            "<p>gone</p>".as_bytes(),
+        );
+        let record = Record {
+            record_type: RecordType::Response,
+            id: record_id(),
+            date: Utc::now(),
+            target_uri: Some("http://www.thebluebook.com/iProView/1"),
+            concurrent_to: None,
+            content_type: "application/http; msgtype=response",
+            block: &block,
+        };
+        let mut out = Vec::new();
+        write_record(&mut out, &record).unwrap();
+
+        let (fields, read_block) = read_record(&mut out.as_slice()).unwrap();
+        assert!(fields.contains(&("WARC-Type".to_string(), "response".to_string())));
+        assert_eq!(
+            parse_http_response(&read_block).unwrap(),
+            HttpResponse {
+                status: 404,
+                headers: vec![("Content-Type".to_string(), "text/html".to_string())],
+                body: "<p>gone</p>".to_string(),
+            }
+        )
+    }
+}
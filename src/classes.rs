@@ -0,0 +1,170 @@
+use crate::api::{get_classes_page, Client};
+use crate::data::ClassEntry;
+use crate::error::Error;
+use crate::parser::get_classes;
+use serde::Deserialize;
+use serde_json::{from_reader, to_writer_pretty};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+
+/// `classes.json` started out as a bare list of class ids, the refreshed
+/// catalog stores names and groups as well. Both are accepted on read.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ClassesFile {
+    Legacy(Vec<i32>),
+    Catalog(Vec<ClassEntry>),
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ClassesDiff {
+    pub added: Vec<ClassEntry>,
+    pub removed: Vec<ClassEntry>,
+}
+
+impl fmt::Display for ClassesDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (sign, classes) in &[("+", &self.added), ("-", &self.removed)] {
+            for class in classes.iter() {
+                write!(f, "{} {:04}", sign, class.id)?;
+                if let Some(name) = &class.name {
+                    write!(f, " {}", name)?;
+                }
+                if let Some(group) = &class.group {
+                    write!(f, " [{}]", group)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        write!(
+            f,
+            "{} added, {} removed",
+            self.added.len(),
+            self.removed.len()
+        )
+    }
+}
+
+pub async fn read_classes(path: &Path) -> Result<Vec<ClassEntry>, Error> {
+    let file = File::open(path)?;
+    let classes = match from_reader(file)? {
+        ClassesFile::Legacy(ids) => ids
+            .into_iter()
+            .map(|id| ClassEntry {
+                id,
+                name: None,
+                group: None,
+            })
+            .collect(),
+        ClassesFile::Catalog(classes) => classes,
+    };
+    Ok(classes)
+}
+
+pub async fn write_classes(path: &Path, classes: &[ClassEntry]) -> Result<(), Error> {
+    let file = File::create(path)?;
+    to_writer_pretty(file, classes)?;
+    Ok(())
+}
+
+pub fn diff_classes(old: &[ClassEntry], new: &[ClassEntry]) -> ClassesDiff {
+    let old_ids: HashSet<i32> = old.iter().map(|class| class.id).collect();
+    let new_ids: HashSet<i32> = new.iter().map(|class| class.id).collect();
+
+    ClassesDiff {
+        added: new
+            .iter()
+            .filter(|class| !old_ids.contains(&class.id))
+            .cloned()
+            .collect(),
+        removed: old
+            .iter()
+            .filter(|class| !new_ids.contains(&class.id))
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Scrapes the live class directory, overwrites the catalog at `path` and
+/// returns what changed compared with the previous version of the file.
+pub async fn refresh_classes(client: &Client, path: &Path) -> Result<ClassesDiff, Error> {
+    let page = get_classes_page(client).await?;
+    let classes = get_classes(&page).await?;
+
+    let previous = match read_classes(path).await {
+        Ok(previous) => previous,
+        Err(Error::Io(ref e)) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+
+    write_classes(path, &classes).await?;
+    Ok(diff_classes(&previous, &classes))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::*;
+
+    fn entry(id: i32, name: &str) -> ClassEntry {
+        ClassEntry {
+            id,
+            name: Some(name.to_string()),
+            group: Some("Mechanical".to_string()),
+        }
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_read_classes() {
+        let classes = read_classes(Path::new(crate::config::DEFAULT_CLASSES_PATH))
+            .await
+            .unwrap();
+        assert_eq!(classes.len(), 612)
+    }
+
+    #[tokio::test]
+    async fn test_write_and_read_catalog() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("classes.json");
+        let classes = vec![entry(4030, "Plumbing Contractors"), entry(4040, "Pumps")];
+
+        write_classes(&path, &classes).await.unwrap();
+        let read = read_classes(&path).await.unwrap();
+        assert_eq!(read, classes)
+    }
+
+    #[test]
+    fn test_diff_classes() {
+        let old = vec![
+            ClassEntry {
+                id: 4030,
+                name: None,
+                group: None,
+            },
+            ClassEntry {
+                id: 4040,
+                name: None,
+                group: None,
+            },
+        ];
+        let new = vec![entry(4030, "Plumbing Contractors"), entry(4050, "Radon")];
+
+        let diff = diff_classes(&old, &new);
+        assert_eq!(
+            diff,
+            ClassesDiff {
+                added: vec![entry(4050, "Radon")],
+                removed: vec![ClassEntry {
+                    id: 4040,
+                    name: None,
+                    group: None,
+                }],
+            }
+        );
+        assert_eq!(
+            diff.to_string(),
+            "+ 4050 Radon [Mechanical]\n- 4040\n1 added, 1 removed"
+        )
+    }
+}
@@ -0,0 +1,280 @@
+use crate::export::{ExportFormat, ParquetCompression};
+use crate::logging::LogFormat;
+use chrono::NaiveDate;
+use reqwest::Url;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_ERROR: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_PARTIAL: i32 = 3;
+pub const EXIT_BLOCKED: i32 = 4;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "bluebook-scraper",
+    about = "Scrapes company profiles from The Blue Book.",
+    after_help = "EXIT CODES:\n    \
+                  0    success\n    \
+                  1    error\n    \
+                  2    invalid arguments or configuration\n    \
+                  3    finished, but some classes or companies failed\n    \
+                  4    stopped because the site blocked the scraper"
+)]
+pub struct Opt {
+    /// Config file with settings and named profiles [default: bluebook.toml if it exists]
+    #[structopt(long, global = true, value_name = "path", parse(from_os_str))]
+    pub config: Option<PathBuf>,
+    /// Profile from the config file to apply
+    #[structopt(long, global = true, value_name = "name")]
+    pub profile: Option<String>,
+    /// Proxy for all requests [default: socks5://127.0.0.1:9050]
+    #[structopt(long, global = true, value_name = "url")]
+    pub proxy: Option<String>,
+    /// Connect to the site directly, without a proxy
+    #[structopt(long, global = true)]
+    pub direct: bool,
+    /// Browser to pass for, by name from the header profiles
+    #[structopt(long, global = true, value_name = "name")]
+    pub header_profile: Option<String>,
+    /// Seed that picks the browser to pass for [default: random]
+    #[structopt(long, global = true, value_name = "n")]
+    pub header_seed: Option<u64>,
+    /// Keep the session's cookies in this file between runs
+    #[structopt(long, global = true, value_name = "path", parse(from_os_str))]
+    pub cookie_jar: Option<PathBuf>,
+    /// Another copy of the site to scrape, e.g. a mirror or a mock server
+    #[structopt(long, global = true, value_name = "url")]
+    pub base_url: Option<Url>,
+    /// Host to send every request to instead of the site's own
+    #[structopt(long, global = true)]
+    pub host: Option<String>,
+    /// Use HTTPS for every request
+    #[structopt(long, global = true)]
+    pub https: bool,
+    /// Serve pages from a directory of fixtures instead of the network
+    #[structopt(long, global = true, value_name = "dir", parse(from_os_str))]
+    pub fixtures: Option<PathBuf>,
+    /// Archive every request and response to WARC files in this directory
+    #[structopt(long, global = true, value_name = "dir", parse(from_os_str))]
+    pub archive: Option<PathBuf>,
+    /// How many companies to scrape at once
+    #[structopt(short = "j", long, global = true)]
+    pub concurrency: Option<usize>,
+    /// Log levels on stderr, e.g. `info` or `warn,bluebook_scraper::parser=debug`
+    /// [default: $BLUEBOOK_LOG or warn]
+    #[structopt(long, global = true, value_name = "filter")]
+    pub log: Option<String>,
+    /// Log format: pretty or json
+    #[structopt(long, global = true, default_value = "pretty", value_name = "format")]
+    pub log_format: LogFormat,
+    #[structopt(subcommand)]
+    pub command: Command,
+}
+
+// Where profiles go, shared by the commands that scrape.
+#[derive(Debug, StructOpt)]
+pub struct SinkOpt {
+    /// JSON lines file for profiles, `-` for stdout
+    #[structopt(short, long, default_value = "profiles.jsonl")]
+    pub output: String,
+    /// Skip companies already in the output and append to it
+    #[structopt(long)]
+    pub resume: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Scrape every company listed under the given classes in the given cities
+    Crawl {
+        /// Class ids to crawl [default: every class in the catalog]
+        #[structopt(short, long = "class", value_name = "id")]
+        classes: Vec<i32>,
+        /// Cities to list companies in [default: from the config]
+        #[structopt(long = "city", value_name = "city")]
+        cities: Vec<String>,
+        /// Keep the crawl's jobs in this directory, so an interrupted crawl
+        /// picks up where it stopped. Appends to the output
+        #[structopt(long, value_name = "dir", parse(from_os_str))]
+        queue: Option<PathBuf>,
+        /// Start the queue's crawl over, scraping again what it already did
+        #[structopt(long, requires = "queue")]
+        fresh: bool,
+        #[structopt(flatten)]
+        sink: SinkOpt,
+    },
+    /// List the jobs of a crawl queue that failed for good, or requeue them
+    DeadLetters {
+        #[structopt(long, value_name = "dir", parse(from_os_str))]
+        queue: PathBuf,
+        /// Make the jobs pending again
+        #[structopt(long)]
+        requeue: bool,
+        /// Jobs to list or requeue [default: every dead job]
+        #[structopt(value_name = "id")]
+        ids: Vec<u64>,
+    },
+    /// Scrape new companies, those whose listing changed and those not
+    /// scraped within `refresh_ttl_days`, updating their lines of the output
+    Refresh {
+        /// Class ids to list [default: every class in the catalog]
+        #[structopt(short, long = "class", value_name = "id")]
+        classes: Vec<i32>,
+        /// Cities to list companies in [default: from the config]
+        #[structopt(long = "city", value_name = "city")]
+        cities: Vec<String>,
+        /// JSON lines file for profiles, with its state in <output>.state.jsonl
+        #[structopt(short, long, default_value = "profiles.jsonl")]
+        output: String,
+    },
+    /// Scrape specific companies
+    Company {
+        #[structopt(required = true, value_name = "id")]
+        ids: Vec<i32>,
+        #[structopt(flatten)]
+        sink: SinkOpt,
+    },
+    /// List companies of a class or a keyword without scraping them
+    Search {
+        /// A class id instead of a keyword
+        #[structopt(long)]
+        class: bool,
+        /// Print only company ids
+        #[structopt(long)]
+        ids: bool,
+        /// Narrow a keyword search down to results that also match this
+        #[structopt(long, value_name = "term", conflicts_with = "class")]
+        within: Option<String>,
+        term: String,
+        /// Cities to search in [default: from the config]
+        cities: Vec<String>,
+    },
+    /// Refresh the class catalog and show what changed
+    Classes,
+    /// Rebuild profiles from an archive without touching the network
+    Reparse {
+        #[structopt(parse(from_os_str))]
+        archive_dir: PathBuf,
+        /// Companies to rebuild [default: every company in the archive]
+        #[structopt(value_name = "id")]
+        ids: Vec<i32>,
+        /// JSON lines file with the previous profiles, rewritten in place
+        #[structopt(short, long, default_value = "profiles.jsonl")]
+        output: String,
+    },
+    /// Convert stored profiles to another format
+    Export {
+        #[structopt(short, long, default_value = "profiles.jsonl")]
+        input: String,
+        /// jsonl, json, csv or parquet
+        #[structopt(short, long, default_value = "jsonl")]
+        format: ExportFormat,
+        /// Output file, `-` for stdout
+        #[structopt(short, long, default_value = "-")]
+        output: String,
+        /// Joins lists like categories within one CSV cell
+        #[structopt(long, default_value = "; ", value_name = "text")]
+        list_delimiter: String,
+        /// CSV columns to write, comma separated [default: all]
+        #[structopt(long, use_delimiter = true, value_name = "name")]
+        columns: Vec<String>,
+        /// Also write contacts, locations and categories to <output>.<table>.csv
+        #[structopt(long)]
+        tables: bool,
+        /// Companies per Parquet row group, also how many are held in memory
+        #[structopt(long, default_value = "10000", value_name = "n")]
+        row_group_size: usize,
+        /// Parquet compression: none, snappy, gzip or zstd
+        #[structopt(long, default_value = "snappy", value_name = "codec")]
+        compression: ParquetCompression,
+    },
+    /// Build the gazetteer to geocode with from Census Bureau files
+    BuildGazetteer {
+        /// County gazetteer file, like 2023_Gaz_counties_national.txt
+        #[structopt(long, value_name = "path")]
+        counties: PathBuf,
+        /// ZCTA gazetteer file, like 2023_Gaz_zcta_national.txt
+        #[structopt(long, value_name = "path")]
+        zctas: PathBuf,
+        /// ZCTA to county relationship file, like tab20_zcta520_county20_natl.txt
+        #[structopt(long, value_name = "path")]
+        relationships: PathBuf,
+        #[structopt(short, long, default_value = "gazetteer.tsv")]
+        output: PathBuf,
+    },
+    /// Show how complete stored profiles are
+    Stats {
+        #[structopt(short, long, default_value = "profiles.jsonl")]
+        input: String,
+    },
+    /// Cluster stored profiles that are likely the same business
+    Resolve {
+        #[structopt(short, long, default_value = "profiles.jsonl")]
+        input: String,
+        /// JSON lines file of reviewed pairs to merge or keep apart
+        #[structopt(long, default_value = "decisions.jsonl")]
+        decisions: String,
+        /// Score from which pairs are merged [default: 0.7]
+        #[structopt(long)]
+        threshold: Option<f64>,
+        /// JSON lines file for the cluster of every company, `-` for stdout
+        #[structopt(short, long, default_value = "-")]
+        output: String,
+        /// Also write every scored pair, with its evidence, to this file
+        #[structopt(long, value_name = "path")]
+        pairs: Option<String>,
+    },
+    /// Record what changed between two crawls in the history
+    Diff {
+        /// Profiles from the earlier crawl
+        #[structopt(long, value_name = "path")]
+        old: String,
+        /// Profiles from the later crawl
+        #[structopt(long, value_name = "path")]
+        new: String,
+        /// Date of the later crawl [default: today]
+        #[structopt(long, value_name = "yyyy-mm-dd")]
+        date: Option<NaiveDate>,
+        /// JSON lines file the changes are added to
+        #[structopt(long, default_value = "history.jsonl")]
+        history: String,
+        /// Only print the changes
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Show recorded changes
+    History {
+        #[structopt(long, default_value = "history.jsonl")]
+        history: String,
+        /// Only changes to these companies
+        #[structopt(long = "company", value_name = "id")]
+        companies: Vec<i32>,
+        /// Only changes recorded on or after this date
+        #[structopt(long, value_name = "yyyy-mm-dd")]
+        since: Option<NaiveDate>,
+        /// Only changes recorded on or before this date
+        #[structopt(long, value_name = "yyyy-mm-dd")]
+        until: Option<NaiveDate>,
+        /// Print JSON lines instead of one readable line per change
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Print the effective configuration as TOML
+    Config,
+}
+
+impl Command {
+    /// Whether the command talks to the site, and so needs a client with a
+    /// browser session. The others only read and write local files.
+    pub fn uses_network(&self) -> bool {
+        matches!(
+            self,
+            Command::Crawl { .. }
+                | Command::Refresh { .. }
+                | Command::Company { .. }
+                | Command::Search { .. }
+                | Command::Classes
+        )
+    }
+}
@@ -0,0 +1,643 @@
+use crate::api::{HeaderProfile, HeaderProfiles, DEFAULT_PROXY};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub const DEFAULT_CONFIG_PATH: &str = "bluebook.toml";
+pub const DEFAULT_CITY: &str = "New York, NY";
+pub const DEFAULT_CLASSES_PATH: &str = "classes.json";
+/// Where `build-gazetteer` writes, and where the gazetteer is looked for
+/// when none is configured.
+pub const DEFAULT_GAZETTEER_PATH: &str = "gazetteer.tsv";
+pub const DEFAULT_CONCURRENCY: usize = 4;
+/// The home page, as a visitor would arrive.
+pub const DEFAULT_WARM_UP: &str = "./";
+pub const DEFAULT_SESSION_IDLE_MINUTES: u64 = 30;
+const MAX_SESSION_IDLE_MINUTES: u64 = 60 * 24 * 365;
+pub const DEFAULT_REFRESH_TTL_DAYS: u64 = 30;
+const MAX_REFRESH_TTL_DAYS: u64 = 365 * 10;
+const MAX_CONCURRENCY: usize = 256;
+/// `proxy` value that means connecting directly.
+pub const NO_PROXY: &str = "none";
+const ENV_PREFIX: &str = "BLUEBOOK_";
+
+/// Every setting a run depends on, after defaults, the config file, the
+/// profile, the environment and command line flags have been applied in that
+/// order. Dumps to TOML that can be loaded again as a config file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Config {
+    /// Profile the settings came from, if any. Only noted in a comment when
+    /// dumped, the dump already has the profile applied.
+    #[serde(skip)]
+    pub profile: Option<String>,
+    /// Proxy URL, or `none` to connect directly.
+    pub proxy: String,
+    /// File of browser profiles to use instead of the built-in ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header_profiles: Option<PathBuf>,
+    /// Always pass for this browser instead of picking one by weight.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header_profile: Option<String>,
+    /// Picks the browser, random unless set so every run can be repeated.
+    pub header_seed: u64,
+    /// The browser picked for this run's session.
+    #[serde(skip)]
+    pub browser: HeaderProfile,
+    /// Pages visited before scraping, relative to the site's home page.
+    pub warm_up: Vec<String>,
+    /// Where the session's cookies are kept between runs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cookie_jar: Option<PathBuf>,
+    /// Minutes after which a saved session is considered expired.
+    pub session_idle_minutes: u64,
+    pub concurrency: usize,
+    pub cities: Vec<String>,
+    pub classes_path: PathBuf,
+    /// File to geocode locations and service areas with, see
+    /// [`crate::gazetteer`]. [`DEFAULT_GAZETTEER_PATH`] when it exists,
+    /// nothing is geocoded without one and a warning says so.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gazetteer: Option<PathBuf>,
+    /// Days after which `refresh` scrapes a company again.
+    pub refresh_ttl_days: u64,
+    /// Another copy of the site to scrape instead of the public one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<Url>,
+    pub https: bool,
+}
+
+/// One layer of settings, where anything left out falls through to the
+/// layer below.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub proxy: Option<String>,
+    pub header_profiles: Option<PathBuf>,
+    pub header_profile: Option<String>,
+    pub header_seed: Option<u64>,
+    pub warm_up: Option<Vec<String>>,
+    pub cookie_jar: Option<PathBuf>,
+    pub session_idle_minutes: Option<u64>,
+    pub concurrency: Option<usize>,
+    pub cities: Option<Vec<String>>,
+    pub classes_path: Option<PathBuf>,
+    pub gazetteer: Option<PathBuf>,
+    pub refresh_ttl_days: Option<u64>,
+    pub base_url: Option<String>,
+    pub https: Option<bool>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    UnknownProfile {
+        name: String,
+        available: Vec<String>,
+    },
+    /// A setting has a value that can't work, `origin` says where it was set.
+    Invalid {
+        key: &'static str,
+        origin: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Read { path, .. } => write!(f, "cannot read {}", path.display()),
+            Error::Parse { path, .. } => write!(f, "cannot parse {}", path.display()),
+            Error::UnknownProfile { name, available } if available.is_empty() => {
+                write!(f, "unknown profile {:?}, no profiles are defined", name)
+            }
+            Error::UnknownProfile { name, available } => write!(
+                f,
+                "unknown profile {:?}, expected one of {}",
+                name,
+                available.join(", ")
+            ),
+            Error::Invalid {
+                key,
+                origin,
+                reason,
+            } => write!(f, "invalid {} in {}: {}", key, origin, reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Read { source, .. } => Some(source),
+            Error::Parse { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl Settings {
+    /// `other` wins wherever it has a value.
+    fn merge(self, other: Settings) -> Settings {
+        Settings {
+            proxy: other.proxy.or(self.proxy),
+            header_profiles: other.header_profiles.or(self.header_profiles),
+            header_profile: other.header_profile.or(self.header_profile),
+            header_seed: other.header_seed.or(self.header_seed),
+            warm_up: other.warm_up.or(self.warm_up),
+            cookie_jar: other.cookie_jar.or(self.cookie_jar),
+            session_idle_minutes: other.session_idle_minutes.or(self.session_idle_minutes),
+            concurrency: other.concurrency.or(self.concurrency),
+            cities: other.cities.or(self.cities),
+            classes_path: other.classes_path.or(self.classes_path),
+            gazetteer: other.gazetteer.or(self.gazetteer),
+            refresh_ttl_days: other.refresh_ttl_days.or(self.refresh_ttl_days),
+            base_url: other.base_url.or(self.base_url),
+            https: other.https.or(self.https),
+        }
+    }
+
+    /// Settings from `BLUEBOOK_*` variables, as returned by `var`. Lists are
+    /// separated by `;` since city names contain commas.
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Result<Settings, Error> {
+        let var = |name: &str| var(&format!("{}{}", ENV_PREFIX, name));
+        let invalid = |key, name: &str, reason: String| Error::Invalid {
+            key,
+            origin: format!("${}{}", ENV_PREFIX, name),
+            reason,
+        };
+
+        fn number<T: FromStr>(
+            value: Option<String>,
+            invalid: impl Fn(String) -> Error,
+        ) -> Result<Option<T>, Error>
+        where
+            T::Err: fmt::Display,
+        {
+            match value {
+                Some(value) => value
+                    .trim()
+                    .parse()
+                    .map(Some)
+                    .map_err(|e| invalid(format!("{:?}: {}", value, e))),
+                None => Ok(None),
+            }
+        }
+        let list = |value: String| -> Vec<String> {
+            value
+                .split(';')
+                .map(|item| item.trim().to_string())
+                .collect()
+        };
+
+        let concurrency = number(var("CONCURRENCY"), |reason| {
+            invalid("concurrency", "CONCURRENCY", reason)
+        })?;
+        let header_seed = number(var("HEADER_SEED"), |reason| {
+            invalid("header_seed", "HEADER_SEED", reason)
+        })?;
+        let session_idle_minutes = number(var("SESSION_IDLE_MINUTES"), |reason| {
+            invalid("session_idle_minutes", "SESSION_IDLE_MINUTES", reason)
+        })?;
+        let refresh_ttl_days = number(var("REFRESH_TTL_DAYS"), |reason| {
+            invalid("refresh_ttl_days", "REFRESH_TTL_DAYS", reason)
+        })?;
+        let https = match var("HTTPS") {
+            Some(value) => Some(match value.trim() {
+                "1" | "true" | "yes" => true,
+                "0" | "false" | "no" | "" => false,
+                _ => {
+                    return Err(invalid(
+                        "https",
+                        "HTTPS",
+                        format!("{:?} is not true or false", value),
+                    ))
+                }
+            }),
+            None => None,
+        };
+
+        Ok(Settings {
+            proxy: var("PROXY"),
+            header_profiles: var("HEADER_PROFILES").map(PathBuf::from),
+            header_profile: var("HEADER_PROFILE"),
+            header_seed,
+            warm_up: var("WARM_UP").map(list),
+            cookie_jar: var("COOKIE_JAR").map(PathBuf::from),
+            session_idle_minutes,
+            concurrency,
+            cities: var("CITIES").map(list),
+            classes_path: var("CLASSES_PATH").map(PathBuf::from),
+            gazetteer: var("GAZETTEER").map(PathBuf::from),
+            refresh_ttl_days,
+            base_url: var("BASE_URL"),
+            https,
+        })
+    }
+}
+
+/// A config file: top-level settings plus named `[profiles.<name>]` tables.
+#[derive(Debug, Default)]
+pub struct ConfigFile {
+    path: PathBuf,
+    base: Settings,
+    profiles: BTreeMap<String, Settings>,
+}
+
+impl ConfigFile {
+    pub fn parse(path: &Path, text: &str) -> Result<Self, Error> {
+        let parse_error = |source| Error::Parse {
+            path: path.to_path_buf(),
+            source,
+        };
+        let mut table: toml::value::Table = toml::from_str(text).map_err(parse_error)?;
+        let profiles = match table.remove("profiles") {
+            Some(profiles) => profiles.try_into().map_err(parse_error)?,
+            None => BTreeMap::new(),
+        };
+        let base = toml::Value::Table(table).try_into().map_err(parse_error)?;
+
+        Ok(ConfigFile {
+            path: path.to_path_buf(),
+            base,
+            profiles,
+        })
+    }
+
+    /// Reads `path`. Only an explicitly requested file has to exist, the
+    /// default one is optional.
+    pub fn load(path: &Path, required: bool) -> Result<Self, Error> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => ConfigFile::parse(path, &text),
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+                Ok(ConfigFile::default())
+            }
+            Err(source) => Err(Error::Read {
+                path: path.to_path_buf(),
+                source,
+            }),
+        }
+    }
+
+    /// The file's settings with `profile` applied on top.
+    fn settings(&self, profile: Option<&str>) -> Result<Settings, Error> {
+        let mut settings = self.base.clone();
+        if let Some(name) = profile {
+            let overlay = self
+                .profiles
+                .get(name)
+                .ok_or_else(|| Error::UnknownProfile {
+                    name: name.to_string(),
+                    available: self.profiles.keys().cloned().collect(),
+                })?;
+            settings = settings.merge(overlay.clone());
+        }
+        Ok(settings)
+    }
+}
+
+impl Config {
+    /// Layers the file (with `profile`), `env` and `flags` over the defaults
+    /// and validates the result.
+    pub fn resolve(
+        file: &ConfigFile,
+        profile: Option<&str>,
+        env: Settings,
+        flags: Settings,
+    ) -> Result<Config, Error> {
+        let origin = |key: &'static str, layer: &Settings| -> String {
+            let set = |settings: &Settings| match key {
+                "proxy" => settings.proxy.is_some(),
+                "header_profiles" => settings.header_profiles.is_some(),
+                "header_profile" => settings.header_profile.is_some(),
+                "header_seed" => settings.header_seed.is_some(),
+                "warm_up" => settings.warm_up.is_some(),
+                "session_idle_minutes" => settings.session_idle_minutes.is_some(),
+                "concurrency" => settings.concurrency.is_some(),
+                "cities" => settings.cities.is_some(),
+                "refresh_ttl_days" => settings.refresh_ttl_days.is_some(),
+                "base_url" => settings.base_url.is_some(),
+                _ => false,
+            };
+            if set(&flags) {
+                "command line flags".to_string()
+            } else if set(&env) {
+                "environment".to_string()
+            } else if set(layer) {
+                match profile {
+                    Some(name) => format!("{} (profile {})", file.path.display(), name),
+                    None => file.path.display().to_string(),
+                }
+            } else {
+                "defaults".to_string()
+            }
+        };
+
+        let from_file = file.settings(profile)?;
+        let settings = from_file.clone().merge(env.clone()).merge(flags.clone());
+        let invalid = |key, reason: String| Error::Invalid {
+            key,
+            origin: origin(key, &from_file),
+            reason,
+        };
+
+        let proxy = settings.proxy.unwrap_or_else(|| DEFAULT_PROXY.to_string());
+        if proxy != NO_PROXY {
+            let scheme_ok = Url::parse(&proxy)
+                .map(|url| ["http", "https", "socks5", "socks5h"].contains(&url.scheme()))
+                .unwrap_or(false);
+            if !scheme_ok {
+                return Err(invalid(
+                    "proxy",
+                    format!(
+                        "{:?} is neither {:?} nor an http or socks5 URL",
+                        proxy, NO_PROXY
+                    ),
+                ));
+            }
+        }
+
+        let browsers = match &settings.header_profiles {
+            Some(path) => {
+                let text = std::fs::read_to_string(path).map_err(|source| Error::Read {
+                    path: path.clone(),
+                    source,
+                })?;
+                HeaderProfiles::from_toml(&text).map_err(|source| Error::Parse {
+                    path: path.clone(),
+                    source,
+                })?
+            }
+            None => HeaderProfiles::builtin(),
+        };
+        let header_seed = settings.header_seed.unwrap_or_else(random_seed);
+        let browser = match &settings.header_profile {
+            Some(name) => browsers.named(name).ok_or_else(|| {
+                invalid(
+                    "header_profile",
+                    format!("{:?} is not one of {}", name, browsers.names().join(", ")),
+                )
+            })?,
+            None => browsers.choose(header_seed).ok_or_else(|| {
+                invalid(
+                    "header_profiles",
+                    "no profile has a weight above 0".to_string(),
+                )
+            })?,
+        }
+        .clone();
+
+        let concurrency = settings.concurrency.unwrap_or(DEFAULT_CONCURRENCY);
+        if concurrency == 0 || concurrency > MAX_CONCURRENCY {
+            return Err(invalid(
+                "concurrency",
+                format!("{} is not between 1 and {}", concurrency, MAX_CONCURRENCY),
+            ));
+        }
+
+        let cities = settings
+            .cities
+            .unwrap_or_else(|| vec![DEFAULT_CITY.to_string()]);
+        if cities.is_empty() || cities.iter().any(|city| city.trim().is_empty()) {
+            return Err(invalid(
+                "cities",
+                "needs at least one city and no empty names".to_string(),
+            ));
+        }
+
+        let warm_up = settings
+            .warm_up
+            .unwrap_or_else(|| vec![DEFAULT_WARM_UP.to_string()]);
+        let home = Url::parse("http://example.com/").unwrap();
+        let on_site = |page: &String| {
+            home.join(page)
+                .map(|url| url.host() == home.host())
+                .unwrap_or(false)
+        };
+        if let Some(page) = warm_up.iter().find(|page| !on_site(page)) {
+            return Err(invalid(
+                "warm_up",
+                format!("{:?} is not a path on the site", page),
+            ));
+        }
+
+        let session_idle_minutes = settings
+            .session_idle_minutes
+            .unwrap_or(DEFAULT_SESSION_IDLE_MINUTES);
+        if session_idle_minutes == 0 || session_idle_minutes > MAX_SESSION_IDLE_MINUTES {
+            return Err(invalid(
+                "session_idle_minutes",
+                format!(
+                    "{} is not between 1 and {}",
+                    session_idle_minutes, MAX_SESSION_IDLE_MINUTES
+                ),
+            ));
+        }
+
+        let refresh_ttl_days = settings
+            .refresh_ttl_days
+            .unwrap_or(DEFAULT_REFRESH_TTL_DAYS);
+        if refresh_ttl_days == 0 || refresh_ttl_days > MAX_REFRESH_TTL_DAYS {
+            return Err(invalid(
+                "refresh_ttl_days",
+                format!(
+                    "{} is not between 1 and {}",
+                    refresh_ttl_days, MAX_REFRESH_TTL_DAYS
+                ),
+            ));
+        }
+
+        let base_url = match settings.base_url {
+            Some(url) => Some(
+                Url::parse(&url).map_err(|e| invalid("base_url", format!("{:?}: {}", url, e)))?,
+            ),
+            None => None,
+        };
+
+        Ok(Config {
+            profile: profile.map(str::to_string),
+            proxy,
+            header_profiles: settings.header_profiles,
+            header_profile: settings.header_profile,
+            header_seed,
+            browser,
+            warm_up,
+            cookie_jar: settings.cookie_jar,
+            session_idle_minutes,
+            concurrency,
+            cities,
+            classes_path: settings
+                .classes_path
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_CLASSES_PATH)),
+            gazetteer: settings.gazetteer.or_else(|| {
+                Some(PathBuf::from(DEFAULT_GAZETTEER_PATH)).filter(|path| path.is_file())
+            }),
+            refresh_ttl_days,
+            base_url,
+            https: settings.https.unwrap_or(false),
+        })
+    }
+
+    pub fn proxy(&self) -> Option<&str> {
+        if self.proxy == NO_PROXY {
+            None
+        } else {
+            Some(&self.proxy)
+        }
+    }
+
+    pub fn to_toml(&self) -> String {
+        let settings = toml::to_string_pretty(self).expect("Config always serializes");
+        let browser = format!("# browser: {}\n", self.browser.name);
+        match &self.profile {
+            Some(profile) => format!("# profile: {}\n{}{}", profile, browser, settings),
+            None => format!("{}{}", browser, settings),
+        }
+    }
+}
+
+/// A seed from the clock, kept within what a TOML integer can hold.
+fn random_seed() -> u64 {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    (now.as_nanos() as u64 ^ u64::from(std::process::id())) & i64::MAX as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::*;
+
+    const FILE: &str = r#"
+        concurrency = 2
+        cities = ["New York, NY", "Newark, NJ"]
+
+        [profiles.tor-slow]
+        concurrency = 1
+
+        [profiles.direct-dev]
+        proxy = "none"
+        base_url = "http://localhost:8080/"
+    "#;
+
+    fn file() -> ConfigFile {
+        ConfigFile::parse(Path::new("bluebook.toml"), FILE).unwrap()
+    }
+
+    #[test]
+    fn test_layers() {
+        let config =
+            Config::resolve(&file(), None, Settings::default(), Settings::default()).unwrap();
+        assert_eq!(config.concurrency, 2);
+        assert_eq!(config.proxy(), Some(DEFAULT_PROXY));
+        assert_eq!(config.cities.len(), 2);
+
+        let config = Config::resolve(
+            &file(),
+            Some("direct-dev"),
+            Settings::default(),
+            Settings::default(),
+        )
+        .unwrap();
+        assert_eq!(config.proxy(), None);
+        assert_eq!(config.base_url.unwrap().as_str(), "http://localhost:8080/");
+
+        let env = Settings::from_env(|name| match name {
+            "BLUEBOOK_CONCURRENCY" => Some("8".to_string()),
+            "BLUEBOOK_CITIES" => Some("Boston, MA; Hartford, CT".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let flags = Settings {
+            concurrency: Some(3),
+            ..Settings::default()
+        };
+        let config = Config::resolve(&file(), Some("tor-slow"), env, flags).unwrap();
+        assert_eq!(config.concurrency, 3);
+        assert_eq!(config.cities, vec!["Boston, MA", "Hartford, CT"]);
+    }
+
+    #[test]
+    fn test_example_profiles() {
+        let example = ConfigFile::load(Path::new("bluebook.example.toml"), true).unwrap();
+        for profile in &["tor-slow", "direct-dev", "full-northeast"] {
+            Config::resolve(
+                &example,
+                Some(profile),
+                Settings::default(),
+                Settings::default(),
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let error = Config::resolve(
+            &file(),
+            Some("fast"),
+            Settings::default(),
+            Settings::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown profile \"fast\", expected one of direct-dev, tor-slow"
+        );
+
+        let env = Settings::from_env(|name| match name {
+            "BLUEBOOK_CONCURRENCY" => Some("0".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let error = Config::resolve(&file(), None, env, Settings::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid concurrency in environment: 0 is not between 1 and 256"
+        );
+
+        let flags = Settings {
+            proxy: Some("tor".to_string()),
+            ..Settings::default()
+        };
+        let error = Config::resolve(&file(), None, Settings::default(), flags).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("invalid proxy in command line flags"));
+
+        let typo = ConfigFile::parse(Path::new("bluebook.toml"), "concurrenc = 2");
+        assert!(matches!(typo, Err(Error::Parse { .. })));
+    }
+
+    #[test]
+    fn test_dump_round_trip() {
+        let config = Config::resolve(
+            &file(),
+            Some("direct-dev"),
+            Settings::default(),
+            Settings::default(),
+        )
+        .unwrap();
+        let dumped = config.to_toml();
+        assert!(dumped.starts_with("# profile: direct-dev\n"));
+        let reloaded = ConfigFile::parse(Path::new("dump.toml"), &dumped).unwrap();
+        let config_again =
+            Config::resolve(&reloaded, None, Settings::default(), Settings::default()).unwrap();
+        assert_eq!(
+            Config {
+                profile: Some("direct-dev".to_string()),
+                ..config_again
+            },
+            config
+        );
+    }
+}
@@ -0,0 +1,517 @@
+use crate::api::{
+    get_categories_page, get_company_page, get_locations_page, get_search_page, Client,
+};
+use crate::data::CompanyProfile;
+use crate::error::Error;
+use crate::parser::{division_ids, get_company_profile, get_page_ids, heading_ids};
+use crate::queue::{Job, JobKind, JobQueue};
+use crate::search::{pages_count, search_ids, SearchMode};
+use crate::sink::JsonLinesSink;
+use chrono::Utc;
+use futures::future::{try_join, try_join_all};
+use futures::stream::{self, FuturesUnordered, StreamExt};
+use select::document::Document;
+use select::predicate::Name;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tracing::{info, info_span, warn, Instrument, Span};
+
+/// Fetches everything a profile is built from and parses it. The same path
+/// serves live crawls and replays of an archive, depending on `client`.
+pub async fn scrape_company(client: &Client, company_id: i32) -> Result<CompanyProfile, Error> {
+    async {
+        let started = Instant::now();
+        let (company_page, locations_page) = try_join(
+            get_company_page(client, company_id),
+            get_locations_page(client, company_id),
+        )
+        .await
+        .map_err(|e| Error::company(company_id, e))?;
+
+        let profile = get_company_profile(client, company_id, &company_page, &locations_page)
+            .await
+            .map_err(|e| Error::company(company_id, e))?;
+        info!(
+            locations = profile.locations.len(),
+            contacts = profile.contacts.len(),
+            categories = profile.categories.len(),
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "scraped"
+        );
+        Ok(profile)
+    }
+    .instrument(info_span!("company", company_id))
+    .await
+}
+
+/// How a crawl went. Failures of single classes or companies don't stop it,
+/// being blocked by the site does.
+#[derive(Debug, Default)]
+pub struct CrawlReport {
+    /// Companies scraped, in the order they came in.
+    pub scraped: Vec<i32>,
+    pub not_found: Vec<i32>,
+    pub failed_classes: Vec<(i32, String)>,
+    pub failed: Vec<(i32, String)>,
+    /// Why the crawl stopped early, if it did.
+    pub blocked: Option<String>,
+}
+
+impl CrawlReport {
+    pub fn is_complete(&self) -> bool {
+        self.blocked.is_none() && self.failed.is_empty() && self.failed_classes.is_empty()
+    }
+
+    fn record_failure(&mut self, company_id: i32, error: Error) {
+        warn!(company_id, error = %error.report(), "company failed");
+        match error.api_error() {
+            Some(api_error) if api_error.is_block() => self.blocked = Some(error.report()),
+            Some(api_error) if api_error.is_not_found() => self.not_found.push(company_id),
+            _ => self.failed.push((company_id, error.report())),
+        }
+    }
+}
+
+impl fmt::Display for CrawlReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (class_id, report) in &self.failed_classes {
+            writeln!(f, "! class {:04}: {}", class_id, report)?;
+        }
+        for (_, report) in &self.failed {
+            writeln!(f, "! {}", report)?;
+        }
+        if let Some(report) = &self.blocked {
+            writeln!(f, "stopped, blocked by the site: {}", report)?;
+        }
+        write!(
+            f,
+            "{} scraped, {} not found, {} failed",
+            self.scraped.len(),
+            self.not_found.len(),
+            self.failed.len()
+        )
+    }
+}
+
+/// Scrapes `company_ids`, up to `concurrency` at a time, writing profiles to
+/// `sink` as they come in.
+pub async fn scrape_companies(
+    client: &Client,
+    company_ids: &[i32],
+    concurrency: usize,
+    sink: &mut JsonLinesSink,
+) -> Result<CrawlReport, Error> {
+    let mut report = CrawlReport::default();
+    let mut results = stream::iter(company_ids.iter().copied())
+        .map(|id| async move { (id, scrape_company(client, id).await) })
+        .buffer_unordered(concurrency.max(1));
+
+    while let Some((id, result)) = results.next().await {
+        match result {
+            Ok(profile) => {
+                sink.write(&profile)?;
+                report.scraped.push(id);
+            }
+            Err(e) => {
+                report.record_failure(id, e);
+                if report.blocked.is_some() {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Lists every company of `class_ids` in `cities` and scrapes those not in
+/// `skip`.
+pub async fn crawl(
+    client: &Client,
+    class_ids: &[i32],
+    cities: &[&str],
+    skip: &HashSet<i32>,
+    concurrency: usize,
+    sink: &mut JsonLinesSink,
+) -> Result<CrawlReport, Error> {
+    let mut report = CrawlReport::default();
+    let mut seen = skip.clone();
+    let mut company_ids = Vec::new();
+
+    for &class_id in class_ids {
+        let span = info_span!("class", class_id);
+        match search_ids(client, SearchMode::Class(class_id), cities, concurrency)
+            .instrument(span.clone())
+            .await
+        {
+            Ok(ids) => company_ids.extend(ids.into_iter().filter(|id| seen.insert(*id))),
+            Err(e) if matches!(e.api_error(), Some(api_error) if api_error.is_block()) => {
+                span.in_scope(|| warn!(error = %e.report(), "blocked"));
+                report.blocked = Some(e.report());
+                return Ok(report);
+            }
+            Err(e) => {
+                span.in_scope(|| warn!(error = %e.report(), "class failed"));
+                report.failed_classes.push((class_id, e.report()))
+            }
+        }
+    }
+
+    let scraped = scrape_companies(client, &company_ids, concurrency, sink).await?;
+    Ok(CrawlReport {
+        failed_classes: report.failed_classes,
+        ..scraped
+    })
+}
+
+/// What a job of the queue came up with.
+enum JobOutput {
+    Jobs(Vec<JobKind>),
+    Profile(Box<CompanyProfile>),
+}
+
+/// Keeps a page for the company's assembly, under the name the fixture
+/// client looks for.
+fn save_page(dir: &Path, name: &str, page: &Document) -> Result<(), Error> {
+    fs::create_dir_all(dir)?;
+    let html = page.find(Name("html")).next().map(|node| node.html());
+    fs::write(dir.join(name), html.unwrap_or_default())?;
+    Ok(())
+}
+
+async fn run_job(client: &Client, kind: &JobKind, pages_dir: &Path) -> Result<JobOutput, Error> {
+    let jobs = match kind {
+        JobKind::SearchPage {
+            class_id,
+            city,
+            page_number,
+        } => {
+            let page = get_search_page(client, *class_id, *page_number, city).await?;
+            let mut jobs: Vec<JobKind> = get_page_ids(&page)
+                .await?
+                .into_iter()
+                .map(|company_id| JobKind::CompanyPage { company_id })
+                .collect();
+            if *page_number == 1 {
+                let pages_count = pages_count(&page).await?;
+                jobs.extend((2..=pages_count).map(|page_number| JobKind::SearchPage {
+                    class_id: *class_id,
+                    city: city.clone(),
+                    page_number,
+                }));
+            }
+            jobs
+        }
+        JobKind::CompanyPage { company_id } => {
+            let company_id = *company_id;
+            let page = get_company_page(client, company_id).await?;
+            save_page(pages_dir, &format!("company-{}.html", company_id), &page)?;
+            let mut jobs = vec![JobKind::LocationsPage { company_id }];
+            jobs.extend(
+                division_ids(&page)
+                    .into_iter()
+                    .map(|div_id| JobKind::CategoryDivision { company_id, div_id }),
+            );
+            jobs.push(JobKind::Assemble { company_id });
+            jobs
+        }
+        JobKind::LocationsPage { company_id } => {
+            let page = get_locations_page(client, *company_id).await?;
+            save_page(pages_dir, &format!("locations-{}.html", company_id), &page)?;
+            Vec::new()
+        }
+        JobKind::CategoryDivision { company_id, div_id } => {
+            let page = get_categories_page(client, *company_id, div_id, None).await?;
+            let headings = heading_ids(&page);
+            let heading_pages = try_join_all(headings.iter().map(|(div_id, head_id)| {
+                get_categories_page(client, *company_id, div_id, Some(head_id))
+            }))
+            .await?;
+            for ((div_id, head_id), heading_page) in headings.iter().zip(&heading_pages) {
+                let name = format!("subcategory-{}-{}.html", div_id, head_id);
+                save_page(pages_dir, &name, heading_page)?;
+            }
+            save_page(pages_dir, &format!("category-{}.html", div_id), &page)?;
+            Vec::new()
+        }
+        JobKind::Assemble { company_id } => {
+            let profile = scrape_company(&Client::fixtures(pages_dir), *company_id).await?;
+            return Ok(JobOutput::Profile(Box::new(profile)));
+        }
+    };
+    Ok(JobOutput::Jobs(jobs))
+}
+
+/// The span of the crawl stage a job belongs to.
+fn job_span(job: &Job) -> Span {
+    match &job.kind {
+        JobKind::SearchPage {
+            class_id,
+            city,
+            page_number,
+        } => info_span!("search_page", job_id = job.id, class_id, page_number, city = %city),
+        JobKind::CompanyPage { company_id } | JobKind::LocationsPage { company_id } => {
+            info_span!("company", job_id = job.id, company_id)
+        }
+        JobKind::CategoryDivision { company_id, div_id } => {
+            info_span!("company", job_id = job.id, company_id, div_id = %div_id)
+        }
+        // The assembly has a span of its own, see scrape_company().
+        JobKind::Assemble { .. } => info_span!("assemble", job_id = job.id),
+    }
+}
+
+/// Runs the jobs of `queue`, up to `concurrency` at a time, until none are
+/// left that can run, writing profiles to `sink` as companies are
+/// assembled. Companies in `skip` aren't queued.
+pub async fn crawl_queue(
+    client: &Client,
+    queue: &mut JobQueue,
+    skip: &HashSet<i32>,
+    concurrency: usize,
+    sink: &mut JsonLinesSink,
+) -> Result<CrawlReport, Error> {
+    let mut report = CrawlReport::default();
+    let mut running = FuturesUnordered::new();
+
+    loop {
+        while running.len() < concurrency.max(1) {
+            let job: Job = match queue.take(Utc::now()) {
+                Some(job) => job,
+                None => break,
+            };
+            let pages_dir = match job.kind.company_id() {
+                Some(company_id) => queue.pages_dir(company_id),
+                None => PathBuf::new(),
+            };
+            let span = job_span(&job);
+            running.push(
+                async move {
+                    let result = match job.kind.company_id() {
+                        Some(company_id) => run_job(client, &job.kind, &pages_dir)
+                            .await
+                            .map_err(|e| Error::company(company_id, e)),
+                        None => run_job(client, &job.kind, &pages_dir).await,
+                    };
+                    (job, result)
+                }
+                .instrument(span),
+            );
+        }
+
+        let (job, result) = match running.next().await {
+            Some(finished) => finished,
+            None => match queue.wait(Utc::now()) {
+                Some(wait) => {
+                    tokio::time::delay_for(wait.to_std().unwrap_or_default()).await;
+                    continue;
+                }
+                None => break,
+            },
+        };
+
+        match result {
+            Ok(JobOutput::Jobs(kinds)) => {
+                for kind in kinds {
+                    if !matches!(kind, JobKind::CompanyPage { company_id } if skip.contains(&company_id))
+                    {
+                        queue.push(kind, None, Utc::now())?;
+                    }
+                }
+                queue.complete(job.id)?;
+            }
+            Ok(JobOutput::Profile(profile)) => {
+                sink.write(&profile)?;
+                queue.complete(job.id)?;
+                fs::remove_dir_all(queue.pages_dir(profile.id))?;
+                report.scraped.push(profile.id);
+            }
+            Err(e) => match (e.api_error(), &job.kind) {
+                (Some(api_error), _) if api_error.is_block() => {
+                    // Stays pending for the next run, as do the jobs running
+                    // alongside it, which are dropped unfinished.
+                    job_span(&job).in_scope(|| warn!(error = %e.report(), "blocked"));
+                    queue.release(job.id);
+                    queue.release_running();
+                    report.blocked = Some(e.report());
+                    break;
+                }
+                (Some(api_error), JobKind::CompanyPage { company_id })
+                    if api_error.is_not_found() =>
+                {
+                    queue.complete(job.id)?;
+                    report.not_found.push(*company_id);
+                }
+                _ => {
+                    let dead = queue.fail(job.id, e.report(), Utc::now())?;
+                    job_span(&job).in_scope(|| {
+                        warn!(attempts = job.attempts + 1, dead, error = %e.report(), "job failed")
+                    });
+                    if dead {
+                        match &job.kind {
+                            JobKind::SearchPage { class_id, .. } => {
+                                report.failed_classes.push((*class_id, e.report()))
+                            }
+                            kind => report
+                                .failed
+                                .push((kind.company_id().unwrap_or_default(), e.report())),
+                        }
+                    }
+                }
+            },
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{client_factory, Client, Endpoints, HeaderProfile, SessionConfig};
+    use crate::crawler::*;
+    use crate::mock::{Fault, MockServer};
+    use crate::queue::QueueCounts;
+    use crate::sink::read_profiles;
+
+    fn first_search_page() -> JobKind {
+        JobKind::SearchPage {
+            class_id: 4030,
+            city: "New York, NY".to_string(),
+            page_number: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scrape_company_from_fixtures() {
+        let client = Client::fixtures("test-data");
+        let profile = scrape_company(&client, 922369).await.unwrap();
+        assert_eq!(profile.id, 922369);
+        assert_eq!(profile.story, "TEST STORY");
+        assert!(!profile.locations.is_empty());
+        assert!(!profile.contacts.is_empty());
+        assert_eq!(profile.categories.len(), 104);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_company_from_mock_server() {
+        let server = MockServer::start().await;
+        let endpoints = Endpoints::for_base(&server.base_url()).unwrap();
+        let client = client_factory(None, HeaderProfile::default(), SessionConfig::default())
+            .await
+            .unwrap()
+            .with_endpoints(endpoints);
+        let profile = scrape_company(&client, 922369).await.unwrap();
+        assert_eq!(profile.story, "TEST STORY");
+        assert_eq!(profile.categories.len(), 104);
+        assert_eq!(server.hits("/ajax/profile/proViewCSIClassByDiv/"), 7 + 32);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_companies_stops_when_blocked() {
+        let temp = tempfile::tempdir().unwrap();
+        let server = MockServer::start().await;
+        let endpoints = Endpoints::for_base(&server.base_url()).unwrap();
+        let client = client_factory(None, HeaderProfile::default(), SessionConfig::default())
+            .await
+            .unwrap()
+            .with_endpoints(endpoints);
+        server.template(
+            "company-2.html",
+            "<html><head><title>Page Not Found</title></head></html>",
+        );
+        server.push_fault("/iProView/3", Fault::Captcha);
+
+        let path = temp.path().join("crawl.jsonl");
+        let mut sink = JsonLinesSink::create(&path).unwrap();
+        let report = scrape_companies(&client, &[1, 2, 3, 4, 5], 1, &mut sink)
+            .await
+            .unwrap();
+        sink.finish().unwrap();
+
+        assert_eq!(report.scraped, vec![1]);
+        assert_eq!(report.not_found, vec![2]);
+        assert!(report.blocked.unwrap().contains("captcha"));
+        assert_eq!(server.hits("/iProView/5"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_company_reports_id() {
+        let client = Client::fixtures("no-such-dir");
+        let error = scrape_company(&client, 922369).await.unwrap_err();
+        assert!(error.report().starts_with("company 922369: network error"));
+    }
+
+    #[tokio::test]
+    async fn test_crawl_queue_from_fixtures() {
+        let temp = tempfile::tempdir().unwrap();
+        let client = Client::fixtures("test-data");
+        let dir = temp.path().join("crawl-queue");
+        let mut queue = JobQueue::open(&dir).unwrap();
+        queue.push(first_search_page(), None, Utc::now()).unwrap();
+
+        let path = temp.path().join("crawl-queue.jsonl");
+        let mut sink = JsonLinesSink::create(&path).unwrap();
+        let skip = vec![1469248].into_iter().collect();
+        let report = crawl_queue(&client, &mut queue, &skip, 4, &mut sink)
+            .await
+            .unwrap();
+        sink.finish().unwrap();
+
+        // Every page of the listing has the same ten companies.
+        assert!(report.is_complete());
+        assert_eq!(report.scraped.len(), 9);
+        let profiles = read_profiles(&path).unwrap();
+        assert_eq!(profiles.len(), 9);
+        assert!(!profiles.contains_key(&1469248));
+        assert_eq!(profiles[&798320].story, "TEST STORY");
+        assert_eq!(profiles[&798320].categories.len(), 104);
+        assert_eq!(
+            queue.counts(),
+            QueueCounts {
+                pending: 0,
+                done: 6 + 9 * (3 + 7),
+                dead: 0
+            }
+        );
+        assert!(!dir.join("pages").join("798320").exists());
+        assert!(!queue.push(first_search_page(), None, Utc::now()).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_crawl_queue_resumes_after_block() {
+        let temp = tempfile::tempdir().unwrap();
+        let server = MockServer::start().await;
+        let endpoints = Endpoints::for_base(&server.base_url()).unwrap();
+        let client = client_factory(None, HeaderProfile::default(), SessionConfig::default())
+            .await
+            .unwrap()
+            .with_endpoints(endpoints);
+        server.push_fault("/iProView/", Fault::Captcha);
+        let dir = temp.path().join("crawl-queue-block");
+        let path = temp.path().join("crawl-queue-block.jsonl");
+
+        let mut queue = JobQueue::open(&dir).unwrap();
+        queue.push(first_search_page(), None, Utc::now()).unwrap();
+        let mut sink = JsonLinesSink::append(&path).unwrap();
+        let report = crawl_queue(&client, &mut queue, &HashSet::new(), 4, &mut sink)
+            .await
+            .unwrap();
+        sink.finish().unwrap();
+        assert!(report.blocked.unwrap().contains("captcha"));
+
+        // The jobs in flight alongside the blocked one are pending again.
+        let mut sink = JsonLinesSink::append(&path).unwrap();
+        let report = crawl_queue(&client, &mut queue, &HashSet::new(), 2, &mut sink)
+            .await
+            .unwrap();
+        sink.finish().unwrap();
+        assert!(report.is_complete());
+        assert_eq!(queue.counts().pending, 0);
+        let profiles = read_profiles(&path).unwrap();
+        assert_eq!(profiles.len(), report.scraped.len());
+        assert!(profiles
+            .values()
+            .all(|profile| profile.story == "TEST STORY"));
+    }
+}
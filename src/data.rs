@@ -1,10 +1,47 @@
-#[derive(Debug, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CompanyLocation {
     pub address: String,
+    /// `address` split into its parts, if it looks like a US address.
+    #[serde(default)]
+    pub postal_address: Option<PostalAddress>,
     pub phone_number: String,
+    /// `phone_number` parsed, if it is a valid number.
+    #[serde(default)]
+    pub normalized_phone: Option<PhoneNumber>,
+    /// Where the address's ZIP code is, from the gazetteer.
+    #[serde(default)]
+    pub geocode: Option<Geocode>,
+}
+
+/// A point resolved offline from a ZIP code.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Geocode {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Five digit FIPS code of the county most of the ZIP code lies in.
+    pub county_fips: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PostalAddress {
+    /// House number and street, or a PO box.
+    pub street: Option<String>,
+    /// Suite, unit or floor, as written.
+    pub suite: Option<String>,
+    pub city: Option<String>,
+    /// USPS code, e.g. `NY`.
+    pub state: Option<String>,
+    /// Five digit ZIP code.
+    pub zip: Option<String>,
+    /// The four digits of ZIP+4, when given.
+    pub zip4: Option<String>,
+    /// The text the parts were parsed from.
+    pub original: String,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CompanyInfo {
     pub name: String,
     pub rating: Option<String>,
@@ -14,22 +51,123 @@ pub struct CompanyInfo {
     pub duns: Option<String>,
     pub other: Vec<String>,
     pub annual_vol: Option<String>,
+    /// The strings above parsed, where they could be.
+    #[serde(default)]
+    pub facts: CompanyFacts,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompanyFacts {
+    pub founded: Option<i32>,
+    pub employees: Option<EmployeeRange>,
+    pub annual_volume: Option<CurrencyRange>,
+    /// Nine digits, without separators.
+    pub duns: Option<String>,
+    pub rating: Option<Rating>,
+    pub website: Option<Website>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Website {
+    /// Canonical form, e.g. `https://skyviewac.com/`, or the link as given
+    /// when it isn't a URL.
+    pub url: String,
+    /// Registrable domain, e.g. `skyviewac.com`.
+    pub domain: Option<String>,
+    /// Why `domain` can't identify the company, if it can't.
+    pub issue: Option<LinkIssue>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkIssue {
+    /// Not a web address at all.
+    Malformed,
+    /// `example.com`, `localhost`, "n/a" and the like.
+    Placeholder,
+    /// Back to the Blue Book itself.
+    SiteLink,
+    /// A page on a site shared by many companies, e.g. Facebook.
+    SharedHost,
+}
+
+/// Inclusive, `max` is `None` for bands like "500+ Employees".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmployeeRange {
+    pub min: u32,
+    pub max: Option<u32>,
+}
+
+/// Inclusive, in whole units of `currency`. `max` is `None` for bands like
+/// "Over $100 Million".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CurrencyRange {
+    /// ISO 4217 code.
+    pub currency: String,
+    pub min: u64,
+    pub max: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rating {
+    pub score: f64,
+    /// The best possible score.
+    pub scale: f64,
+    pub reviews: Option<u32>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CompanyContact {
     pub name: String,
     pub position: String,
     pub phone: String,
+    /// `phone` parsed, if it is a valid number.
+    #[serde(default)]
+    pub normalized_phone: Option<PhoneNumber>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PhoneNumber {
+    /// E.164, e.g. `+15163791800`.
+    pub e164: String,
+    pub extension: Option<String>,
+    pub kind: PhoneKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhoneKind {
+    /// A North American number tied to an area.
+    Geographic,
+    /// 800, 833, 844, 855, 866, 877 and 888 numbers.
+    TollFree,
+    /// 900 numbers.
+    Premium,
+    /// Outside the North American Numbering Plan.
+    International,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ServiceArea {
     pub state_name: String,
+    /// Counties, despite the name, as the site lists them.
     pub cities: Vec<String>,
+    /// Those of `cities` found in the gazetteer.
+    #[serde(default)]
+    pub counties: Vec<County>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct County {
+    /// As listed in the service area.
+    pub name: String,
+    /// Five digit FIPS code, state and county.
+    pub fips: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Brand {
     pub logo: String,
     pub name: String,
@@ -37,32 +175,33 @@ pub struct Brand {
 
 pub type Category = String;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct ProjectExperience {
     pub general: Vec<String>,
     pub commercial: Vec<String>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Qualification {
     name: String,
     issuer: Option<String>,
     qualifier: Option<String>,
-    expiration: Option<String>
+    expiration: Option<String>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Project {
     name: String,
     image: Option<ImageLink>,
     location: Option<String>,
-    completed: Option<bool>
+    completed: Option<bool>,
 }
 
 pub type ImageLink = String;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CompanyProfile {
+    pub id: i32,
     pub info: CompanyInfo,
     pub locations: Vec<CompanyLocation>,
     pub contacts: Vec<CompanyContact>,
@@ -76,5 +215,30 @@ pub struct CompanyProfile {
     pub social_media: Vec<String>,
     pub qualifications: Vec<Qualification>,
     pub media_gallery: Vec<ImageLink>,
-    pub projects: Vec<Project>
+    pub projects: Vec<Project>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClassEntry {
+    pub id: i32,
+    pub name: Option<String>,
+    pub group: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchSummary {
+    pub id: i32,
+    pub name: String,
+    pub locality: Option<String>,
+    pub region: Option<String>,
+    pub postal_code: Option<String>,
+    /// `locality`, `region` and `postal_code` as an address.
+    #[serde(default)]
+    pub address: Option<PostalAddress>,
+    pub phone: Option<String>,
+    pub website: Option<String>,
+    /// `website` in canonical form.
+    #[serde(default)]
+    pub canonical_website: Option<Website>,
+    pub keywords: Vec<String>,
 }
@@ -1,42 +1,155 @@
-use crate::{api, parser};
+use crate::{api, config, gazetteer, parser};
 use std::fmt;
 
 #[derive(Debug)]
 pub enum Error {
-    SerdeError(serde_json::Error),
-    IOError(std::io::Error),
-    ApiError(api::Error),
-    ParserError(parser::Error),
+    Json(serde_json::Error),
+    Io(std::io::Error),
+    Api(api::Error),
+    Parser(parser::Error),
+    Config(config::Error),
+    Gazetteer(gazetteer::Error),
+    Parquet(parquet::errors::ParquetError),
+    /// Anything that went wrong while scraping one company.
+    Company {
+        company_id: i32,
+        source: Box<Error>,
+    },
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Error: {:?}", self)
+        match self {
+            Error::Json(_) => write!(f, "JSON error"),
+            Error::Io(_) => write!(f, "I/O error"),
+            Error::Api(_) => write!(f, "network error"),
+            Error::Parser(_) => write!(f, "parse error"),
+            Error::Config(_) => write!(f, "configuration error"),
+            Error::Gazetteer(_) => write!(f, "gazetteer error"),
+            Error::Parquet(_) => write!(f, "Parquet error"),
+            Error::Company { company_id, .. } => write!(f, "company {}", company_id),
+        }
     }
 }
 
-impl std::error::Error for Error {}
+impl Error {
+    /// The network error behind this one, however deep in the chain.
+    pub fn api_error(&self) -> Option<&api::Error> {
+        let mut source: Option<&(dyn std::error::Error + 'static)> = Some(self);
+        while let Some(error) = source {
+            if let Some(api_error) = error.downcast_ref::<api::Error>() {
+                return Some(api_error);
+            }
+            source = error.source();
+        }
+        None
+    }
+
+    pub fn company(company_id: i32, source: impl Into<Error>) -> Self {
+        Error::Company {
+            company_id,
+            source: Box::new(source.into()),
+        }
+    }
+
+    /// The whole `source()` chain on one line, e.g. for a log or a report of
+    /// failed companies.
+    pub fn report(&self) -> String {
+        let mut report = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(cause) = source {
+            report.push_str(": ");
+            report.push_str(&cause.to_string());
+            source = cause.source();
+        }
+        report
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Json(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Api(e) => Some(e),
+            Error::Parser(e) => Some(e),
+            Error::Config(e) => Some(e),
+            Error::Gazetteer(e) => Some(e),
+            Error::Parquet(e) => Some(e),
+            Error::Company { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
 
 impl std::convert::From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
-        Error::IOError(e)
+        Error::Io(e)
     }
 }
 
 impl std::convert::From<serde_json::error::Error> for Error {
     fn from(e: serde_json::error::Error) -> Self {
-        Error::SerdeError(e)
+        Error::Json(e)
     }
 }
 
 impl From<parser::Error> for Error {
     fn from(e: parser::Error) -> Self {
-        Error::ParserError(e)
+        Error::Parser(e)
+    }
+}
+
+impl From<config::Error> for Error {
+    fn from(e: config::Error) -> Self {
+        Error::Config(e)
+    }
+}
+
+impl From<gazetteer::Error> for Error {
+    fn from(e: gazetteer::Error) -> Self {
+        Error::Gazetteer(e)
+    }
+}
+
+impl From<parquet::errors::ParquetError> for Error {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        Error::Parquet(e)
     }
 }
 
 impl From<api::Error> for Error {
     fn from(e: api::Error) -> Self {
-        Error::ApiError(e)
+        Error::Api(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_report() {
+        let source = "x".parse::<i32>().unwrap_err();
+        let error: Error = parser::Error::data_convert("search_results", "x", source).into();
+        assert_eq!(
+            error.report(),
+            "parse error: search_results: cannot convert \"x\" to a number: \
+             invalid digit found in string"
+        )
+    }
+
+    #[test]
+    fn test_nested_api_error() {
+        let blocked = api::Error::Blocked {
+            url: "http://www.thebluebook.com/".to_string(),
+        };
+        let subpage = parser::Error::network_subpage("company_categories", 1, blocked);
+        let error = Error::company(1, subpage);
+        assert!(error.api_error().unwrap().is_block());
+        assert!(
+            Error::company(1, Error::Io(std::io::ErrorKind::Other.into()))
+                .api_error()
+                .is_none()
+        );
     }
 }
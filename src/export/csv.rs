@@ -0,0 +1,376 @@
+use crate::data::{CompanyLocation, CompanyProfile};
+use crate::error::Error;
+use crate::normalize::{company_domain, parse_address, parse_phone, state_code};
+use std::io::{self, Write};
+
+/// Options for CSV exports.
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    /// Joins lists, like categories, within one cell.
+    pub list_delimiter: String,
+    /// Company columns to write, in this order. All of them when empty.
+    pub columns: Vec<String>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            list_delimiter: "; ".to_string(),
+            columns: Vec::new(),
+        }
+    }
+}
+
+type Cell = fn(&CompanyProfile, &str) -> String;
+
+/// The company table, one row per company. The first location stands for
+/// the company's address and phone, all of them are in the locations table.
+const COMPANY_COLUMNS: &[(&str, Cell)] = &[
+    ("id", |p, _| p.id.to_string()),
+    ("name", |p, _| p.info.name.clone()),
+    ("website", |p, _| p.info.website.clone()),
+    ("domain", |p, _| company_domain(&p.info).unwrap_or_default()),
+    ("rating", |p, _| text(&p.info.rating)),
+    ("founded", |p, _| display(&p.info.facts.founded)),
+    ("size", |p, _| text(&p.info.size)),
+    ("employees_min", |p, _| {
+        display(&p.info.facts.employees.map(|range| range.min))
+    }),
+    ("employees_max", |p, _| {
+        display(&p.info.facts.employees.and_then(|range| range.max))
+    }),
+    ("annual_vol", |p, _| text(&p.info.annual_vol)),
+    ("annual_volume_min", |p, _| {
+        display(&p.info.facts.annual_volume.as_ref().map(|range| range.min))
+    }),
+    ("annual_volume_max", |p, _| {
+        display(
+            &p.info
+                .facts
+                .annual_volume
+                .as_ref()
+                .and_then(|range| range.max),
+        )
+    }),
+    ("duns", |p, _| text(&p.info.facts.duns)),
+    ("phone", |p, _| {
+        p.locations.first().and_then(e164).unwrap_or_default()
+    }),
+    ("street", |p, _| address_part(p, |a| a.street.clone())),
+    ("city", |p, _| address_part(p, |a| a.city.clone())),
+    ("state", |p, _| address_part(p, |a| a.state.clone())),
+    ("zip", |p, _| address_part(p, |a| a.zip.clone())),
+    ("locations", |p, _| p.locations.len().to_string()),
+    ("contacts", |p, _| p.contacts.len().to_string()),
+    ("categories", |p, delimiter| p.categories.join(delimiter)),
+    ("brands", |p, delimiter| {
+        let names: Vec<&str> = p.preferred_brands.iter().map(|b| b.name.as_str()).collect();
+        names.join(delimiter)
+    }),
+    ("service_area_counties", |p, delimiter| {
+        let counties: Vec<String> = p
+            .service_areas
+            .iter()
+            .flat_map(|area| {
+                let state = state_code(&area.state_name).unwrap_or(&area.state_name);
+                area.cities
+                    .iter()
+                    .map(move |county| format!("{}, {}", county, state))
+            })
+            .collect();
+        counties.join(delimiter)
+    }),
+    ("emails", |p, delimiter| p.emails.join(delimiter)),
+    ("social_media", |p, delimiter| {
+        p.social_media.join(delimiter)
+    }),
+    ("logo", |p, _| text(&p.logo)),
+    ("story", |p, _| p.story.clone()),
+];
+
+/// The one-to-many tables written next to the company table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CsvTable {
+    Contacts,
+    Locations,
+    Categories,
+}
+
+impl CsvTable {
+    pub const ALL: [CsvTable; 3] = [
+        CsvTable::Contacts,
+        CsvTable::Locations,
+        CsvTable::Categories,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CsvTable::Contacts => "contacts",
+            CsvTable::Locations => "locations",
+            CsvTable::Categories => "categories",
+        }
+    }
+
+    fn header(self) -> &'static [&'static str] {
+        match self {
+            CsvTable::Contacts => &[
+                "company_id",
+                "name",
+                "position",
+                "phone",
+                "phone_e164",
+                "extension",
+            ],
+            CsvTable::Locations => &[
+                "company_id",
+                "address",
+                "street",
+                "suite",
+                "city",
+                "state",
+                "zip",
+                "phone",
+                "phone_e164",
+                "latitude",
+                "longitude",
+                "county_fips",
+            ],
+            CsvTable::Categories => &["company_id", "category"],
+        }
+    }
+
+    fn rows(self, profile: &CompanyProfile) -> Vec<Vec<String>> {
+        let id = profile.id.to_string();
+        match self {
+            CsvTable::Contacts => profile
+                .contacts
+                .iter()
+                .map(|contact| {
+                    let phone = contact
+                        .normalized_phone
+                        .clone()
+                        .or_else(|| parse_phone(&contact.phone));
+                    vec![
+                        id.clone(),
+                        contact.name.clone(),
+                        contact.position.clone(),
+                        contact.phone.clone(),
+                        phone.as_ref().map(|p| p.e164.clone()).unwrap_or_default(),
+                        phone.and_then(|p| p.extension).unwrap_or_default(),
+                    ]
+                })
+                .collect(),
+            CsvTable::Locations => profile
+                .locations
+                .iter()
+                .map(|location| {
+                    let address = location
+                        .postal_address
+                        .clone()
+                        .or_else(|| parse_address(&location.address))
+                        .unwrap_or_default();
+                    let geocode = location.geocode.as_ref();
+                    vec![
+                        id.clone(),
+                        location.address.clone(),
+                        text(&address.street),
+                        text(&address.suite),
+                        text(&address.city),
+                        text(&address.state),
+                        text(&address.zip),
+                        location.phone_number.clone(),
+                        e164(location).unwrap_or_default(),
+                        display(&geocode.map(|g| g.latitude)),
+                        display(&geocode.map(|g| g.longitude)),
+                        geocode
+                            .and_then(|g| g.county_fips.clone())
+                            .unwrap_or_default(),
+                    ]
+                })
+                .collect(),
+            CsvTable::Categories => profile
+                .categories
+                .iter()
+                .map(|category| vec![id.clone(), category.clone()])
+                .collect(),
+        }
+    }
+}
+
+/// Checks that every requested column exists, so a typo fails before
+/// anything is written.
+pub fn check_columns(columns: &[String]) -> Result<(), String> {
+    match columns
+        .iter()
+        .find(|name| !COMPANY_COLUMNS.iter().any(|(column, _)| column == name))
+    {
+        Some(name) => {
+            let known: Vec<&str> = COMPANY_COLUMNS.iter().map(|(column, _)| *column).collect();
+            Err(format!(
+                "unknown column {:?}, expected any of {}",
+                name,
+                known.join(", ")
+            ))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Writes the company table, one row per company.
+pub fn write_companies(
+    profiles: &[CompanyProfile],
+    options: &CsvOptions,
+    writer: &mut impl Write,
+) -> Result<(), Error> {
+    check_columns(&options.columns).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let columns: Vec<&(&str, Cell)> = if options.columns.is_empty() {
+        COMPANY_COLUMNS.iter().collect()
+    } else {
+        options
+            .columns
+            .iter()
+            .filter_map(|name| COMPANY_COLUMNS.iter().find(|(column, _)| column == name))
+            .collect()
+    };
+
+    let mut csv = ::csv::Writer::from_writer(writer);
+    csv.write_record(columns.iter().map(|(name, _)| *name))
+        .map_err(io::Error::from)?;
+    for profile in profiles {
+        csv.write_record(
+            columns
+                .iter()
+                .map(|(_, cell)| cell(profile, &options.list_delimiter)),
+        )
+        .map_err(io::Error::from)?;
+    }
+    csv.flush()?;
+    Ok(())
+}
+
+/// Writes one of the one-to-many tables, keyed by company id.
+pub fn write_table(
+    profiles: &[CompanyProfile],
+    table: CsvTable,
+    writer: &mut impl Write,
+) -> Result<(), Error> {
+    let mut csv = ::csv::Writer::from_writer(writer);
+    csv.write_record(table.header()).map_err(io::Error::from)?;
+    for profile in profiles {
+        for row in table.rows(profile) {
+            csv.write_record(&row).map_err(io::Error::from)?;
+        }
+    }
+    csv.flush()?;
+    Ok(())
+}
+
+fn text(value: &Option<String>) -> String {
+    value.clone().unwrap_or_default()
+}
+
+fn display<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+fn e164(location: &CompanyLocation) -> Option<String> {
+    location
+        .normalized_phone
+        .clone()
+        .or_else(|| parse_phone(&location.phone_number))
+        .map(|phone| phone.e164)
+}
+
+/// A part of the first location's address.
+fn address_part(
+    profile: &CompanyProfile,
+    part: impl Fn(&crate::data::PostalAddress) -> Option<String>,
+) -> String {
+    profile
+        .locations
+        .first()
+        .and_then(|location| {
+            location
+                .postal_address
+                .clone()
+                .or_else(|| parse_address(&location.address))
+        })
+        .and_then(|address| part(&address))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::csv::*;
+    use crate::test_support::fixture_profile;
+
+    async fn profile() -> CompanyProfile {
+        let mut profile = fixture_profile().await;
+        profile.story = "Family owned.\nWe say \"cool\", you say \"done\".".to_string();
+        profile
+    }
+
+    #[tokio::test]
+    async fn test_write_companies() {
+        let profile = profile().await;
+        let options = CsvOptions {
+            list_delimiter: "|".to_string(),
+            columns: vec![
+                "id".to_string(),
+                "domain".to_string(),
+                "employees_max".to_string(),
+                "zip".to_string(),
+                "service_area_counties".to_string(),
+                "story".to_string(),
+            ],
+        };
+        let mut output = Vec::new();
+        write_companies(&[profile], &options, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "id,domain,employees_max,zip,service_area_counties,story\n\
+             922369,skyviewac.com,4,11701,\
+             \"Bronx, NY|Kings, NY|New York, NY|Queens, NY|Richmond, NY\",\
+             \"Family owned.\nWe say \"\"cool\"\", you say \"\"done\"\".\"\n"
+        );
+
+        let options = CsvOptions {
+            columns: vec!["id".to_string(), "fax".to_string()],
+            ..CsvOptions::default()
+        };
+        assert!(check_columns(&options.columns)
+            .unwrap_err()
+            .starts_with("unknown column \"fax\""));
+        assert!(write_companies(&[], &options, &mut Vec::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_tables() {
+        let profile = profile().await;
+        let mut output = Vec::new();
+        write_table(
+            std::slice::from_ref(&profile),
+            CsvTable::Locations,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "company_id,address,street,suite,city,state,zip,phone,phone_e164,\
+             latitude,longitude,county_fips\n\
+             922369,\"155 Park Ave. Amityville, NY 11701\",155 Park Ave.,,Amityville,NY,\
+             11701,(516) 379-1800,+15163791800,,,\n"
+        );
+
+        let mut output = Vec::new();
+        write_table(
+            std::slice::from_ref(&profile),
+            CsvTable::Categories,
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), profile.categories.len() + 1);
+        assert!(output.starts_with("company_id,category\n922369,"));
+    }
+}
@@ -0,0 +1,73 @@
+use crate::data::CompanyProfile;
+use crate::error::Error;
+pub use crate::export::csv::{check_columns, write_table, CsvOptions, CsvTable};
+pub use crate::export::parquet::{write_parquet, ParquetCompression, ParquetOptions};
+use std::io::Write;
+use std::str::FromStr;
+
+mod csv;
+mod parquet;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// One profile per line, the format crawls write.
+    JsonLines,
+    /// A single JSON array.
+    Json,
+    /// One row per company, see [`CsvOptions`].
+    Csv,
+    /// Columnar, with nested lists, see [`ParquetOptions`].
+    Parquet,
+}
+
+/// Options of the formats that have any.
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    pub csv: CsvOptions,
+    pub parquet: ParquetOptions,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(ExportFormat::JsonLines),
+            "json" => Ok(ExportFormat::Json),
+            "csv" => Ok(ExportFormat::Csv),
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(format!(
+                "unknown export format {:?}, expected jsonl, json, csv or parquet",
+                s
+            )),
+        }
+    }
+}
+
+pub fn export_profiles(
+    profiles: &[CompanyProfile],
+    format: ExportFormat,
+    options: &ExportOptions,
+    writer: &mut (impl Write + Send),
+) -> Result<(), Error> {
+    match format {
+        ExportFormat::JsonLines => {
+            for profile in profiles {
+                serde_json::to_writer(&mut *writer, profile)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut *writer, profiles)?;
+            writer.write_all(b"\n")?;
+        }
+        ExportFormat::Csv => csv::write_companies(profiles, &options.csv, writer)?,
+        ExportFormat::Parquet => parquet::write_parquet(
+            profiles.iter().cloned().map(Ok),
+            &options.parquet,
+            &mut *writer,
+        )?,
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,343 @@
+use crate::data::CompanyProfile;
+use crate::error::Error;
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::basic::{Compression, GzipLevel, ZstdLevel};
+use ::parquet::errors::ParquetError;
+use ::parquet::file::properties::WriterProperties;
+use arrow_json::ReaderBuilder;
+use arrow_schema::{DataType, Field, Fields, Schema, SchemaRef};
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Options for Parquet exports.
+#[derive(Debug, Clone)]
+pub struct ParquetOptions {
+    /// Companies per row group, and at most this many are held in memory.
+    pub row_group_size: usize,
+    pub compression: ParquetCompression,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        ParquetOptions {
+            row_group_size: 10_000,
+            compression: ParquetCompression::Snappy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParquetCompression {
+    None,
+    Snappy,
+    Gzip,
+    Zstd,
+}
+
+impl FromStr for ParquetCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(ParquetCompression::None),
+            "snappy" => Ok(ParquetCompression::Snappy),
+            "gzip" => Ok(ParquetCompression::Gzip),
+            "zstd" => Ok(ParquetCompression::Zstd),
+            _ => Err(format!(
+                "unknown compression {:?}, expected none, snappy, gzip or zstd",
+                s
+            )),
+        }
+    }
+}
+
+impl From<ParquetCompression> for Compression {
+    fn from(compression: ParquetCompression) -> Self {
+        match compression {
+            ParquetCompression::None => Compression::UNCOMPRESSED,
+            ParquetCompression::Snappy => Compression::SNAPPY,
+            ParquetCompression::Gzip => Compression::GZIP(GzipLevel::default()),
+            ParquetCompression::Zstd => Compression::ZSTD(ZstdLevel::default()),
+        }
+    }
+}
+
+/// The Arrow schema of the export: one row per company, shaped like
+/// [`CompanyProfile`], with its child collections as list columns.
+pub fn profile_schema() -> Schema {
+    let phone = strukt(
+        "normalized_phone",
+        true,
+        vec![
+            utf8("e164", false),
+            utf8("extension", true),
+            utf8("kind", false),
+        ],
+    );
+    Schema::new(vec![
+        Field::new("id", DataType::Int32, false),
+        strukt(
+            "info",
+            false,
+            vec![
+                utf8("name", false),
+                utf8("rating", true),
+                utf8("website", false),
+                utf8("founded", true),
+                utf8("size", true),
+                utf8("duns", true),
+                utf8_list("other"),
+                utf8("annual_vol", true),
+                strukt(
+                    "facts",
+                    false,
+                    vec![
+                        Field::new("founded", DataType::Int32, true),
+                        strukt(
+                            "employees",
+                            true,
+                            vec![
+                                Field::new("min", DataType::UInt32, false),
+                                Field::new("max", DataType::UInt32, true),
+                            ],
+                        ),
+                        strukt(
+                            "annual_volume",
+                            true,
+                            vec![
+                                utf8("currency", false),
+                                Field::new("min", DataType::UInt64, false),
+                                Field::new("max", DataType::UInt64, true),
+                            ],
+                        ),
+                        utf8("duns", true),
+                        strukt(
+                            "rating",
+                            true,
+                            vec![
+                                Field::new("score", DataType::Float64, false),
+                                Field::new("scale", DataType::Float64, false),
+                                Field::new("reviews", DataType::UInt32, true),
+                            ],
+                        ),
+                        strukt(
+                            "website",
+                            true,
+                            vec![
+                                utf8("url", false),
+                                utf8("domain", true),
+                                utf8("issue", true),
+                            ],
+                        ),
+                    ],
+                ),
+            ],
+        ),
+        struct_list(
+            "locations",
+            vec![
+                utf8("address", false),
+                strukt(
+                    "postal_address",
+                    true,
+                    vec![
+                        utf8("street", true),
+                        utf8("suite", true),
+                        utf8("city", true),
+                        utf8("state", true),
+                        utf8("zip", true),
+                        utf8("zip4", true),
+                        utf8("original", false),
+                    ],
+                ),
+                utf8("phone_number", false),
+                phone.clone(),
+                strukt(
+                    "geocode",
+                    true,
+                    vec![
+                        Field::new("latitude", DataType::Float64, false),
+                        Field::new("longitude", DataType::Float64, false),
+                        utf8("county_fips", true),
+                    ],
+                ),
+            ],
+        ),
+        struct_list(
+            "contacts",
+            vec![
+                utf8("name", false),
+                utf8("position", false),
+                utf8("phone", false),
+                phone,
+            ],
+        ),
+        utf8("story", false),
+        utf8_list("categories"),
+        struct_list(
+            "service_areas",
+            vec![
+                utf8("state_name", false),
+                utf8_list("cities"),
+                struct_list(
+                    "counties",
+                    vec![
+                        utf8("name", false),
+                        utf8("fips", false),
+                        Field::new("latitude", DataType::Float64, false),
+                        Field::new("longitude", DataType::Float64, false),
+                    ],
+                ),
+            ],
+        ),
+        strukt(
+            "project_experience",
+            false,
+            vec![utf8_list("general"), utf8_list("commercial")],
+        ),
+        utf8_list("emails"),
+        utf8("logo", true),
+        struct_list(
+            "preferred_brands",
+            vec![utf8("logo", false), utf8("name", false)],
+        ),
+        utf8_list("social_media"),
+        struct_list(
+            "qualifications",
+            vec![
+                utf8("name", false),
+                utf8("issuer", true),
+                utf8("qualifier", true),
+                utf8("expiration", true),
+            ],
+        ),
+        utf8_list("media_gallery"),
+        struct_list(
+            "projects",
+            vec![
+                utf8("name", false),
+                utf8("image", true),
+                utf8("location", true),
+                Field::new("completed", DataType::Boolean, true),
+            ],
+        ),
+    ])
+}
+
+/// Writes `profiles` as they come, a row group at a time, so only one row
+/// group is ever held in memory.
+pub fn write_parquet<W: Write + Send>(
+    profiles: impl IntoIterator<Item = Result<CompanyProfile, Error>>,
+    options: &ParquetOptions,
+    writer: W,
+) -> Result<(), Error> {
+    let schema: SchemaRef = Arc::new(profile_schema());
+    let row_group_size = options.row_group_size.max(1);
+    let properties = WriterProperties::builder()
+        .set_max_row_group_size(row_group_size)
+        .set_compression(options.compression.into())
+        .build();
+    let mut writer = ArrowWriter::try_new(writer, schema.clone(), Some(properties))?;
+    let mut decoder = ReaderBuilder::new(schema)
+        .with_batch_size(row_group_size)
+        .build_decoder()
+        .map_err(ParquetError::from)?;
+
+    let mut rows = Vec::with_capacity(row_group_size);
+    let mut profiles = profiles.into_iter().peekable();
+    while let Some(profile) = profiles.next() {
+        rows.push(profile?);
+        if rows.len() == row_group_size || profiles.peek().is_none() {
+            decoder.serialize(&rows).map_err(ParquetError::from)?;
+            rows.clear();
+            let batch = decoder.flush().map_err(ParquetError::from)?;
+            if let Some(batch) = batch {
+                writer.write(&batch)?;
+            }
+        }
+    }
+    writer.close()?;
+    Ok(())
+}
+
+fn utf8(name: &str, nullable: bool) -> Field {
+    Field::new(name, DataType::Utf8, nullable)
+}
+
+fn strukt(name: &str, nullable: bool, fields: Vec<Field>) -> Field {
+    Field::new(name, DataType::Struct(Fields::from(fields)), nullable)
+}
+
+/// A list that is empty rather than null when there's nothing in it.
+fn list(name: &str, item: DataType) -> Field {
+    let item = Field::new("item", item, false);
+    Field::new(name, DataType::List(Arc::new(item)), false)
+}
+
+fn utf8_list(name: &str) -> Field {
+    list(name, DataType::Utf8)
+}
+
+fn struct_list(name: &str, fields: Vec<Field>) -> Field {
+    list(name, DataType::Struct(Fields::from(fields)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::parquet::*;
+    use crate::test_support::{fixture_profile, variant};
+    use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int32Type;
+    use std::fs::File;
+
+    #[tokio::test]
+    async fn test_write_parquet() {
+        let temp = tempfile::tempdir().unwrap();
+        let profile = fixture_profile().await;
+        let other = variant(&profile, 922370, |other| {
+            other.categories.clear();
+            other.locations.clear();
+        });
+
+        let path = temp.path().join("export.parquet");
+        let options = ParquetOptions {
+            row_group_size: 1,
+            compression: ParquetCompression::Zstd,
+        };
+        let profiles = vec![Ok(profile.clone()), Ok(other)];
+        write_parquet(profiles, &options, File::create(&path).unwrap()).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().num_row_groups(), 2);
+        assert_eq!(reader.schema().as_ref(), &profile_schema());
+        let batches: Vec<_> = reader.build().unwrap().collect::<Result<_, _>>().unwrap();
+
+        let ids: Vec<i32> = batches
+            .iter()
+            .flat_map(|batch| batch["id"].as_primitive::<Int32Type>().values().to_vec())
+            .collect();
+        assert_eq!(ids, vec![922369, 922370]);
+        let categories = batches[0]["categories"].as_list::<i32>().value(0);
+        assert_eq!(categories.len(), profile.categories.len());
+        assert_eq!(
+            categories.as_string::<i32>().value(0),
+            profile.categories[0]
+        );
+        assert!(batches[0]["categories"]
+            .as_list::<i32>()
+            .value(1)
+            .is_empty());
+        let cities = batches[0]["service_areas"].as_list::<i32>().value(0);
+        let cities = cities.as_struct().column_by_name("cities").unwrap();
+        assert_eq!(cities.as_list::<i32>().value(0).len(), 5);
+    }
+
+    #[test]
+    fn test_write_error() {
+        let failed = vec![Err(Error::Io(std::io::ErrorKind::InvalidData.into()))];
+        let result = write_parquet(failed, &ParquetOptions::default(), Vec::new());
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+}
@@ -0,0 +1,489 @@
+//! Offline geocoding from a gazetteer file, so no address ever goes to an
+//! online geocoder.
+//!
+//! The file is tab separated with a header row naming the columns `kind`,
+//! `code`, `state`, `name`, `county_fips`, `latitude` and `longitude`, in any
+//! order. `county` rows have the county's FIPS code as `code`, `zip` rows the
+//! ZIP code and the FIPS code of the county most of it lies in. Both are
+//! built from the Census Bureau's county and ZCTA gazetteer files and its
+//! ZCTA to county relationship file by [`build_gazetteer`], which is what
+//! `build-gazetteer` runs. Lines starting with `#` are comments.
+
+use crate::data::{CompanyProfile, County, Geocode, PostalAddress};
+use crate::normalize::{parse_address, state_code};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+const COLUMNS: &[&str] = &[
+    "kind",
+    "code",
+    "state",
+    "name",
+    "county_fips",
+    "latitude",
+    "longitude",
+];
+/// Dropped from county names before matching, the site lists "Kings" for
+/// "Kings County".
+const COUNTY_SUFFIXES: &[&str] = &[" county", " parish", " borough", " census area"];
+
+#[derive(Debug)]
+pub enum Error {
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// `line` counts from 1.
+    Line {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Read { path, .. } => write!(f, "cannot read gazetteer {}", path.display()),
+            Error::Line { path, line, reason } => {
+                write!(f, "{}:{}: {}", path.display(), line, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Read { source, .. } => Some(source),
+            Error::Line { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CountyEntry {
+    fips: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+/// ZIP codes and counties with their coordinates, loaded once at startup.
+#[derive(Debug, Default)]
+pub struct Gazetteer {
+    zips: HashMap<String, Geocode>,
+    /// By USPS state code and [`county_key`].
+    counties: HashMap<(String, String), CountyEntry>,
+}
+
+impl Gazetteer {
+    pub fn load(path: &Path) -> Result<Gazetteer, Error> {
+        let text = std::fs::read_to_string(path).map_err(|source| Error::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Gazetteer::parse(path, &text)
+    }
+
+    /// Parses the text of the file at `path`, which only shows in errors.
+    pub fn parse(path: &Path, text: &str) -> Result<Gazetteer, Error> {
+        let mut gazetteer = Gazetteer::default();
+        let mut columns: Option<Vec<usize>> = None;
+        for (index, line) in text.lines().enumerate() {
+            let invalid = |reason: String| Error::Line {
+                path: path.to_path_buf(),
+                line: index + 1,
+                reason,
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+            let columns = match &columns {
+                Some(columns) => columns,
+                None => {
+                    let header = COLUMNS
+                        .iter()
+                        .map(|name| {
+                            fields
+                                .iter()
+                                .position(|field| field.eq_ignore_ascii_case(name))
+                                .ok_or_else(|| invalid(format!("no {} column", name)))
+                        })
+                        .collect::<Result<_, _>>()?;
+                    columns = Some(header);
+                    continue;
+                }
+            };
+            let field = |column: usize| fields.get(columns[column]).copied().unwrap_or("");
+            let (kind, code, state, name, county_fips) =
+                (field(0), field(1), field(2), field(3), field(4));
+            let coordinate = |column: usize, limit: f64| match field(column).parse::<f64>() {
+                Ok(value) if value.abs() <= limit => Ok(value),
+                _ => Err(invalid(format!(
+                    "{:?} is not a valid {}",
+                    field(column),
+                    COLUMNS[column]
+                ))),
+            };
+            let (latitude, longitude) = (coordinate(5, 90.0)?, coordinate(6, 180.0)?);
+            if !is_code(code) {
+                return Err(invalid(format!("{:?} is not a five digit code", code)));
+            }
+
+            match kind {
+                "zip" => {
+                    let county_fips = match county_fips {
+                        "" => None,
+                        fips if is_code(fips) => Some(fips.to_string()),
+                        fips => {
+                            return Err(invalid(format!("{:?} is not a county FIPS code", fips)))
+                        }
+                    };
+                    let geocode = Geocode {
+                        latitude,
+                        longitude,
+                        county_fips,
+                    };
+                    gazetteer.zips.insert(code.to_string(), geocode);
+                }
+                "county" => {
+                    let state = state_code(state)
+                        .ok_or_else(|| invalid(format!("{:?} is not a US state", state)))?;
+                    if name.is_empty() {
+                        return Err(invalid("county without a name".to_string()));
+                    }
+                    let entry = CountyEntry {
+                        fips: code.to_string(),
+                        latitude,
+                        longitude,
+                    };
+                    gazetteer
+                        .counties
+                        .insert((state.to_string(), county_key(name)), entry);
+                }
+                _ => return Err(invalid(format!("unknown kind {:?}", kind))),
+            }
+        }
+        Ok(gazetteer)
+    }
+
+    /// Where the ZIP code of `address` is.
+    pub fn locate(&self, address: &PostalAddress) -> Option<Geocode> {
+        self.zips.get(address.zip.as_ref()?).cloned()
+    }
+
+    /// A county by name, with or without "County", in a state given by name
+    /// or code.
+    pub fn county(&self, state: &str, name: &str) -> Option<County> {
+        let key = (state_code(state)?.to_string(), county_key(name));
+        self.counties.get(&key).map(|entry| County {
+            name: name.to_string(),
+            fips: entry.fips.clone(),
+            latitude: entry.latitude,
+            longitude: entry.longitude,
+        })
+    }
+
+    /// Fills in the coordinates of the profile's locations and service area
+    /// counties, replacing any from an earlier run. Locations stored before
+    /// addresses were parsed are parsed on the fly.
+    pub fn geocode_profile(&self, profile: &mut CompanyProfile) {
+        for location in &mut profile.locations {
+            let address = match &location.postal_address {
+                Some(address) => Some(address.clone()),
+                None => parse_address(&location.address),
+            };
+            location.geocode = address.and_then(|address| self.locate(&address));
+        }
+        for area in &mut profile.service_areas {
+            area.counties = area
+                .cities
+                .iter()
+                .filter_map(|name| self.county(&area.state_name, name))
+                .collect();
+        }
+    }
+}
+
+/// A table of the Census Bureau, with a header row naming its columns.
+struct CensusTable {
+    path: PathBuf,
+    header: Vec<String>,
+    /// With their line numbers, counting from 1.
+    rows: Vec<(usize, Vec<String>)>,
+}
+
+impl CensusTable {
+    fn read(path: &Path, delimiter: char) -> Result<CensusTable, Error> {
+        let text = std::fs::read_to_string(path).map_err(|source| Error::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let split = |line: &str| -> Vec<String> {
+            line.split(delimiter)
+                .map(|field| field.trim().to_string())
+                .collect()
+        };
+        let mut lines = text
+            .trim_start_matches('\u{feff}')
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let header = lines
+            .next()
+            .map(|(_, line)| split(line))
+            .unwrap_or_default();
+        Ok(CensusTable {
+            path: path.to_path_buf(),
+            header,
+            rows: lines
+                .map(|(index, line)| (index + 1, split(line)))
+                .collect(),
+        })
+    }
+
+    fn column(&self, name: &str) -> Result<usize, Error> {
+        self.header
+            .iter()
+            .position(|column| column.eq_ignore_ascii_case(name))
+            .ok_or_else(|| Error::Line {
+                path: self.path.clone(),
+                line: 1,
+                reason: format!("no {} column", name),
+            })
+    }
+}
+
+fn field(row: &[String], column: usize) -> &str {
+    row.get(column).map(String::as_str).unwrap_or("")
+}
+
+/// Builds the gazetteer file from the Census Bureau's county and ZCTA
+/// gazetteer files, like `2023_Gaz_counties_national.txt` and
+/// `2023_Gaz_zcta_national.txt`, and its ZCTA to county relationship file,
+/// `tab20_zcta520_county20_natl.txt`. A ZIP code gets the county, and the
+/// state, that most of its land lies in.
+pub fn build_gazetteer(
+    counties: &Path,
+    zctas: &Path,
+    relationships: &Path,
+) -> Result<String, Error> {
+    let counties = CensusTable::read(counties, '\t')?;
+    let (state, geoid, name, latitude, longitude) = (
+        counties.column("USPS")?,
+        counties.column("GEOID")?,
+        counties.column("NAME")?,
+        counties.column("INTPTLAT")?,
+        counties.column("INTPTLONG")?,
+    );
+    let mut rows = Vec::new();
+    let mut county_states = HashMap::new();
+    for (_, row) in &counties.rows {
+        county_states.insert(field(row, geoid), field(row, state));
+        rows.push(vec![
+            "county",
+            field(row, geoid),
+            field(row, state),
+            field(row, name),
+            "",
+            field(row, latitude),
+            field(row, longitude),
+        ]);
+    }
+    rows.sort_by(|a, b| a[1].cmp(b[1]));
+
+    let relationships = CensusTable::read(relationships, '|')?;
+    let (zcta, county, land) = (
+        relationships.column("GEOID_ZCTA5_20")?,
+        relationships.column("GEOID_COUNTY_20")?,
+        relationships.column("AREALAND_PART")?,
+    );
+    let mut largest: HashMap<&str, (u64, &str)> = HashMap::new();
+    for (line, row) in &relationships.rows {
+        let area = field(row, land).parse::<u64>().map_err(|_| Error::Line {
+            path: relationships.path.clone(),
+            line: *line,
+            reason: format!("{:?} is not an area", field(row, land)),
+        })?;
+        let part = largest.entry(field(row, zcta)).or_insert((0, ""));
+        if area >= part.0 {
+            *part = (area, field(row, county));
+        }
+    }
+
+    let zctas = CensusTable::read(zctas, '\t')?;
+    let (geoid, latitude, longitude) = (
+        zctas.column("GEOID")?,
+        zctas.column("INTPTLAT")?,
+        zctas.column("INTPTLONG")?,
+    );
+    let mut zips = BTreeMap::new();
+    for (_, row) in &zctas.rows {
+        let county = largest
+            .get(field(row, geoid))
+            .map_or("", |(_, county)| county);
+        let state = county_states.get(county).copied().unwrap_or("");
+        zips.insert(
+            field(row, geoid),
+            vec![
+                "zip",
+                field(row, geoid),
+                state,
+                "",
+                county,
+                field(row, latitude),
+                field(row, longitude),
+            ],
+        );
+    }
+    rows.extend(zips.into_values());
+
+    let mut text = COLUMNS.join("\t");
+    text.push('\n');
+    for row in rows {
+        text.push_str(&row.join("\t"));
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+fn is_code(code: &str) -> bool {
+    code.len() == 5 && code.chars().all(|c| c.is_ascii_digit())
+}
+
+/// A county name as it is matched: lowercase, without periods or the word
+/// for a county, "Saint" shortened the way the site writes it.
+fn county_key(name: &str) -> String {
+    let mut key = name.trim().to_lowercase().replace('.', "");
+    for suffix in COUNTY_SUFFIXES {
+        if let Some(stripped) = key.strip_suffix(suffix) {
+            key = stripped.to_string();
+            break;
+        }
+    }
+    match key.strip_prefix("saint ") {
+        Some(rest) => format!("st {}", rest),
+        None => key,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gazetteer::*;
+    use crate::test_support::fixture_profile;
+
+    fn fixture() -> Gazetteer {
+        Gazetteer::load(Path::new("test-data/gazetteer.tsv")).unwrap()
+    }
+
+    #[test]
+    fn test_lookups() {
+        let gazetteer = fixture();
+        let address = parse_address("155 Park Ave. Amityville, NY 11701").unwrap();
+        let geocode = gazetteer.locate(&address).unwrap();
+        assert_eq!(geocode.county_fips.as_deref(), Some("36103"));
+        assert!((geocode.latitude - 40.68486).abs() < 1e-6);
+        assert_eq!(
+            gazetteer.locate(&parse_address("Springfield, IL 62701").unwrap()),
+            None
+        );
+
+        assert_eq!(gazetteer.county("New York", "Kings").unwrap().fips, "36047");
+        assert_eq!(
+            gazetteer.county("NY", "Suffolk County").unwrap().fips,
+            "36103"
+        );
+        assert_eq!(
+            gazetteer.county("louisiana", "Orleans").unwrap().fips,
+            "22071"
+        );
+        assert_eq!(gazetteer.county("New Jersey", "Kings"), None);
+        assert_eq!(gazetteer.county("Atlantis", "Kings"), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let path = Path::new("gazetteer.tsv");
+        let header = "code\tkind\tname\tstate\tlatitude\tlongitude\tcounty_fips\n";
+        let parse = |rows: &str| Gazetteer::parse(path, &format!("{}{}", header, rows));
+
+        let gazetteer = parse("# comment\n\n11701\tzip\t\tNY\t40.68\t-73.41\t\n").unwrap();
+        assert_eq!(gazetteer.zips["11701"].county_fips, None);
+
+        let error = parse("1170\tzip\t\tNY\t40.68\t-73.41\t\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "gazetteer.tsv:2: \"1170\" is not a five digit code"
+        );
+        let error = parse("36047\tcounty\tKings\tNY\t140.6\t-73.95\t\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "gazetteer.tsv:2: \"140.6\" is not a valid latitude"
+        );
+        let error = parse("36047\tborough\tKings\tNY\t40.6\t-73.95\t\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "gazetteer.tsv:2: unknown kind \"borough\""
+        );
+        let error = Gazetteer::parse(path, "kind\tcode\n").unwrap_err();
+        assert_eq!(error.to_string(), "gazetteer.tsv:1: no state column");
+    }
+
+    #[test]
+    fn test_build_gazetteer() {
+        let text = build_gazetteer(
+            Path::new("test-data/census-counties.txt"),
+            Path::new("test-data/census-zctas.txt"),
+            Path::new("test-data/census-zcta-county.txt"),
+        )
+        .unwrap();
+        let gazetteer = Gazetteer::parse(Path::new("gazetteer.tsv"), &text).unwrap();
+
+        // Most of 11701 is in Suffolk, a corner in Nassau.
+        let address = parse_address("155 Park Ave. Amityville, NY 11701").unwrap();
+        let geocode = gazetteer.locate(&address).unwrap();
+        assert_eq!(geocode.county_fips.as_deref(), Some("36103"));
+        assert!((geocode.latitude - 40.68486).abs() < 1e-6);
+        assert_eq!(
+            gazetteer.zips["70112"].county_fips.as_deref(),
+            Some("22071")
+        );
+        assert_eq!(gazetteer.county("NY", "Nassau").unwrap().fips, "36059");
+        assert_eq!(gazetteer.county("LA", "Orleans").unwrap().fips, "22071");
+        assert!(text.contains("\nzip\t10001\tNY\t\t36061\t40.750633\t-73.997177\n"));
+
+        let error = build_gazetteer(
+            Path::new("test-data/census-zctas.txt"),
+            Path::new("test-data/census-zctas.txt"),
+            Path::new("test-data/census-zcta-county.txt"),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "test-data/census-zctas.txt:1: no USPS column"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_geocode_profile() {
+        let mut profile = fixture_profile().await;
+        fixture().geocode_profile(&mut profile);
+
+        let geocode = profile.locations[0].geocode.as_ref().unwrap();
+        assert_eq!(geocode.county_fips.as_deref(), Some("36103"));
+        let fips: Vec<&str> = profile.service_areas[0]
+            .counties
+            .iter()
+            .map(|county| county.fips.as_str())
+            .collect();
+        assert_eq!(fips, vec!["36005", "36047", "36061", "36081", "36085"]);
+        assert_eq!(profile.service_areas[0].counties[1].name, "Kings");
+
+        profile.locations[0].postal_address = None;
+        profile.locations[0].geocode = None;
+        fixture().geocode_profile(&mut profile);
+        assert!(profile.locations[0].geocode.is_some());
+    }
+}
@@ -0,0 +1,411 @@
+//! What changed between two crawls, field by field, kept as a history file
+//! that can be searched by company and date.
+
+use crate::data::CompanyProfile;
+use crate::error::Error;
+use crate::normalize::{parse_phone, state_code};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// The field for companies that came or went.
+const COMPANY: &str = "company";
+
+/// A field's values by key. Single values have no key, list entries are
+/// keyed by what identifies them, e.g. a location by its address, and
+/// numbered when that comes more than once.
+type Entries = BTreeMap<Option<String>, Value>;
+type Facet = fn(&CompanyProfile) -> Entries;
+
+/// The fields compared, in the order their changes are listed.
+const FIELDS: &[(&str, Facet)] = &[
+    ("name", |p| single(Some(&p.info.name))),
+    ("website", |p| single(Some(&p.info.website))),
+    ("rating", |p| single(p.info.rating.as_ref())),
+    ("founded", |p| single(p.info.founded.as_ref())),
+    ("size", |p| single(p.info.size.as_ref())),
+    ("annual_vol", |p| single(p.info.annual_vol.as_ref())),
+    ("duns", |p| single(p.info.duns.as_ref())),
+    ("locations", |p| {
+        keyed(p.locations.iter().map(|location| {
+            let phone = phone(&location.phone_number);
+            (location.address.clone(), phone)
+        }))
+    }),
+    ("contacts", |p| {
+        keyed(p.contacts.iter().map(|contact| {
+            let key = match contact.position.trim() {
+                "" => contact.name.clone(),
+                position => format!("{}, {}", contact.name, position),
+            };
+            (key, json!({ "phone": phone(&contact.phone) }))
+        }))
+    }),
+    ("emails", |p| set(p.emails.iter().cloned())),
+    ("categories", |p| set(p.categories.iter().cloned())),
+    ("service_area_counties", |p| {
+        set(p.service_areas.iter().flat_map(|area| {
+            let state = state_code(&area.state_name).unwrap_or(&area.state_name);
+            area.cities
+                .iter()
+                .map(move |county| format!("{}, {}", county, state))
+        }))
+    }),
+    ("qualifications", |p| by_name(&p.qualifications)),
+    ("brands", |p| {
+        set(p.preferred_brands.iter().map(|b| b.name.clone()))
+    }),
+    ("projects", |p| by_name(&p.projects)),
+    ("social_media", |p| set(p.social_media.iter().cloned())),
+    ("logo", |p| single(p.logo.as_ref())),
+    ("story", |p| single(Some(&p.story))),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// One difference between two crawls of a company.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    pub company_id: i32,
+    /// When the newer crawl was taken.
+    pub date: NaiveDate,
+    pub kind: ChangeKind,
+    /// `company` when the whole company came or went, with its name as the
+    /// value.
+    pub field: String,
+    /// Which entry of a list field changed, e.g. the county or the address
+    /// of the location.
+    pub key: Option<String>,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = match self.kind {
+            ChangeKind::Added => "+",
+            ChangeKind::Removed => "-",
+            ChangeKind::Modified => "~",
+        };
+        write!(
+            f,
+            "{} {} {} {}",
+            self.date, self.company_id, sign, self.field
+        )?;
+        if let Some(key) = &self.key {
+            write!(f, " {:?}", key)?;
+        }
+        // Entries of sets are their own key.
+        let shown = |value: &Option<Value>| match (value, &self.key) {
+            (Some(Value::String(value)), Some(key)) if value == key => None,
+            (value, _) => value.clone(),
+        };
+        match (shown(&self.old), shown(&self.new)) {
+            (Some(old), Some(new)) => write!(f, ": {} -> {}", old, new),
+            (Some(value), None) | (None, Some(value)) => write!(f, ": {}", value),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Which changes to read back from the history.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    /// Any company when empty.
+    pub company_ids: Vec<i32>,
+    /// Inclusive.
+    pub since: Option<NaiveDate>,
+    /// Inclusive.
+    pub until: Option<NaiveDate>,
+}
+
+impl HistoryQuery {
+    pub fn matches(&self, change: &Change) -> bool {
+        (self.company_ids.is_empty() || self.company_ids.contains(&change.company_id))
+            && !matches!(self.since, Some(since) if change.date < since)
+            && !matches!(self.until, Some(until) if change.date > until)
+    }
+}
+
+/// The changes from `old` to `new`, two crawls by company id, dated `date`.
+/// Ordered by company id, then field.
+pub fn diff_snapshots(
+    old: &HashMap<i32, CompanyProfile>,
+    new: &HashMap<i32, CompanyProfile>,
+    date: NaiveDate,
+) -> Vec<Change> {
+    let ids: BTreeSet<i32> = old.keys().chain(new.keys()).copied().collect();
+    ids.into_iter()
+        .flat_map(|id| diff_profiles(id, old.get(&id), new.get(&id), date))
+        .collect()
+}
+
+/// The changes to one company, which either crawl may have missed.
+pub fn diff_profiles(
+    company_id: i32,
+    old: Option<&CompanyProfile>,
+    new: Option<&CompanyProfile>,
+    date: NaiveDate,
+) -> Vec<Change> {
+    let change = |kind, field: &str, key: Option<String>, old, new| Change {
+        company_id,
+        date,
+        kind,
+        field: field.to_string(),
+        key,
+        old,
+        new,
+    };
+    let (old, new) = match (old, new) {
+        (Some(old), Some(new)) => (old, new),
+        (None, Some(new)) => {
+            let name = Some(Value::from(new.info.name.clone()));
+            return vec![change(ChangeKind::Added, COMPANY, None, None, name)];
+        }
+        (Some(old), None) => {
+            let name = Some(Value::from(old.info.name.clone()));
+            return vec![change(ChangeKind::Removed, COMPANY, None, name, None)];
+        }
+        (None, None) => return Vec::new(),
+    };
+
+    let mut changes = Vec::new();
+    for (field, facet) in FIELDS {
+        let (mut before, after) = (facet(old), facet(new));
+        for (key, value) in after {
+            match before.remove(&key) {
+                None => changes.push(change(ChangeKind::Added, field, key, None, Some(value))),
+                Some(previous) if previous != value => changes.push(change(
+                    ChangeKind::Modified,
+                    field,
+                    key,
+                    Some(previous),
+                    Some(value),
+                )),
+                Some(_) => {}
+            }
+        }
+        for (key, value) in before {
+            changes.push(change(ChangeKind::Removed, field, key, Some(value), None));
+        }
+    }
+    changes
+}
+
+/// Adds `changes` to the end of the history file, creating it if needed.
+pub fn append_history(path: impl AsRef<Path>, changes: &[Change]) -> Result<(), Error> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut writer = BufWriter::new(file);
+    for change in changes {
+        serde_json::to_writer(&mut writer, change)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The changes in the history file that match `query`, in the order they
+/// were recorded. A missing file just means there weren't any.
+pub fn read_history(path: impl AsRef<Path>, query: &HistoryQuery) -> Result<Vec<Change>, Error> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut changes = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let change: Change = serde_json::from_str(&line)?;
+        if query.matches(&change) {
+            changes.push(change);
+        }
+    }
+    Ok(changes)
+}
+
+/// A value with no key, left out when it is empty.
+fn single(value: Option<&String>) -> Entries {
+    value
+        .filter(|value| !value.trim().is_empty())
+        .map(|value| (None, Value::from(value.as_str())))
+        .into_iter()
+        .collect()
+}
+
+/// Entries that are their own key.
+fn set(values: impl Iterator<Item = String>) -> Entries {
+    values
+        .map(|value| (Some(value.clone()), Value::from(value)))
+        .collect()
+}
+
+/// Entries under the keys given, the second of a key as `key #2` and so
+/// on, so two offices at one address are both kept.
+fn keyed(entries: impl Iterator<Item = (String, Value)>) -> Entries {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    entries
+        .map(|(key, value)| {
+            let count = counts.entry(key.clone()).or_insert(0);
+            *count += 1;
+            let key = match *count {
+                1 => key,
+                n => format!("{} #{}", key, n),
+            };
+            (Some(key), value)
+        })
+        .collect()
+}
+
+/// Records keyed by their `name`, whole records as values.
+fn by_name<T: Serialize>(records: &[T]) -> Entries {
+    keyed(
+        records
+            .iter()
+            .filter_map(|record| serde_json::to_value(record).ok())
+            .map(|value| {
+                let name = value["name"].as_str().unwrap_or_default().to_string();
+                (name, value)
+            }),
+    )
+}
+
+/// A phone number in E.164 where it parses, so a change of formatting
+/// isn't a change of number.
+fn phone(raw: &str) -> Value {
+    match parse_phone(raw) {
+        Some(phone) => Value::from(phone.e164),
+        None => Value::from(raw.trim()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::history::*;
+    use crate::test_support::{fixture_profile, variant};
+
+    fn date(text: &str) -> NaiveDate {
+        text.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_diff_profiles() {
+        let old = fixture_profile().await;
+        let mut new = old.clone();
+        new.locations[0].phone_number = "516-379-1899".to_string();
+        new.categories.push("Solar Panels".to_string());
+        new.service_areas[0]
+            .cities
+            .retain(|county| county != "Richmond");
+        new.info.founded = None;
+
+        let changes = diff_profiles(old.id, Some(&old), Some(&new), date("2026-10-01"));
+        let lines: Vec<String> = changes.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            vec![
+                format!(
+                    "2026-10-01 922369 - founded: {:?}",
+                    old.info.founded.as_ref().unwrap()
+                ),
+                "2026-10-01 922369 ~ locations \"155 Park Ave. Amityville, NY 11701\": \
+                 \"+15163791800\" -> \"+15163791899\""
+                    .to_string(),
+                "2026-10-01 922369 + categories \"Solar Panels\"".to_string(),
+                "2026-10-01 922369 - service_area_counties \"Richmond, NY\"".to_string(),
+            ]
+        );
+        assert_eq!(changes[1].kind, ChangeKind::Modified);
+        assert_eq!(changes[1].field, "locations");
+
+        assert!(diff_profiles(old.id, Some(&old), Some(&old), date("2026-10-01")).is_empty());
+
+        let mut formatted = old.clone();
+        formatted.locations[0].phone_number = "516.379.1800".to_string();
+        assert!(diff_profiles(old.id, Some(&old), Some(&formatted), date("2026-10-01")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_repeated_keys() {
+        let profile = fixture_profile().await;
+        let old = variant(&profile, profile.id, |old| {
+            let mut annex = old.locations[0].clone();
+            annex.phone_number = "(516) 379-1801".to_string();
+            old.locations.push(annex);
+            let mut partner = old.contacts[0].clone();
+            partner.position = "Partner".to_string();
+            old.contacts.push(partner);
+        });
+        let address = &profile.locations[0].address;
+
+        let changes = diff_profiles(old.id, Some(&old), Some(&profile), date("2026-10-01"));
+        let lines: Vec<String> = changes.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            vec![
+                format!(
+                    "2026-10-01 922369 - locations \"{} #2\": \"+15163791801\"",
+                    address
+                ),
+                format!(
+                    "2026-10-01 922369 - contacts \"{}, Partner\": {{\"phone\":{}}}",
+                    profile.contacts[0].name,
+                    phone(&profile.contacts[0].phone)
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_diff_snapshots_and_history() {
+        let temp = tempfile::tempdir().unwrap();
+        let profile = fixture_profile().await;
+        let gone = variant(&profile, 1, |_| ());
+        let old: HashMap<i32, CompanyProfile> = vec![(1, gone)].into_iter().collect();
+        let new: HashMap<i32, CompanyProfile> = vec![(922369, profile)].into_iter().collect();
+
+        let path = temp.path().join("history.jsonl");
+        append_history(&path, &diff_snapshots(&old, &new, date("2026-09-01"))).unwrap();
+        append_history(&path, &diff_snapshots(&new, &new, date("2026-10-01"))).unwrap();
+        append_history(&path, &diff_snapshots(&new, &old, date("2026-11-01"))).unwrap();
+
+        let all = read_history(&path, &HistoryQuery::default()).unwrap();
+        let kinds: Vec<(i32, ChangeKind)> = all.iter().map(|c| (c.company_id, c.kind)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (1, ChangeKind::Removed),
+                (922369, ChangeKind::Added),
+                (1, ChangeKind::Added),
+                (922369, ChangeKind::Removed),
+            ]
+        );
+        assert_eq!(all[1].field, "company");
+        assert_eq!(
+            all[1].new,
+            Some(Value::from("Skyview Air Conditioning & Heating Corp."))
+        );
+
+        let query = HistoryQuery {
+            company_ids: vec![922369],
+            since: Some(date("2026-10-01")),
+            until: None,
+        };
+        let changes = read_history(&path, &query).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].date, date("2026-11-01"));
+    }
+}
@@ -0,0 +1,155 @@
+//! Diagnostics on stderr, one line per event. Every stage of a crawl runs in
+//! a span of its own, `class` → `search_page` → `company` → `division` →
+//! `heading`, and each line names the spans it happened in with their ids,
+//! so `grep company_id=922369` finds everything about one company.
+
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+use tracing::Subscriber;
+use tracing_subscriber::filter::{EnvFilter, ParseError};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Variable with the log filter, when `--log` isn't given.
+pub const LOG_VAR: &str = "BLUEBOOK_LOG";
+const DEFAULT_FILTER: &str = "warn";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// `time LEVEL span{field=value}:span{..}: message field=value`
+    Pretty,
+    /// One JSON object per event, with the fields of every span it's in.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!(
+                "unknown log format {:?}, expected pretty or json",
+                s
+            )),
+        }
+    }
+}
+
+/// Levels like `info` or `warn,bluebook_scraper::parser=debug`, from
+/// `filter` or else [`LOG_VAR`].
+pub fn log_filter(filter: Option<&str>) -> Result<EnvFilter, ParseError> {
+    match filter {
+        Some(filter) => EnvFilter::try_new(filter),
+        None => match std::env::var(LOG_VAR) {
+            Ok(filter) => EnvFilter::try_new(filter),
+            Err(_) => EnvFilter::try_new(DEFAULT_FILTER),
+        },
+    }
+}
+
+fn subscriber<W>(
+    format: LogFormat,
+    filter: EnvFilter,
+    writer: W,
+) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer);
+    match format {
+        // Colors would split `field=value` up for grep.
+        LogFormat::Pretty => Box::new(builder.with_ansi(io::stderr().is_terminal()).finish()),
+        LogFormat::Json => Box::new(
+            builder
+                .json()
+                .with_current_span(false)
+                .with_span_list(true)
+                .finish(),
+        ),
+    }
+}
+
+/// Sends the events of the whole process to stderr.
+pub fn init_logging(format: LogFormat, filter: EnvFilter) {
+    tracing::subscriber::set_global_default(subscriber(format, filter, io::stderr))
+        .expect("Logging is set up once");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logging::*;
+    use crate::test_support::fixture_profile;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_log_filter() {
+        assert!(log_filter(Some("info,bluebook_scraper::api=debug")).is_ok());
+        assert!(log_filter(Some("bluebook_scraper=loud")).is_err());
+        assert_eq!("json".parse(), Ok(LogFormat::Json));
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_company_events_carry_its_id() {
+        let buffer = Buffer::default();
+        let filter = log_filter(Some("bluebook_scraper=debug")).unwrap();
+        let _guard =
+            tracing::subscriber::set_default(subscriber(LogFormat::Json, filter, buffer.clone()));
+        fixture_profile().await;
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let spans = |event: &serde_json::Value| event["spans"].as_array().unwrap().clone();
+        assert!(events
+            .iter()
+            .all(|event| spans(event).iter().any(|span| span["company_id"] == 922369)));
+
+        let categories = events
+            .iter()
+            .find(|event| event["fields"]["message"] == "categories collected")
+            .unwrap();
+        assert_eq!(categories["fields"]["divisions"], 7);
+        assert_eq!(categories["fields"]["categories"], 104);
+        let fetch = events
+            .iter()
+            .find(|event| {
+                spans(event)
+                    .iter()
+                    .any(|span| span["name"] == "heading" && span["head_id"] == "1669")
+            })
+            .unwrap();
+        assert_eq!(fetch["fields"]["status"], 200);
+        assert!(fetch["fields"]["bytes"].as_u64().unwrap() > 0);
+        assert!(fetch["fields"]["url"]
+            .as_str()
+            .unwrap()
+            .contains("proViewCSIClassByDiv"));
+    }
+}
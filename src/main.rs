@@ -1,43 +1,593 @@
-use crate::api::{client_factory, get_search_page};
+use crate::api::{client_factory, Client, Endpoints, SessionConfig};
+use crate::archive::{ArchiveReader, Archiver, DEFAULT_MAX_FILE_SIZE};
+use crate::classes::{read_classes, refresh_classes};
+use crate::cli::{
+    Command, Opt, SinkOpt, EXIT_BLOCKED, EXIT_ERROR, EXIT_OK, EXIT_PARTIAL, EXIT_USAGE,
+};
+use crate::config::{
+    Config, ConfigFile, Settings, DEFAULT_CONFIG_PATH, DEFAULT_GAZETTEER_PATH, NO_PROXY,
+};
+use crate::crawler::{crawl, crawl_queue, scrape_companies, CrawlReport};
 use crate::error::Error;
-use crate::parser::{get_page_ids, get_pages_count};
-use reqwest::Client;
-use serde_json::from_reader;
+use crate::export::{
+    check_columns, export_profiles, write_parquet, write_table, CsvOptions, CsvTable, ExportFormat,
+    ExportOptions, ParquetOptions,
+};
+use crate::gazetteer::{build_gazetteer, Gazetteer};
+use crate::history::{append_history, diff_snapshots, read_history, HistoryQuery};
+use crate::logging::{init_logging, log_filter, LOG_VAR};
+use crate::parser::get_search_suggestions;
+use crate::queue::{JobKind, JobQueue};
+use crate::refresh::{read_state, refresh, state_path, write_state, CompanyState};
+use crate::reparse::reparse;
+use crate::resolve::{read_decisions, resolve, DEFAULT_THRESHOLD};
+use crate::search::{page_ids, page_summaries, search_pages, SearchMode};
+use crate::sink::{compact_profiles, read_profiles, stream_profiles, JsonLinesSink};
+use crate::stats::profile_stats;
+use chrono::{Duration, Utc};
+use reqwest::Url;
+use std::collections::HashSet;
 use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use structopt::clap::ErrorKind;
+use structopt::StructOpt;
+use tracing::warn;
 
 mod api;
+mod archive;
+mod classes;
+mod cli;
+mod config;
+mod crawler;
 mod data;
 mod error;
+mod export;
+mod gazetteer;
+mod history;
+mod logging;
+#[cfg(test)]
+mod mock;
+mod normalize;
 mod parser;
-
-const DEFAULT_CITY: &str = "New York, NY";
+mod queue;
+mod refresh;
+mod reparse;
+mod resolve;
+mod search;
+mod sink;
+mod stats;
+#[cfg(test)]
+mod test_support;
 
 #[tokio::main]
 async fn main() {
-    let client = client_factory().await;
+    let opt = Opt::from_iter_safe(std::env::args_os()).unwrap_or_else(|e| match e.kind {
+        ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => e.exit(),
+        _ => {
+            eprintln!("{}", e.message);
+            process::exit(EXIT_USAGE);
+        }
+    });
+    match log_filter(opt.log.as_deref()) {
+        Ok(filter) => init_logging(opt.log_format, filter),
+        Err(e) => exit_usage(&format!(
+            "invalid log filter in --log or ${}: {}",
+            LOG_VAR, e
+        )),
+    }
+    match run(opt).await {
+        Ok(code) => process::exit(code),
+        Err(e @ Error::Config(_)) => {
+            eprintln!("{}", e.report());
+            process::exit(EXIT_USAGE);
+        }
+        Err(e) => {
+            eprintln!("{}", e.report());
+            process::exit(EXIT_ERROR);
+        }
+    }
 }
 
-async fn get_companies_ids_from_class(client: Client, class_id: i32) -> Result<Vec<i32>, Error> {
-    let page = get_search_page(&client, class_id, 1, DEFAULT_CITY).await?;
-    let pages_count = get_pages_count(&page).await?;
-    let first_page_ids = get_page_ids(&page).await?;
+/// Defaults, then the config file and profile, then `BLUEBOOK_*` variables,
+/// then flags.
+fn load_config(opt: &Opt) -> Result<Config, Error> {
+    let path = opt
+        .config
+        .clone()
+        .or_else(|| std::env::var_os("BLUEBOOK_CONFIG").map(Into::into));
+    let file = match &path {
+        Some(path) => ConfigFile::load(path, true)?,
+        None => ConfigFile::load(Path::new(DEFAULT_CONFIG_PATH), false)?,
+    };
+    let profile = opt
+        .profile
+        .clone()
+        .or_else(|| std::env::var("BLUEBOOK_PROFILE").ok());
+    let env = Settings::from_env(|name| std::env::var(name).ok())?;
+    let flags = Settings {
+        proxy: if opt.direct {
+            Some(NO_PROXY.to_string())
+        } else {
+            opt.proxy.clone()
+        },
+        header_profile: opt.header_profile.clone(),
+        header_seed: opt.header_seed,
+        cookie_jar: opt.cookie_jar.clone(),
+        concurrency: opt.concurrency,
+        base_url: opt.base_url.as_ref().map(Url::to_string),
+        https: if opt.https { Some(true) } else { None },
+        ..Settings::default()
+    };
+    Ok(Config::resolve(&file, profile.as_deref(), env, flags)?)
+}
 
-    unimplemented!()
+async fn build_client(opt: &Opt, config: &Config) -> Result<Client, Error> {
+    let mut endpoints = Endpoints::default();
+    if let Some(url) = &config.base_url {
+        endpoints = Endpoints::for_base(url).expect("A parsed URL is a valid base");
+    }
+    if let Some(host) = &opt.host {
+        endpoints = endpoints
+            .with_host(host)
+            .unwrap_or_else(|e| exit_usage(&format!("invalid host {:?}: {}", host, e)));
+    }
+    if config.https {
+        endpoints = endpoints.with_https();
+    }
+
+    let mut client = match &opt.fixtures {
+        Some(dir) => Client::fixtures(dir),
+        None => {
+            let home = endpoints.home();
+            let session = SessionConfig {
+                warm_up: config
+                    .warm_up
+                    .iter()
+                    .map(|page| home.join(page).expect("Validated with the config"))
+                    .collect(),
+                cookie_jar: config.cookie_jar.clone(),
+                max_idle: Some(chrono::Duration::minutes(
+                    config.session_idle_minutes as i64,
+                )),
+            };
+            client_factory(config.proxy(), config.browser.clone(), session).await?
+        }
+    };
+    client = client.with_endpoints(endpoints);
+
+    if let Some(dir) = &opt.archive {
+        let archiver = Archiver::open(dir, DEFAULT_MAX_FILE_SIZE)?;
+        client = client.with_archiver(Arc::new(archiver));
+    }
+    Ok(client)
 }
 
-async fn read_classes() -> Result<Vec<i32>, Error> {
-    let file = File::open("classes.json")?;
-    let classes: Vec<i32> = from_reader(file)?;
-    Ok(classes)
+fn exit_usage(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(EXIT_USAGE);
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::*;
+/// Cities given on the command line, or the configured ones.
+fn cities<'a>(cities: &'a [String], config: &'a Config) -> Vec<&'a str> {
+    if cities.is_empty() {
+        config.cities.iter().map(String::as_str).collect()
+    } else {
+        cities.iter().map(String::as_str).collect()
+    }
+}
+
+/// Geocodes what `sink` writes when a gazetteer is configured.
+fn with_gazetteer(sink: JsonLinesSink, config: &Config) -> Result<JsonLinesSink, Error> {
+    Ok(match &config.gazetteer {
+        Some(path) => sink.with_gazetteer(Gazetteer::load(path)?),
+        None => {
+            warn!(
+                path = DEFAULT_GAZETTEER_PATH,
+                "no gazetteer, profiles are not geocoded; write one with build-gazetteer"
+            );
+            sink
+        }
+    })
+}
+
+/// The sink and the companies it already has, when resuming. The effective
+/// config is written next to a file sink so the run can be repeated.
+fn open_sink(sink: &SinkOpt, config: &Config) -> Result<(JsonLinesSink, HashSet<i32>), Error> {
+    if sink.output == "-" {
+        return Ok((
+            with_gazetteer(JsonLinesSink::stdout(), config)?,
+            HashSet::new(),
+        ));
+    }
+    let (output, done) = if sink.resume {
+        let done = read_profiles(&sink.output)?.into_keys().collect();
+        (JsonLinesSink::append(&sink.output)?, done)
+    } else {
+        (JsonLinesSink::create(&sink.output)?, HashSet::new())
+    };
+    let output = with_gazetteer(output, config)?;
+    std::fs::write(format!("{}.config.toml", sink.output), config.to_toml())?;
+    Ok((output, done))
+}
+
+fn crawl_exit_code(report: &CrawlReport) -> i32 {
+    if report.blocked.is_some() {
+        EXIT_BLOCKED
+    } else if !report.is_complete() {
+        EXIT_PARTIAL
+    } else {
+        EXIT_OK
+    }
+}
+
+async fn run(opt: Opt) -> Result<i32, Error> {
+    let config = load_config(&opt)?;
+    if !opt.command.uses_network() {
+        return run_local(&opt, &config).await;
+    }
+    let client = build_client(&opt, &config).await?;
+
+    match &opt.command {
+        Command::Crawl {
+            classes,
+            cities: city_names,
+            queue,
+            fresh,
+            sink,
+        } => {
+            let class_ids = if classes.is_empty() {
+                read_classes(&config.classes_path)
+                    .await?
+                    .iter()
+                    .map(|class| class.id)
+                    .collect()
+            } else {
+                classes.clone()
+            };
+            let report = match queue {
+                Some(dir) => {
+                    // The queue knows which companies are done, so the
+                    // output must keep them.
+                    let sink = SinkOpt {
+                        output: sink.output.clone(),
+                        resume: true,
+                    };
+                    let (mut output, mut done) = open_sink(&sink, &config)?;
+                    let mut queue = JobQueue::open(dir)?;
+                    if *fresh {
+                        queue.start_generation();
+                        done.clear();
+                    }
+                    for &class_id in &class_ids {
+                        for city in cities(city_names, &config) {
+                            let kind = JobKind::SearchPage {
+                                class_id,
+                                city: city.to_string(),
+                                page_number: 1,
+                            };
+                            queue.push(kind, None, Utc::now())?;
+                        }
+                    }
+                    let report =
+                        crawl_queue(&client, &mut queue, &done, config.concurrency, &mut output)
+                            .await?;
+                    output.finish()?;
+                    eprintln!("{}", queue.counts());
+                    report
+                }
+                None => {
+                    let (mut output, done) = open_sink(sink, &config)?;
+                    let report = crawl(
+                        &client,
+                        &class_ids,
+                        &cities(city_names, &config),
+                        &done,
+                        config.concurrency,
+                        &mut output,
+                    )
+                    .await?;
+                    output.finish()?;
+                    report
+                }
+            };
+            eprintln!("{}", report);
+            Ok(crawl_exit_code(&report))
+        }
+        Command::Refresh {
+            classes,
+            cities: city_names,
+            output,
+        } => {
+            let class_ids = if classes.is_empty() {
+                read_classes(&config.classes_path)
+                    .await?
+                    .iter()
+                    .map(|class| class.id)
+                    .collect()
+            } else {
+                classes.clone()
+            };
+            if output == "-" {
+                exit_usage("refresh needs an output file to keep its state next to");
+            }
+            let state_path = state_path(output);
+            let mut states = read_state(&state_path)?;
+            for id in stream_profiles(output)?.company_ids() {
+                states.entry(id).or_insert_with(|| CompanyState::new(id));
+            }
+            let mut sink = with_gazetteer(JsonLinesSink::append(output)?, &config)?;
+            let report = refresh(
+                &client,
+                &class_ids,
+                &cities(city_names, &config),
+                &mut states,
+                Duration::days(config.refresh_ttl_days as i64),
+                config.concurrency,
+                &mut sink,
+            )
+            .await?;
+            sink.finish()?;
+            // Re-scraped companies were appended, only their last lines count.
+            compact_profiles(output)?;
+            write_state(&state_path, &states)?;
+            eprintln!("{}", report);
+            Ok(crawl_exit_code(&report.crawl))
+        }
+        Command::Company { ids, sink } => {
+            let (mut output, done) = open_sink(sink, &config)?;
+            let ids: Vec<i32> = ids
+                .iter()
+                .copied()
+                .filter(|id| !done.contains(id))
+                .collect();
+            let report = scrape_companies(&client, &ids, config.concurrency, &mut output).await?;
+            output.finish()?;
+            eprintln!("{}", report);
+            Ok(crawl_exit_code(&report))
+        }
+        Command::Search {
+            class,
+            ids,
+            within,
+            term,
+            cities: city_names,
+        } => {
+            let cities = cities(city_names, &config);
+            let mode = if *class {
+                SearchMode::Class(
+                    term.parse()
+                        .unwrap_or_else(|_| exit_usage("class id must be a number")),
+                )
+            } else {
+                SearchMode::Keyword {
+                    term,
+                    within: within.as_deref(),
+                }
+            };
+
+            let pages = search_pages(&client, mode, &cities, config.concurrency).await?;
+            if *ids {
+                for id in page_ids(&pages).await? {
+                    println!("{}", id);
+                }
+            } else {
+                for summary in page_summaries(&pages).await? {
+                    println!("{}", serde_json::to_string(&summary)?);
+                }
+            }
+
+            if let (SearchMode::Keyword { .. }, Some(first_page)) = (mode, pages.first()) {
+                for class in get_search_suggestions(first_page).await? {
+                    eprintln!(
+                        "Did you mean class {:04} {}?",
+                        class.id,
+                        class.name.unwrap_or_default()
+                    );
+                }
+            }
+            Ok(EXIT_OK)
+        }
+        Command::Classes => {
+            let diff = refresh_classes(&client, &config.classes_path).await?;
+            println!("{}", diff);
+            Ok(EXIT_OK)
+        }
+        _ => unreachable!("local commands run without a client"),
+    }
+}
 
-    #[tokio::test(threaded_scheduler)]
-    async fn test_read_classes() {
-        let classes = read_classes().await.unwrap();
-        assert_eq!(classes.len(), 612)
+/// Commands that only work on local files, without a client and so without
+/// a session to start or save.
+async fn run_local(opt: &Opt, config: &Config) -> Result<i32, Error> {
+    match &opt.command {
+        Command::DeadLetters {
+            queue,
+            requeue,
+            ids,
+        } => {
+            let mut queue = JobQueue::open(queue)?;
+            if *requeue {
+                let requeued = queue.requeue(ids, Utc::now())?;
+                eprintln!("{} jobs requeued", requeued);
+            } else {
+                for job in queue.dead() {
+                    if ids.is_empty() || ids.contains(&job.id) {
+                        println!("{}", job);
+                    }
+                }
+                eprintln!("{}", queue.counts());
+            }
+            Ok(EXIT_OK)
+        }
+        Command::Export {
+            input,
+            format,
+            output,
+            list_delimiter,
+            columns,
+            tables,
+            row_group_size,
+            compression,
+        } => {
+            let options = ExportOptions {
+                csv: CsvOptions {
+                    list_delimiter: list_delimiter.clone(),
+                    columns: columns.clone(),
+                },
+                parquet: ParquetOptions {
+                    row_group_size: *row_group_size,
+                    compression: *compression,
+                },
+            };
+            check_columns(&options.csv.columns).unwrap_or_else(|e| exit_usage(&e));
+            if *tables && (*format != ExportFormat::Csv || output == "-") {
+                exit_usage("--tables needs --format csv and an output file");
+            }
+            if *row_group_size == 0 {
+                exit_usage("--row-group-size must be at least 1");
+            }
+            // Parquet is streamed, the other formats need all profiles at once.
+            if *format == ExportFormat::Parquet {
+                let profiles = stream_profiles(input)?;
+                if output == "-" {
+                    write_parquet(profiles, &options.parquet, io::stdout())?;
+                } else {
+                    write_parquet(profiles, &options.parquet, File::create(output)?)?;
+                }
+                return Ok(EXIT_OK);
+            }
+            let mut profiles: Vec<_> = read_profiles(input)?.into_values().collect();
+            profiles.sort_by_key(|profile| profile.id);
+            if output == "-" {
+                export_profiles(&profiles, *format, &options, &mut io::stdout())?;
+            } else {
+                let mut file = BufWriter::new(File::create(output)?);
+                export_profiles(&profiles, *format, &options, &mut file)?;
+            }
+            if *tables {
+                let stem = output.strip_suffix(".csv").unwrap_or(output);
+                for table in CsvTable::ALL.iter() {
+                    let path = format!("{}.{}.csv", stem, table.name());
+                    let mut file = BufWriter::new(File::create(path)?);
+                    write_table(&profiles, *table, &mut file)?;
+                }
+            }
+            Ok(EXIT_OK)
+        }
+        Command::BuildGazetteer {
+            counties,
+            zctas,
+            relationships,
+            output,
+        } => {
+            let text = build_gazetteer(counties, zctas, relationships)?;
+            std::fs::write(output, text)?;
+            eprintln!("gazetteer written to {}", output.display());
+            Ok(EXIT_OK)
+        }
+        Command::Stats { input } => {
+            let profiles = read_profiles(input)?;
+            print!("{}", profile_stats(profiles.values()));
+            Ok(EXIT_OK)
+        }
+        Command::Resolve {
+            input,
+            decisions,
+            threshold,
+            output,
+            pairs,
+        } => {
+            let mut profiles: Vec<_> = read_profiles(input)?.into_values().collect();
+            profiles.sort_by_key(|profile| profile.id);
+            let threshold = threshold.unwrap_or(DEFAULT_THRESHOLD);
+            let resolution = resolve(&profiles, &read_decisions(decisions)?, threshold);
+            let mut writer: Box<dyn Write> = if output == "-" {
+                Box::new(io::stdout())
+            } else {
+                Box::new(BufWriter::new(File::create(output)?))
+            };
+            for assignment in &resolution.assignments {
+                serde_json::to_writer(&mut writer, assignment)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+            if let Some(path) = pairs {
+                let mut file = BufWriter::new(File::create(path)?);
+                for pair in &resolution.pairs {
+                    serde_json::to_writer(&mut file, pair)?;
+                    file.write_all(b"\n")?;
+                }
+                file.flush()?;
+            }
+            eprintln!("{}", resolution);
+            Ok(EXIT_OK)
+        }
+        Command::Diff {
+            old,
+            new,
+            date,
+            history,
+            dry_run,
+        } => {
+            let date = date.unwrap_or_else(|| Utc::now().date_naive());
+            let changes = diff_snapshots(&read_profiles(old)?, &read_profiles(new)?, date);
+            for change in &changes {
+                println!("{}", change);
+            }
+            if !*dry_run {
+                append_history(history, &changes)?;
+            }
+            eprintln!("{} changes", changes.len());
+            Ok(EXIT_OK)
+        }
+        Command::History {
+            history,
+            companies,
+            since,
+            until,
+            json,
+        } => {
+            let query = HistoryQuery {
+                company_ids: companies.clone(),
+                since: *since,
+                until: *until,
+            };
+            let stdout = io::stdout();
+            let mut writer = stdout.lock();
+            for change in read_history(history, &query)? {
+                if *json {
+                    serde_json::to_writer(&mut writer, &change)?;
+                    writer.write_all(b"\n")?;
+                } else {
+                    writeln!(writer, "{}", change)?;
+                }
+            }
+            Ok(EXIT_OK)
+        }
+        Command::Reparse {
+            archive_dir,
+            ids,
+            output,
+        } => {
+            let reader = ArchiveReader::open(archive_dir)?;
+            let previous = read_profiles(output)?;
+            let mut sink = with_gazetteer(JsonLinesSink::create(output)?, config)?;
+            let report = reparse(Arc::new(reader), ids, &previous, &mut sink).await?;
+            sink.finish()?;
+            println!("{}", report);
+            Ok(if report.failed.is_empty() {
+                EXIT_OK
+            } else {
+                EXIT_PARTIAL
+            })
+        }
+        Command::Config => {
+            print!("{}", config.to_toml());
+            Ok(EXIT_OK)
+        }
+        _ => unreachable!("network commands need a client"),
     }
 }
@@ -0,0 +1,307 @@
+use crate::api::fixture_names;
+use crate::api::{
+    PageRequest, CATEGORIES_SEARCH_PATH, COMPANY_PAGE_PATH, COMPANY_SEARCH_PATH,
+    SEARCH_CLASSES_PATH,
+};
+use futures::channel::oneshot;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use reqwest::Url;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const CAPTCHA_PAGE: &str = "<html><head><title>Just a moment...</title></head>\
+                            <body><div class=\"g-recaptcha\"></div></body></html>";
+
+/// Something to go wrong with a response instead of, or before, serving it.
+#[derive(Debug, Clone)]
+pub enum Fault {
+    /// Wait before answering normally.
+    Delay(Duration),
+    /// Answer with this status and an empty page.
+    Status(u16),
+    /// `429 Too Many Requests` with a `Retry-After` in seconds.
+    RateLimit { retry_after: u64 },
+    /// A captcha interstitial served with `200 OK`.
+    Captcha,
+    /// `302 Found` to another path, e.g. the login page.
+    Redirect(String),
+}
+
+#[derive(Default)]
+struct State {
+    /// Faults waiting for a request whose path starts with the prefix, in
+    /// order.
+    faults: Vec<(String, Fault)>,
+    /// Bodies that take precedence over files, by fixture name.
+    templates: HashMap<String, String>,
+    /// `Set-Cookie` headers for every response whose path starts with the
+    /// prefix.
+    cookies: Vec<(String, String)>,
+    /// Paths requested, their query strings and the `Cookie` header sent
+    /// with them.
+    hits: Vec<(String, String, Option<String>)>,
+}
+
+/// An in-process imitation of the site for tests: serves `test-data/`
+/// fixtures on localhost and can be told to misbehave. Stops when dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockServer {
+    /// Starts serving `test-data/` on a free port of localhost.
+    pub async fn start() -> MockServer {
+        let fixtures = PathBuf::from("test-data");
+        let state = Arc::new(Mutex::new(State::default()));
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            let fixtures = fixtures.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle(state.clone(), fixtures.clone(), request)
+                }))
+            }
+        });
+
+        let (shutdown, stop) = oneshot::channel::<()>();
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            stop.await.ok();
+        }));
+
+        MockServer {
+            addr,
+            state,
+            shutdown: Some(shutdown),
+        }
+    }
+
+    pub fn base_url(&self) -> Url {
+        Url::parse(&format!("http://{}/", self.addr)).unwrap()
+    }
+
+    /// Applies `fault` to the next request whose path starts with `prefix`,
+    /// e.g. `/iProView/`.
+    pub fn push_fault(&self, prefix: &str, fault: Fault) {
+        let mut state = self.state.lock().unwrap();
+        state.faults.push((prefix.to_string(), fault));
+    }
+
+    /// Serves `body` in place of the fixture file `name`, with
+    /// `{{company_id}}` replaced by the id the page was requested for.
+    pub fn template(&self, name: &str, body: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.templates.insert(name.to_string(), body.to_string());
+    }
+
+    /// Sets a cookie with every response whose path starts with `prefix`.
+    pub fn set_cookie(&self, prefix: &str, set_cookie: &str) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .cookies
+            .push((prefix.to_string(), set_cookie.to_string()));
+    }
+
+    /// How many requests had a path starting with `prefix`.
+    pub fn hits(&self, prefix: &str) -> usize {
+        self.cookies_sent(prefix).len()
+    }
+
+    /// The `Cookie` header of each request whose path starts with `prefix`.
+    pub fn cookies_sent(&self, prefix: &str) -> Vec<Option<String>> {
+        let state = self.state.lock().unwrap();
+        state
+            .hits
+            .iter()
+            .filter(|(path, _, _)| path.starts_with(prefix))
+            .map(|(_, _, cookie)| cookie.clone())
+            .collect()
+    }
+
+    /// The query string of each request whose path starts with `prefix`.
+    pub fn queries(&self, prefix: &str) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .hits
+            .iter()
+            .filter(|(path, _, _)| path.starts_with(prefix))
+            .map(|(_, query, _)| query.clone())
+            .collect()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+async fn handle(
+    state: Arc<Mutex<State>>,
+    fixtures: PathBuf,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let path = request.uri().path().to_string();
+    let mut response = respond(state.clone(), fixtures, request).await;
+    let state = state.lock().unwrap();
+    for (prefix, cookie) in &state.cookies {
+        if path.starts_with(prefix.as_str()) {
+            response
+                .headers_mut()
+                .append("Set-Cookie", cookie.parse().unwrap());
+        }
+    }
+    Ok(response)
+}
+
+async fn respond(
+    state: Arc<Mutex<State>>,
+    fixtures: PathBuf,
+    request: Request<Body>,
+) -> Response<Body> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let query = request.uri().query().unwrap_or("").to_string();
+    let cookie = request
+        .headers()
+        .get("Cookie")
+        .and_then(|cookie| cookie.to_str().ok())
+        .map(str::to_string);
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .unwrap_or_default();
+
+    let fault = {
+        let mut state = state.lock().unwrap();
+        state.hits.push((path.clone(), query.clone(), cookie));
+        let position = state
+            .faults
+            .iter()
+            .position(|(prefix, _)| path.starts_with(prefix.as_str()));
+        position.map(|position| state.faults.remove(position).1)
+    };
+    match fault {
+        Some(Fault::Delay(delay)) => tokio::time::delay_for(delay).await,
+        Some(Fault::Status(status)) => return status_page(status),
+        Some(Fault::RateLimit { retry_after }) => {
+            let mut response = status_page(429);
+            response
+                .headers_mut()
+                .insert("Retry-After", retry_after.into());
+            return response;
+        }
+        Some(Fault::Captcha) => return Response::new(Body::from(CAPTCHA_PAGE)),
+        Some(Fault::Redirect(location)) => {
+            let mut response = status_page(302);
+            response
+                .headers_mut()
+                .insert("Location", location.parse().unwrap());
+            return response;
+        }
+        None => (),
+    }
+
+    let page = match page_request(&method, &path, &query, &body) {
+        Some(page) => page,
+        None => return status_page(404),
+    };
+
+    let names = fixture_names(&page);
+    let template = {
+        let state = state.lock().unwrap();
+        names
+            .iter()
+            .find_map(|name| state.templates.get(name).cloned())
+    };
+    let content = match template {
+        Some(template) => template.replace(
+            "{{company_id}}",
+            &page
+                .company_id()
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+        ),
+        None => {
+            let file = names
+                .iter()
+                .map(|name| fixtures.join(name))
+                .find(|path| path.is_file());
+            match file.map(std::fs::read_to_string) {
+                Some(Ok(content)) => content,
+                _ => return status_page(404),
+            }
+        }
+    };
+    Response::new(Body::from(content))
+}
+
+fn status_page(status: u16) -> Response<Body> {
+    let mut response = Response::new(Body::from("<html><body></body></html>"));
+    *response.status_mut() = StatusCode::from_u16(status).unwrap();
+    response
+}
+
+/// Works out which request the `api` layer made, the reverse of
+/// `PageRequest::prepare`.
+fn page_request(method: &Method, path: &str, query: &str, body: &[u8]) -> Option<PageRequest> {
+    let path = path.trim_start_matches('/');
+    let params = |raw: &[u8]| -> HashMap<String, String> {
+        url::form_urlencoded::parse(raw).into_owned().collect()
+    };
+
+    if *method == Method::POST && path == CATEGORIES_SEARCH_PATH {
+        let form = params(body);
+        return Some(PageRequest::Categories {
+            company_id: form.get("qp")?.parse().ok()?,
+            div_id: form.get("id")?.clone(),
+            head_id: form.get("headingID").cloned(),
+        });
+    }
+    if *method != Method::GET {
+        return None;
+    }
+
+    if path == SEARCH_CLASSES_PATH {
+        return Some(PageRequest::Classes);
+    }
+    if path == COMPANY_SEARCH_PATH {
+        let query = params(query.as_bytes());
+        let page_number = query.get("page")?.parse().ok()?;
+        let city = query.get("regionLabel").cloned().unwrap_or_default();
+        return Some(match query.get("searchTerm") {
+            Some(term) => PageRequest::KeywordSearch {
+                term: term.clone(),
+                within: query.get("pSeachinp").cloned(),
+                page_number,
+                city,
+            },
+            None => PageRequest::Search {
+                class_id: query.get("class")?.parse().ok()?,
+                page_number,
+                city,
+            },
+        });
+    }
+
+    let mut segments = path
+        .strip_prefix(COMPANY_PAGE_PATH)?
+        .split('/')
+        .filter(|segment| !segment.is_empty());
+    let id = segments.next()?.parse().ok()?;
+    match (segments.next(), segments.next()) {
+        (None, _) => Some(PageRequest::Company(id)),
+        (Some("locations-contacts"), None) => Some(PageRequest::Locations(id)),
+        _ => None,
+    }
+}
@@ -0,0 +1,432 @@
+use crate::data::PostalAddress;
+
+/// USPS codes of the states, DC and the territories, with their names.
+pub const US_STATES: &[(&str, &str)] = &[
+    ("AL", "Alabama"),
+    ("AK", "Alaska"),
+    ("AZ", "Arizona"),
+    ("AR", "Arkansas"),
+    ("CA", "California"),
+    ("CO", "Colorado"),
+    ("CT", "Connecticut"),
+    ("DE", "Delaware"),
+    ("DC", "District of Columbia"),
+    ("FL", "Florida"),
+    ("GA", "Georgia"),
+    ("HI", "Hawaii"),
+    ("ID", "Idaho"),
+    ("IL", "Illinois"),
+    ("IN", "Indiana"),
+    ("IA", "Iowa"),
+    ("KS", "Kansas"),
+    ("KY", "Kentucky"),
+    ("LA", "Louisiana"),
+    ("ME", "Maine"),
+    ("MD", "Maryland"),
+    ("MA", "Massachusetts"),
+    ("MI", "Michigan"),
+    ("MN", "Minnesota"),
+    ("MS", "Mississippi"),
+    ("MO", "Missouri"),
+    ("MT", "Montana"),
+    ("NE", "Nebraska"),
+    ("NV", "Nevada"),
+    ("NH", "New Hampshire"),
+    ("NJ", "New Jersey"),
+    ("NM", "New Mexico"),
+    ("NY", "New York"),
+    ("NC", "North Carolina"),
+    ("ND", "North Dakota"),
+    ("OH", "Ohio"),
+    ("OK", "Oklahoma"),
+    ("OR", "Oregon"),
+    ("PA", "Pennsylvania"),
+    ("RI", "Rhode Island"),
+    ("SC", "South Carolina"),
+    ("SD", "South Dakota"),
+    ("TN", "Tennessee"),
+    ("TX", "Texas"),
+    ("UT", "Utah"),
+    ("VT", "Vermont"),
+    ("VA", "Virginia"),
+    ("WA", "Washington"),
+    ("WV", "West Virginia"),
+    ("WI", "Wisconsin"),
+    ("WY", "Wyoming"),
+    ("AS", "American Samoa"),
+    ("GU", "Guam"),
+    ("MP", "Northern Mariana Islands"),
+    ("PR", "Puerto Rico"),
+    ("VI", "Virgin Islands"),
+];
+
+/// Words that end the street part of an address, as written or
+/// abbreviated, without a trailing period.
+const STREET_SUFFIXES: &[&str] = &[
+    "alley",
+    "aly",
+    "avenue",
+    "ave",
+    "av",
+    "boulevard",
+    "blvd",
+    "broadway",
+    "causeway",
+    "circle",
+    "cir",
+    "court",
+    "ct",
+    "crossing",
+    "xing",
+    "drive",
+    "dr",
+    "expressway",
+    "expy",
+    "freeway",
+    "fwy",
+    "highway",
+    "hwy",
+    "lane",
+    "ln",
+    "loop",
+    "parkway",
+    "pkwy",
+    "pike",
+    "place",
+    "pl",
+    "plaza",
+    "plz",
+    "road",
+    "rd",
+    "square",
+    "sq",
+    "street",
+    "st",
+    "terrace",
+    "ter",
+    "trail",
+    "trl",
+    "turnpike",
+    "tpke",
+    "way",
+];
+const DIRECTIONS: &[&str] = &[
+    "n", "s", "e", "w", "ne", "nw", "se", "sw", "north", "south", "east", "west",
+];
+const UNIT_DESIGNATORS: &[&str] = &[
+    "suite",
+    "ste",
+    "unit",
+    "apt",
+    "apartment",
+    "room",
+    "rm",
+    "floor",
+    "fl",
+    "bldg",
+    "building",
+    "dept",
+    "#",
+];
+const COUNTRY_NAMES: &[&str] = &["usa", "us", "united states", "united states of america"];
+
+/// The USPS code of a state, from its code or its name in any case.
+pub fn state_code(state: &str) -> Option<&'static str> {
+    let state = state.trim().trim_end_matches('.');
+    US_STATES
+        .iter()
+        .find(|(code, name)| code.eq_ignore_ascii_case(state) || name.eq_ignore_ascii_case(state))
+        .map(|(code, _)| *code)
+}
+
+/// Parses a US address as the site displays it, e.g. "155 Park Ave.
+/// Amityville, NY 11701", with line breaks as `\n` where the page has them.
+/// `None` unless at least the state or the ZIP code can be made out.
+pub fn parse_address(text: &str) -> Option<PostalAddress> {
+    let mut address = PostalAddress {
+        original: text.to_string(),
+        ..PostalAddress::default()
+    };
+    let mut lines: Vec<String> = text
+        .lines()
+        .map(|line| line.trim().trim_matches(',').trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect();
+    if let Some(last) = lines.last() {
+        let last = last.to_lowercase();
+        if COUNTRY_NAMES.contains(&last.trim_end_matches('.')) {
+            lines.pop();
+        }
+    }
+
+    // The state and ZIP code end the last line, after the city.
+    let last = lines.pop()?;
+    let mut words: Vec<&str> = last.split_whitespace().collect();
+    while let Some(word) = words.last() {
+        let country = word.trim_end_matches('.').to_lowercase();
+        if COUNTRY_NAMES.contains(&country.as_str()) {
+            words.pop();
+        } else {
+            break;
+        }
+    }
+    if let Some((zip, zip4)) = words.last().and_then(|word| split_zip(word)) {
+        address.zip = Some(zip);
+        address.zip4 = zip4;
+        words.pop();
+    }
+    // State names are up to four words long, "District of Columbia".
+    for length in (1..=4.min(words.len())).rev() {
+        let candidate = words[words.len() - length..].join(" ");
+        if let Some(code) = state_code(candidate.trim_matches(',')) {
+            address.state = Some(code.to_string());
+            words.truncate(words.len() - length);
+            break;
+        }
+    }
+    if address.state.is_none() && address.zip.is_none() {
+        return None;
+    }
+
+    let rest = words.join(" ");
+    let rest = rest.trim().trim_end_matches(',').trim();
+    if lines.is_empty() {
+        let (street, city) = split_street(rest);
+        address.city = city;
+        if let Some(street) = street {
+            let (street, suite) = split_suite(&street);
+            address.street = Some(street);
+            address.suite = suite;
+        }
+    } else {
+        address.city = Some(rest.to_string()).filter(|city| !city.is_empty());
+        let (street, suite) = split_suite(&lines.join(", "));
+        address.street = Some(street);
+        address.suite = suite;
+    }
+    Some(address)
+}
+
+/// An address from separate fields, like the microdata of search results.
+pub fn address_from_parts(
+    locality: Option<&str>,
+    region: Option<&str>,
+    postal_code: Option<&str>,
+) -> Option<PostalAddress> {
+    let state = region.and_then(state_code);
+    let zip = postal_code.and_then(split_zip);
+    if state.is_none() && zip.is_none() {
+        return None;
+    }
+    let original: Vec<&str> = vec![locality, region, postal_code]
+        .into_iter()
+        .flatten()
+        .collect();
+    let (zip, zip4) = match zip {
+        Some((zip, zip4)) => (Some(zip), zip4),
+        None => (None, None),
+    };
+    Some(PostalAddress {
+        city: locality.map(str::to_string),
+        state: state.map(str::to_string),
+        zip,
+        zip4,
+        original: original.join(", "),
+        ..PostalAddress::default()
+    })
+}
+
+/// "11701" or "11701-1234", also written without the dash.
+fn split_zip(word: &str) -> Option<(String, Option<String>)> {
+    let digits: String = word.chars().filter(|c| *c != '-').collect();
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    match digits.len() {
+        5 => Some((digits, None)),
+        9 => Some((digits[..5].to_string(), Some(digits[5..].to_string()))),
+        _ => None,
+    }
+}
+
+/// Splits "155 Park Ave. Suite 3 Amityville" after the street suffix and any
+/// direction or unit that follows it, or at the last comma when there is no
+/// suffix. Without either, text starting with a number is all street and
+/// anything else all city.
+fn split_street(text: &str) -> (Option<String>, Option<String>) {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let bare = |word: &str| word.trim_end_matches(&['.', ','][..]).to_lowercase();
+
+    let is_suffix = |word: &str| STREET_SUFFIXES.contains(&bare(word).as_str());
+    // The first word is the house number, the suffix has a name before it.
+    // Suffixes may follow each other, "Park Place Drive", but "St" after one
+    // is more likely a Saint starting the city.
+    let suffix = (2..words.len())
+        .find(|index| is_suffix(words[*index]))
+        .map(|mut index| {
+            while index + 1 < words.len()
+                && is_suffix(words[index + 1])
+                && bare(words[index + 1]) != "st"
+            {
+                index += 1;
+            }
+            index
+        });
+    let post_office_box = words
+        .windows(2)
+        .position(|pair| bare(pair[0]) == "box" && pair[1].chars().all(|c| c.is_ascii_digit()))
+        .map(|index| index + 1);
+
+    let end = match suffix.or(post_office_box) {
+        Some(index) => {
+            let mut end = index + 1;
+            if matches!(words.get(end), Some(w) if DIRECTIONS.contains(&bare(w).as_str())) {
+                end += 1;
+            }
+            if let Some(word) = words.get(end) {
+                let word = bare(word);
+                if word.starts_with('#') && word.len() > 1 {
+                    end += 1;
+                } else if UNIT_DESIGNATORS.contains(&word.as_str()) && end + 1 < words.len() {
+                    end += 2;
+                }
+            }
+            end
+        }
+        None => {
+            if let Some(comma) = text.rfind(',') {
+                let city = text[comma + 1..].trim();
+                return (
+                    Some(text[..comma].trim().to_string()),
+                    Some(city.to_string()).filter(|city| !city.is_empty()),
+                );
+            }
+            return if text.starts_with(|c: char| c.is_ascii_digit()) {
+                (Some(text.to_string()), None)
+            } else {
+                (None, Some(text.to_string()).filter(|city| !city.is_empty()))
+            };
+        }
+    };
+
+    let street = words[..end].join(" ").trim_end_matches(',').to_string();
+    let city = words[end..].join(" ").trim_matches(',').trim().to_string();
+    (Some(street), Some(city).filter(|city| !city.is_empty()))
+}
+
+/// Splits a trailing unit like "Suite 200" or "#4B" off the street.
+fn split_suite(street: &str) -> (String, Option<String>) {
+    let words: Vec<&str> = street.split_whitespace().collect();
+    let bare = |word: &str| word.trim_end_matches(&['.', ','][..]).to_lowercase();
+    let unit_start = match words.len() {
+        0 | 1 => None,
+        n if words[n - 1].starts_with('#') && words[n - 1].len() > 1 => Some(n - 1),
+        n if UNIT_DESIGNATORS.contains(&bare(words[n - 2]).as_str()) && n > 2 => Some(n - 2),
+        _ => None,
+    };
+    match unit_start {
+        Some(start) => (
+            words[..start].join(" ").trim_end_matches(',').to_string(),
+            Some(words[start..].join(" ")),
+        ),
+        None => (words.join(" "), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::normalize::address::*;
+
+    fn parts(text: &str) -> [Option<String>; 6] {
+        let address = parse_address(text).unwrap();
+        assert_eq!(address.original, text);
+        [
+            address.street,
+            address.suite,
+            address.city,
+            address.state,
+            address.zip,
+            address.zip4,
+        ]
+    }
+
+    fn some(values: [&str; 6]) -> [Option<String>; 6] {
+        let mut parts: [Option<String>; 6] = Default::default();
+        for (part, value) in parts.iter_mut().zip(values.iter()) {
+            if !value.is_empty() {
+                *part = Some(value.to_string());
+            }
+        }
+        parts
+    }
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(
+            parts("155 Park Ave. Amityville, NY 11701"),
+            some(["155 Park Ave.", "", "Amityville", "NY", "11701", ""])
+        );
+        assert_eq!(
+            parts("155 Park Ave.\nAmityville, NY 11701"),
+            some(["155 Park Ave.", "", "Amityville", "NY", "11701", ""])
+        );
+        assert_eq!(
+            parts("40 Wall St Suite 1200 New York, New York 10005-1304"),
+            some([
+                "40 Wall St",
+                "Suite 1200",
+                "New York",
+                "NY",
+                "10005",
+                "1304"
+            ])
+        );
+        assert_eq!(
+            parts("2200 Main Street N #4B Lake Oswego OR 97035"),
+            some([
+                "2200 Main Street N",
+                "#4B",
+                "Lake Oswego",
+                "OR",
+                "97035",
+                ""
+            ])
+        );
+        assert_eq!(
+            parts("P.O. Box 512 Newburgh, NY 12550 USA"),
+            some(["P.O. Box 512", "", "Newburgh", "NY", "12550", ""])
+        );
+        assert_eq!(
+            parts("17 Industrial Park, Ste. 3, Ronkonkoma, NY 11779"),
+            some([
+                "17 Industrial Park",
+                "Ste. 3",
+                "Ronkonkoma",
+                "NY",
+                "11779",
+                ""
+            ])
+        );
+        assert_eq!(
+            parts("100 Park Place Drive St. Louis, MO 63101"),
+            some(["100 Park Place Drive", "", "St. Louis", "MO", "63101", ""])
+        );
+        assert_eq!(
+            parts("Washington, District of Columbia 20001"),
+            some(["", "", "Washington", "DC", "20001", ""])
+        );
+
+        assert_eq!(parse_address(""), None);
+        assert_eq!(parse_address("Call for directions"), None);
+    }
+
+    #[test]
+    fn test_address_from_parts() {
+        let address = address_from_parts(Some("Portland"), Some("OR"), Some("97229")).unwrap();
+        assert_eq!(address.city.as_deref(), Some("Portland"));
+        assert_eq!(address.state.as_deref(), Some("OR"));
+        assert_eq!(address.zip.as_deref(), Some("97229"));
+        assert_eq!(address.original, "Portland, OR, 97229");
+        assert_eq!(address_from_parts(Some("Portland"), None, None), None);
+    }
+}
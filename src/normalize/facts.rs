@@ -0,0 +1,256 @@
+use crate::data::{CompanyFacts, CompanyInfo, CurrencyRange, EmployeeRange, Rating};
+use crate::normalize::website::canonical_website;
+use chrono::{Datelike, Utc};
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Words that make a single number an upper bound, and whether the bound is
+/// included.
+const UPPER_BOUNDS: &[(&str, bool)] = &[
+    ("up to", true),
+    ("under", false),
+    ("less than", false),
+    ("fewer than", false),
+    ("below", false),
+];
+/// Words that make a single number a lower bound.
+const LOWER_BOUNDS: &[&str] = &["+", "over", "more than", "or more", "above", "plus"];
+const MULTIPLIERS: &[(&str, f64)] = &[
+    ("thousand", 1e3),
+    ("k", 1e3),
+    ("million", 1e6),
+    ("mil", 1e6),
+    ("mm", 1e6),
+    ("m", 1e6),
+    ("billion", 1e9),
+    ("bn", 1e9),
+    ("b", 1e9),
+];
+const RATING_SCALE: f64 = 5.0;
+
+static YEAR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b(1[6-9]\d{2}|20\d{2})\b").unwrap());
+static NUMBER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\d+").unwrap());
+static AMOUNT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(\d+(?:\.\d+)?)\s*(thousand|million|billion|mil|mm|bn|k|m|b)?\b").unwrap()
+});
+static REVIEWS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\d+)\s*(?:reviews?|ratings?)").unwrap());
+static SCORE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\d+(?:\.\d+)?)(?:\s*(?:/|out of)\s*(\d+(?:\.\d+)?))?").unwrap());
+
+/// The typed values of the facts a profile shows as text.
+pub fn company_facts(info: &CompanyInfo) -> CompanyFacts {
+    CompanyFacts {
+        founded: info.founded.as_deref().and_then(parse_year),
+        employees: info.size.as_deref().and_then(parse_employees),
+        annual_volume: info.annual_vol.as_deref().and_then(parse_annual_volume),
+        duns: info.duns.as_deref().and_then(parse_duns),
+        rating: info.rating.as_deref().and_then(parse_rating),
+        website: canonical_website(&info.website),
+    }
+}
+
+/// A four digit year, like "1986" or "Est. 1986", that isn't in the future.
+pub fn parse_year(raw: &str) -> Option<i32> {
+    let year: i32 = YEAR.captures(raw)?[1].parse().ok()?;
+    if year <= Utc::now().year() {
+        Some(year)
+    } else {
+        None
+    }
+}
+
+/// An employee band, like "1-4 Employees", "10 to 19" or "500+ Employees".
+pub fn parse_employees(raw: &str) -> Option<EmployeeRange> {
+    let text = raw.to_lowercase().replace(',', "");
+    let numbers: Vec<u32> = NUMBER
+        .find_iter(&text)
+        .map(|m| m.as_str().parse())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let (min, max) = bounds(&text, &numbers)?;
+    Some(EmployeeRange { min, max })
+}
+
+/// An annual volume band in US dollars, like "$1-5 Million", "$500K - $1M"
+/// or "Over $100 Million". `None` for "Undisclosed" and the like.
+pub fn parse_annual_volume(raw: &str) -> Option<CurrencyRange> {
+    let text = raw.to_lowercase().replace(',', "");
+    let mut amounts: Vec<(f64, Option<f64>)> = Vec::new();
+    for captures in AMOUNT.captures_iter(&text) {
+        let number: f64 = captures[1].parse().ok()?;
+        let multiplier = captures.get(2).and_then(|word| {
+            MULTIPLIERS
+                .iter()
+                .find(|(name, _)| *name == word.as_str())
+                .map(|(_, multiplier)| *multiplier)
+        });
+        amounts.push((number, multiplier));
+    }
+    // "$1-5 Million": the first number takes the second one's unit.
+    if let [(low, None), (high, Some(multiplier))] = amounts[..] {
+        if low < high {
+            amounts[0].1 = Some(multiplier);
+        }
+    }
+    let dollars: Vec<u64> = amounts
+        .iter()
+        .map(|(number, multiplier)| (number * multiplier.unwrap_or(1.0)).round() as u64)
+        .collect();
+    let (min, max) = bounds(&text, &dollars)?;
+    Some(CurrencyRange {
+        currency: "USD".to_string(),
+        min,
+        max,
+    })
+}
+
+/// A DUNS number, nine digits with or without dashes.
+pub fn parse_duns(raw: &str) -> Option<String> {
+    let digits: String = raw
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' ' | '.'))
+        .collect();
+    if digits.len() == 9 && digits.chars().all(|c| c.is_ascii_digit()) {
+        Some(digits)
+    } else {
+        None
+    }
+}
+
+/// A rating like "4.5", "4.5/5" or "4.5 out of 5 (12 reviews)". Scores
+/// without a scale are out of five stars.
+pub fn parse_rating(raw: &str) -> Option<Rating> {
+    let text = raw.to_lowercase().replace(',', "");
+    let reviews = REVIEWS
+        .captures(&text)
+        .and_then(|captures| captures[1].parse().ok());
+    let text = REVIEWS.replace_all(&text, "");
+
+    let captures = SCORE.captures(&text)?;
+    let score: f64 = captures[1].parse().ok()?;
+    let scale = match captures.get(2) {
+        Some(scale) => scale.as_str().parse().ok()?,
+        None => RATING_SCALE,
+    };
+    if scale > 0.0 && score <= scale {
+        Some(Rating {
+            score,
+            scale,
+            reviews,
+        })
+    } else {
+        None
+    }
+}
+
+/// The range `numbers` make in `text`: two numbers are both ends, one is an
+/// end if words in the text say so and the only value otherwise.
+fn bounds<T>(text: &str, numbers: &[T]) -> Option<(T, Option<T>)>
+where
+    T: Copy + PartialOrd + Default + std::ops::Sub<Output = T> + From<u8>,
+{
+    match *numbers {
+        [min, max, ..] if min <= max => Some((min, Some(max))),
+        [_, _, ..] => None,
+        [value] => {
+            let upper = UPPER_BOUNDS.iter().find(|(words, _)| text.contains(words));
+            match upper {
+                Some((_, true)) => Some((T::default(), Some(value))),
+                Some((_, false)) if value > T::default() => {
+                    Some((T::default(), Some(value - T::from(1))))
+                }
+                Some((_, false)) => None,
+                None if LOWER_BOUNDS.iter().any(|words| text.contains(words)) => {
+                    Some((value, None))
+                }
+                None => Some((value, Some(value))),
+            }
+        }
+        [] => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::normalize::facts::*;
+
+    #[test]
+    fn test_parse_employees() {
+        let range = |min, max| Some(EmployeeRange { min, max });
+        assert_eq!(parse_employees("1-4 Employees"), range(1, Some(4)));
+        assert_eq!(parse_employees("1 - 4\n\tEmployees"), range(1, Some(4)));
+        assert_eq!(parse_employees("10 to 19"), range(10, Some(19)));
+        assert_eq!(parse_employees("1,000-4,999"), range(1000, Some(4999)));
+        assert_eq!(parse_employees("500+ Employees"), range(500, None));
+        assert_eq!(parse_employees("Over 1000"), range(1000, None));
+        assert_eq!(parse_employees("Less than 10"), range(0, Some(9)));
+        assert_eq!(parse_employees("12"), range(12, Some(12)));
+        assert_eq!(parse_employees("20-10"), None);
+        assert_eq!(parse_employees("Undisclosed"), None);
+    }
+
+    #[test]
+    fn test_parse_annual_volume() {
+        let usd = |min, max| {
+            Some(CurrencyRange {
+                currency: "USD".to_string(),
+                min,
+                max,
+            })
+        };
+        assert_eq!(
+            parse_annual_volume("$1-5 Million"),
+            usd(1_000_000, Some(5_000_000))
+        );
+        assert_eq!(
+            parse_annual_volume("$500K - $1M"),
+            usd(500_000, Some(1_000_000))
+        );
+        assert_eq!(
+            parse_annual_volume("$500,000 - $1 Million"),
+            usd(500_000, Some(1_000_000))
+        );
+        assert_eq!(
+            parse_annual_volume("$2.5 - 10 Million"),
+            usd(2_500_000, Some(10_000_000))
+        );
+        assert_eq!(
+            parse_annual_volume("Over $100 Million"),
+            usd(100_000_000, None)
+        );
+        assert_eq!(
+            parse_annual_volume("Under $1 Million"),
+            usd(0, Some(999_999))
+        );
+        assert_eq!(parse_annual_volume("Undisclosed"), None);
+        assert_eq!(parse_annual_volume(""), None);
+    }
+
+    #[test]
+    fn test_parse_other_facts() {
+        assert_eq!(parse_year("1986"), Some(1986));
+        assert_eq!(parse_year("Est. 1986"), Some(1986));
+        assert_eq!(parse_year("2099"), None);
+        assert_eq!(parse_year("86"), None);
+
+        assert_eq!(parse_duns("12-345-6789").as_deref(), Some("123456789"));
+        assert_eq!(parse_duns("123456789").as_deref(), Some("123456789"));
+        assert_eq!(parse_duns("1234567"), None);
+        assert_eq!(parse_duns("12345678X"), None);
+
+        assert_eq!(
+            parse_rating("4.5 out of 5 (12 reviews)"),
+            Some(Rating {
+                score: 4.5,
+                scale: 5.0,
+                reviews: Some(12),
+            })
+        );
+        assert_eq!(parse_rating("8/10").unwrap().scale, 10.0);
+        assert_eq!(parse_rating("4").unwrap().scale, 5.0);
+        assert_eq!(parse_rating("7 out of 5"), None);
+        assert_eq!(parse_rating("Not rated"), None);
+    }
+}
@@ -0,0 +1,12 @@
+//! Canonical forms of the free text the site displays, for matching records
+//! against each other and against other systems.
+
+pub use crate::normalize::address::{address_from_parts, parse_address, state_code};
+pub use crate::normalize::facts::company_facts;
+pub use crate::normalize::phone::{parse_phone, shared_numbers};
+pub use crate::normalize::website::{canonical_website, company_domain};
+
+mod address;
+mod facts;
+mod phone;
+mod website;
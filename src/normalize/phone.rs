@@ -0,0 +1,247 @@
+use crate::data::{CompanyProfile, PhoneKind, PhoneNumber};
+
+const TOLL_FREE_AREAS: &[&str] = &["800", "833", "844", "855", "866", "877", "888"];
+const PREMIUM_AREA: &str = "900";
+/// Longest from "extension", "ext", "x" and "#" first, so "ext" isn't taken
+/// for a stray "x".
+const EXTENSION_MARKERS: &[&str] = &["extension", "ext", "x", "#"];
+const MAX_EXTENSION_DIGITS: usize = 6;
+/// What US callers dial before a country code.
+const US_EXIT_CODE: &str = "011";
+
+/// Parses a displayed phone number, like "(516) 379-1800 ext. 12" or
+/// "1-800-FLOWERS". Numbers without a country code are taken to be North
+/// American. `None` if the text isn't a valid number.
+pub fn parse_phone(raw: &str) -> Option<PhoneNumber> {
+    let (number, extension) = split_extension(raw);
+    // Labels like "Tel:" or "Main" come before the first digit.
+    let start = number.find(|c: char| c.is_ascii_digit() || c == '+')?;
+    let number = &number[start..];
+
+    let digits = keypad_digits(number)?;
+    if number.starts_with('+') || digits.starts_with(US_EXIT_CODE) {
+        let digits = digits.strip_prefix(US_EXIT_CODE).unwrap_or(&digits);
+        return match digits.strip_prefix('1') {
+            Some(national) => north_american(national, extension),
+            None if (8..=15).contains(&digits.len()) => Some(PhoneNumber {
+                e164: format!("+{}", digits),
+                extension,
+                kind: PhoneKind::International,
+            }),
+            None => None,
+        };
+    }
+    match digits.len() {
+        10 => north_american(&digits, extension),
+        11 => north_american(digits.strip_prefix('1')?, extension),
+        _ => None,
+    }
+}
+
+/// A ten digit North American number, if the area code and exchange are
+/// possible ones.
+fn north_american(national: &str, extension: Option<String>) -> Option<PhoneNumber> {
+    let valid_start = |part: &str| matches!(part.as_bytes().first(), Some(b'2'..=b'9'));
+    if national.len() != 10 || !valid_start(&national[..3]) || !valid_start(&national[3..6]) {
+        return None;
+    }
+
+    let area = &national[..3];
+    let kind = if TOLL_FREE_AREAS.contains(&area) {
+        PhoneKind::TollFree
+    } else if area == PREMIUM_AREA {
+        PhoneKind::Premium
+    } else {
+        PhoneKind::Geographic
+    };
+    Some(PhoneNumber {
+        e164: format!("+1{}", national),
+        extension,
+        kind,
+    })
+}
+
+/// Splits off a trailing extension: a marker that doesn't end a word, like
+/// the "x" of "Fax", followed by nothing but its digits.
+fn split_extension(raw: &str) -> (&str, Option<String>) {
+    let lower = raw.to_ascii_lowercase();
+    for (index, _) in lower.char_indices() {
+        let after_word = lower[..index]
+            .chars()
+            .last()
+            .filter(char::is_ascii_alphabetic)
+            .is_some();
+        if after_word {
+            continue;
+        }
+        for marker in EXTENSION_MARKERS {
+            if let Some(rest) = lower[index..].strip_prefix(marker) {
+                let digits = rest.trim_start_matches(&['.', ':', ' '][..]);
+                let digits = digits.trim_end();
+                if !digits.is_empty()
+                    && digits.len() <= MAX_EXTENSION_DIGITS
+                    && digits.chars().all(|c| c.is_ascii_digit())
+                {
+                    let number = raw[..index].trim_end_matches(&[',', ' '][..]);
+                    return (number, Some(digits.to_string()));
+                }
+            }
+        }
+    }
+    (raw, None)
+}
+
+/// The digits of a number, with the letters of vanity numbers turned into
+/// the digits they are on a keypad. `None` for anything else in the text.
+fn keypad_digits(number: &str) -> Option<String> {
+    let mut digits = String::new();
+    for c in number.chars() {
+        let digit = match c.to_ascii_uppercase() {
+            '0'..='9' => c,
+            'A'..='C' => '2',
+            'D'..='F' => '3',
+            'G'..='I' => '4',
+            'J'..='L' => '5',
+            'M'..='O' => '6',
+            'P'..='S' => '7',
+            'T'..='V' => '8',
+            'W'..='Z' => '9',
+            ' ' | '-' | '.' | '(' | ')' | '/' | '+' => continue,
+            _ => return None,
+        };
+        digits.push(digit);
+    }
+    Some(digits)
+}
+
+/// A number listed both for locations and for contacts of one company,
+/// usually a contact reached through the main line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedNumber {
+    pub e164: String,
+    /// Indices into the profile's locations.
+    pub locations: Vec<usize>,
+    /// Indices into the profile's contacts.
+    pub contacts: Vec<usize>,
+}
+
+/// Numbers that appear in both the locations and the contacts of `profile`,
+/// ignoring extensions. Profiles stored before numbers were normalized are
+/// parsed on the fly.
+pub fn shared_numbers(profile: &CompanyProfile) -> Vec<SharedNumber> {
+    let e164 = |normalized: &Option<PhoneNumber>, raw: &str| {
+        normalized
+            .clone()
+            .or_else(|| parse_phone(raw))
+            .map(|phone| phone.e164)
+    };
+    let location_numbers: Vec<Option<String>> = profile
+        .locations
+        .iter()
+        .map(|location| e164(&location.normalized_phone, &location.phone_number))
+        .collect();
+    let contact_numbers: Vec<Option<String>> = profile
+        .contacts
+        .iter()
+        .map(|contact| e164(&contact.normalized_phone, &contact.phone))
+        .collect();
+
+    let mut shared: Vec<SharedNumber> = Vec::new();
+    for (index, number) in location_numbers.iter().enumerate() {
+        let number = match number {
+            Some(number) => number,
+            None => continue,
+        };
+        if shared.iter().any(|s| &s.e164 == number) {
+            continue;
+        }
+        let contacts: Vec<usize> = contact_numbers
+            .iter()
+            .enumerate()
+            .filter(|(_, contact)| contact.as_ref() == Some(number))
+            .map(|(index, _)| index)
+            .collect();
+        if contacts.is_empty() {
+            continue;
+        }
+        let locations = location_numbers
+            .iter()
+            .enumerate()
+            .skip(index)
+            .filter(|(_, location)| location.as_ref() == Some(number))
+            .map(|(index, _)| index)
+            .collect();
+        shared.push(SharedNumber {
+            e164: number.clone(),
+            locations,
+            contacts,
+        });
+    }
+    shared
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::normalize::phone::*;
+    use crate::test_support::fixture_profile;
+
+    fn e164(raw: &str) -> Option<(String, Option<String>, PhoneKind)> {
+        parse_phone(raw).map(|phone| (phone.e164, phone.extension, phone.kind))
+    }
+
+    #[test]
+    fn test_parse_phone() {
+        let geographic = |extension: Option<&str>| {
+            Some((
+                "+15163791800".to_string(),
+                extension.map(str::to_string),
+                PhoneKind::Geographic,
+            ))
+        };
+        assert_eq!(e164("(516) 379-1800"), geographic(None));
+        assert_eq!(e164("516.379.1800"), geographic(None));
+        assert_eq!(e164("+1 516-379-1800"), geographic(None));
+        assert_eq!(e164("1 (516) 379-1800"), geographic(None));
+        assert_eq!(e164("Tel: 516-379-1800"), geographic(None));
+        assert_eq!(e164("(516) 379-1800 ext. 204"), geographic(Some("204")));
+        assert_eq!(e164("(516) 379-1800 x12"), geographic(Some("12")));
+        assert_eq!(e164("516-379-1800, Extension 7"), geographic(Some("7")));
+        assert_eq!(e164("516-379-1800 #3"), geographic(Some("3")));
+
+        assert_eq!(
+            e164("1-800-FLOWERS"),
+            Some(("+18003569377".to_string(), None, PhoneKind::TollFree))
+        );
+        assert_eq!(e164("(888) 555-0123").unwrap().2, PhoneKind::TollFree);
+        assert_eq!(e164("900-555-0123").unwrap().2, PhoneKind::Premium);
+        assert_eq!(
+            e164("+44 20 7946 0958"),
+            Some(("+442079460958".to_string(), None, PhoneKind::International))
+        );
+        assert_eq!(e164("011 44 20 7946 0958").unwrap().0, "+442079460958");
+
+        assert_eq!(e164(""), None);
+        assert_eq!(e164("379-1800"), None);
+        assert_eq!(e164("(116) 379-1800"), None);
+        assert_eq!(e164("(516) 079-1800"), None);
+        assert_eq!(e164("Call us!"), None);
+    }
+
+    #[tokio::test]
+    async fn test_shared_numbers() {
+        let mut profile = fixture_profile().await;
+        for contact in &mut profile.contacts {
+            contact.phone = "(212) 555-0100".to_string();
+            contact.normalized_phone = None;
+        }
+        assert!(shared_numbers(&profile).is_empty());
+
+        let main_line = profile.locations[0].phone_number.clone();
+        profile.contacts[0].phone = format!("{} ext. 5", main_line);
+        profile.contacts[0].normalized_phone = None;
+        let shared = shared_numbers(&profile);
+        assert_eq!(shared.len(), 1);
+        assert_eq!(shared[0].locations, vec![0]);
+        assert_eq!(shared[0].contacts, vec![0]);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::data::{CompanyInfo, LinkIssue, Website};
+use url::{Host, Url};
+
+/// Hosts, without `www.`, whose links only lead elsewhere: the path of the
+/// redirect and the parameter the real target is passed in.
+const REDIRECTORS: &[(&str, &str, &str)] = &[
+    ("google.com", "/url", "q"),
+    ("google.com", "/url", "url"),
+    ("l.facebook.com", "/l.php", "u"),
+    ("lm.facebook.com", "/l.php", "u"),
+    // The site's own outbound links, `/wsnsa.dll/WService=wsbrk1/out.html`.
+    (SITE_DOMAIN, "/", "url"),
+];
+/// Redirects wrapped in redirects, at most.
+const MAX_UNWRAP: usize = 5;
+/// Dropped from canonical URLs, along with anything starting with `utm_`.
+const TRACKING_PARAMS: &[&str] = &["gclid", "fbclid", "msclkid", "mc_cid", "mc_eid", "_ga"];
+/// Public suffixes with more than one label that companies here register
+/// under. Anything else is taken to be a single label, like `com`.
+const MULTI_LABEL_SUFFIXES: &[&str] = &[
+    "co.uk", "org.uk", "ac.uk", "gov.uk", "com.au", "net.au", "org.au", "co.nz", "co.jp", "com.br",
+    "com.mx", "co.in", "com.cn", "co.za", "co.il", "com.sg",
+];
+const PLACEHOLDER_DOMAINS: &[&str] = &[
+    "example.com",
+    "example.org",
+    "example.net",
+    "test.com",
+    "domain.com",
+    "yourdomain.com",
+    "yoursite.com",
+    "website.com",
+    "none.com",
+];
+const SITE_DOMAIN: &str = "thebluebook.com";
+/// Sites many companies have pages on, so their domain says nothing about
+/// which company it is.
+const SHARED_HOSTS: &[&str] = &[
+    "facebook.com",
+    "linkedin.com",
+    "instagram.com",
+    "twitter.com",
+    "x.com",
+    "youtube.com",
+    "yelp.com",
+    "google.com",
+    "business.site",
+    "wixsite.com",
+    "weebly.com",
+    "godaddysites.com",
+    "squarespace.com",
+    "blogspot.com",
+    "wordpress.com",
+    "houzz.com",
+    "bbb.org",
+    "yellowpages.com",
+    "manta.com",
+];
+
+/// The canonical form of a website link: redirects unwrapped, `https`, the
+/// host lowercase without `www.`, no tracking parameters, fragment or
+/// trailing slash. `None` for an empty link.
+pub fn canonical_website(raw: &str) -> Option<Website> {
+    let raw = raw.trim();
+    if raw.is_empty() || raw == "#" {
+        return None;
+    }
+    let malformed = || Website {
+        url: raw.to_string(),
+        domain: None,
+        issue: Some(LinkIssue::Malformed),
+    };
+
+    let mut url = match parse_link(raw) {
+        Some(url) => url,
+        None => return Some(malformed()),
+    };
+    for _ in 0..MAX_UNWRAP {
+        match redirect_target(&url) {
+            Some(target) => url = target,
+            None => break,
+        }
+    }
+
+    if url.set_scheme("https").is_err() {
+        return Some(malformed());
+    }
+    let host = match url.host() {
+        Some(Host::Domain(domain)) => Host::Domain(strip_www(domain).to_string()),
+        Some(Host::Ipv4(ip)) => Host::Ipv4(ip),
+        Some(Host::Ipv6(ip)) => Host::Ipv6(ip),
+        None => return Some(malformed()),
+    };
+    let host_text = host.to_string();
+    if url.set_host(Some(&host_text)).is_err() {
+        return Some(malformed());
+    }
+    let _ = url.set_port(None);
+    url.set_fragment(None);
+    let query: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| !is_tracking_param(name))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    if query.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(query);
+    }
+    let path = url.path().to_string();
+    if path.len() > 1 && path.ends_with('/') {
+        url.set_path(path.trim_end_matches('/'));
+    }
+
+    let (domain, issue) = match host {
+        Host::Domain(host) => {
+            let domain = registrable_domain(&host);
+            let issue = domain_issue(&host, &domain);
+            (domain, issue)
+        }
+        Host::Ipv4(ip) if ip.is_private() || ip.is_loopback() || ip.is_unspecified() => {
+            (ip.to_string(), Some(LinkIssue::Placeholder))
+        }
+        ip => (ip.to_string(), None),
+    };
+    Some(Website {
+        url: url.to_string(),
+        domain: Some(domain),
+        issue,
+    })
+}
+
+/// The domain that identifies the company of `info`, unless its link has an
+/// issue. Profiles stored before links were canonicalized are handled on
+/// the fly.
+pub fn company_domain(info: &CompanyInfo) -> Option<String> {
+    let website = match &info.facts.website {
+        Some(website) => Some(website.clone()),
+        None => canonical_website(&info.website),
+    };
+    website
+        .filter(|website| website.issue.is_none())
+        .and_then(|website| website.domain)
+}
+
+/// The part of `host` a company registers, e.g. `skyviewac.com` for
+/// `shop.skyviewac.com` or `acme.co.uk` for `www.acme.co.uk`.
+pub fn registrable_domain(host: &str) -> String {
+    let host = host.trim_end_matches('.').to_lowercase();
+    let labels: Vec<&str> = host.split('.').collect();
+    let suffix_labels = if labels.len() > 2
+        && MULTI_LABEL_SUFFIXES.contains(&labels[labels.len() - 2..].join(".").as_str())
+    {
+        2
+    } else {
+        1
+    };
+    let start = labels.len().saturating_sub(suffix_labels + 1);
+    labels[start..].join(".")
+}
+
+/// Links are written with or without a scheme, and sometimes as `//host`.
+fn parse_link(raw: &str) -> Option<Url> {
+    let lower = raw.to_lowercase();
+    let url = if lower.starts_with("http://") || lower.starts_with("https://") {
+        Url::parse(raw)
+    } else if let Some(rest) = raw.strip_prefix("//") {
+        Url::parse(&format!("http://{}", rest))
+    } else if raw.contains("://") || raw.contains(' ') {
+        return None;
+    } else {
+        Url::parse(&format!("http://{}", raw))
+    };
+    // "mailto:x@y.com" would pass for a user and a password.
+    url.ok()
+        .filter(|url| url.host().is_some() && url.username().is_empty())
+}
+
+/// Where a link of a known redirector leads, if it passes a link on. Other
+/// sites' parameters are left alone, `?url=` may be anything to them.
+fn redirect_target(url: &Url) -> Option<Url> {
+    let host = strip_www(url.host_str()?).to_lowercase();
+    let params: Vec<&str> = REDIRECTORS
+        .iter()
+        .filter(|(redirector, path, _)| host == *redirector && url.path().starts_with(path))
+        .map(|(_, _, param)| *param)
+        .collect();
+    url.query_pairs()
+        .filter(|(name, _)| params.contains(&name.as_ref()))
+        .find_map(|(_, value)| {
+            let lower = value.to_lowercase();
+            let is_link = lower.starts_with("http://")
+                || lower.starts_with("https://")
+                || lower.starts_with("//")
+                || lower.starts_with("www.");
+            if is_link {
+                parse_link(&value)
+            } else {
+                None
+            }
+        })
+}
+
+fn strip_www(host: &str) -> &str {
+    match host.strip_prefix("www") {
+        Some(rest) => {
+            let rest = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+            match rest.strip_prefix('.') {
+                Some(domain) if domain.contains('.') => domain,
+                _ => host,
+            }
+        }
+        None => host,
+    }
+}
+
+fn is_tracking_param(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.starts_with("utm_") || TRACKING_PARAMS.contains(&name.as_str())
+}
+
+fn domain_issue(host: &str, domain: &str) -> Option<LinkIssue> {
+    if !host.contains('.') || host == "localhost" || PLACEHOLDER_DOMAINS.contains(&domain) {
+        Some(LinkIssue::Placeholder)
+    } else if domain == SITE_DOMAIN {
+        Some(LinkIssue::SiteLink)
+    } else if SHARED_HOSTS.contains(&domain) {
+        Some(LinkIssue::SharedHost)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::normalize::website::*;
+
+    fn canonical(raw: &str) -> (String, Option<String>, Option<LinkIssue>) {
+        let website = canonical_website(raw).unwrap();
+        (website.url, website.domain, website.issue)
+    }
+
+    #[test]
+    fn test_canonical_website() {
+        let skyview = || {
+            (
+                "https://skyviewac.com/".to_string(),
+                Some("skyviewac.com".to_string()),
+                None,
+            )
+        };
+        assert_eq!(canonical("https://www.skyviewac.com"), skyview());
+        assert_eq!(canonical("http://WWW.SkyviewAC.com/"), skyview());
+        assert_eq!(canonical("www.skyviewac.com"), skyview());
+        assert_eq!(canonical("//skyviewac.com:80/#top"), skyview());
+        assert_eq!(canonical("skyviewac.com/?utm_source=bluebook"), skyview());
+        assert_eq!(
+            canonical("https://www.thebluebook.com/wsnsa.dll/WService=wsbrk1/out.html?url=http%3A%2F%2Fwww.skyviewac.com%2F"),
+            skyview()
+        );
+        assert_eq!(
+            canonical("https://www.google.com/url?q=https://l.facebook.com/l.php?u%3Dhttp%253A%252F%252Fskyviewac.com"),
+            skyview()
+        );
+
+        assert_eq!(
+            canonical("http://shop.acme.co.uk/products/?id=4&gclid=x"),
+            (
+                "https://shop.acme.co.uk/products?id=4".to_string(),
+                Some("acme.co.uk".to_string()),
+                None
+            )
+        );
+        assert_eq!(
+            canonical("http://www2.ajw.com").1.as_deref(),
+            Some("ajw.com")
+        );
+        assert_eq!(canonical("http://www.com").1.as_deref(), Some("www.com"));
+    }
+
+    #[test]
+    fn test_only_redirectors_are_unwrapped() {
+        assert_eq!(
+            canonical("https://l.facebook.com/l.php?u=https%3A%2F%2Fskyviewac.com%2F&h=AT0").0,
+            "https://skyviewac.com/"
+        );
+        assert_eq!(
+            canonical("https://skyviewac.com/share?url=https://www.facebook.com/skyviewac").0,
+            "https://skyviewac.com/share?url=https%3A%2F%2Fwww.facebook.com%2Fskyviewac"
+        );
+        assert_eq!(
+            canonical("http://www.acme.com/?to=http://partner.com")
+                .1
+                .as_deref(),
+            Some("acme.com")
+        );
+        assert_eq!(
+            canonical("https://www.google.com/maps?q=https://skyviewac.com")
+                .1
+                .as_deref(),
+            Some("google.com")
+        );
+    }
+
+    #[test]
+    fn test_link_issues() {
+        assert_eq!(canonical_website(""), None);
+        assert_eq!(canonical_website("#"), None);
+        assert_eq!(canonical("n/a").2, Some(LinkIssue::Placeholder));
+        assert_eq!(canonical("http://none").2, Some(LinkIssue::Placeholder));
+        assert_eq!(canonical("www.example.com").2, Some(LinkIssue::Placeholder));
+        assert_eq!(
+            canonical("http://192.168.1.10/").2,
+            Some(LinkIssue::Placeholder)
+        );
+        assert_eq!(
+            canonical("//www.thebluebook.com/iProView/922369/").2,
+            Some(LinkIssue::SiteLink)
+        );
+        assert_eq!(
+            canonical("https://www.facebook.com/skyviewac/"),
+            (
+                "https://facebook.com/skyviewac".to_string(),
+                Some("facebook.com".to_string()),
+                Some(LinkIssue::SharedHost)
+            )
+        );
+        assert_eq!(
+            canonical("skyview ac dot com"),
+            (
+                "skyview ac dot com".to_string(),
+                None,
+                Some(LinkIssue::Malformed)
+            )
+        );
+        assert_eq!(
+            canonical("mailto:info@skyviewac.com").2,
+            Some(LinkIssue::Malformed)
+        );
+    }
+}
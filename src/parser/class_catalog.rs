@@ -0,0 +1,93 @@
+use crate::data::ClassEntry;
+use crate::parser::Error;
+use regex::Regex;
+use select::document::Document;
+use select::predicate::{Name, Predicate};
+use std::collections::HashSet;
+
+const PARSER: &str = "class_catalog";
+
+pub async fn get_classes(page: &Document) -> Result<Vec<ClassEntry>, Error> {
+    let class_regex = Regex::new(r"[?&]class=(\d+)").unwrap();
+    let mut classes = Vec::new();
+    let mut seen = HashSet::new();
+    let mut group = None;
+
+    // Nodes come in document order, so every class link belongs to the
+    // closest heading above it.
+    for node in page.find(Name("h2").or(Name("h3")).or(Name("h4")).or(Name("a"))) {
+        if node.name() != Some("a") {
+            group = Some(node.text().trim().to_string());
+            continue;
+        }
+
+        let id = match node
+            .attr("href")
+            .and_then(|href| class_regex.captures(href))
+            .and_then(|captures| captures.get(1))
+        {
+            Some(id) => id
+                .as_str()
+                .parse()
+                .map_err(|e| Error::data_convert(PARSER, id.as_str(), e))?,
+            None => continue,
+        };
+
+        if !seen.insert(id) {
+            continue;
+        }
+
+        classes.push(ClassEntry {
+            id,
+            name: Some(node.text().trim().to_string()),
+            group: group.clone(),
+        })
+    }
+
+    if classes.is_empty() {
+        return Err(Error::cannot_find(PARSER, "a[href*=\"class=\"]", page));
+    }
+
+    Ok(classes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::ClassEntry;
+    use crate::parser::class_catalog::*;
+
+    fn entry(id: i32, name: &str, group: &str) -> ClassEntry {
+        ClassEntry {
+            id,
+            name: Some(name.to_string()),
+            group: Some(group.to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_classes() {
+        let page = Document::from(include_str!("../../test-data/classes.html"));
+        let classes = get_classes(&page).await.unwrap();
+        assert_eq!(
+            classes,
+            vec![
+                entry(8, "Access Control Systems", "Access Control & Security"),
+                entry(130, "Alarm Systems", "Access Control & Security"),
+                entry(
+                    1870,
+                    "Fire Protection Equipment & Systems",
+                    "Access Control & Security"
+                ),
+                entry(620, "Concrete Contractors", "Concrete"),
+                entry(623, "Concrete Cutting, Drilling & Sawing", "Concrete"),
+                entry(4030, "Plumbing Contractors", "Mechanical"),
+            ]
+        )
+    }
+
+    #[tokio::test]
+    async fn test_get_classes_from_empty_page() {
+        let page = Document::from("<html><body><h3>Nothing here</h3></body></html>");
+        assert!(get_classes(&page).await.is_err())
+    }
+}
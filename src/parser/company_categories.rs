@@ -1,14 +1,15 @@
-use crate::api::get_categories_page;
+use crate::api::{get_categories_page, Client};
 use crate::data::Category;
 use crate::parser::{Error, ReText};
 use futures::future::try_join_all;
 use regex::Regex;
-use reqwest::Client;
 use select::{
     document::Document,
-    node::Node,
-    predicate::{Attr, Name},
+    predicate::{Attr, Name, Predicate},
 };
+use tracing::{debug, debug_span, Instrument};
+
+const PARSER: &str = "company_categories";
 
 pub async fn get_company_categories(
     client: &Client,
@@ -18,12 +19,9 @@ pub async fn get_company_categories(
     let trade_code_node = page
         .find(Attr("id", "tradeCodeDiv"))
         .next()
-        .ok_or(Error::CannotFindNode)?;
+        .ok_or_else(|| Error::cannot_find(PARSER, "#tradeCodeDiv", page))?;
 
-    let div_ids: Vec<&str> = trade_code_node
-        .find(Name("a"))
-        .map(|node| node.attr("data-divid").unwrap_or(""))
-        .collect();
+    let div_ids = division_ids(page);
 
     let mut categories = Vec::new();
 
@@ -37,8 +35,9 @@ pub async fn get_company_categories(
 
     let category_calls = div_ids
         .iter()
-        .map(|id| collect_categories_from_div_id(&client, id, company_id));
+        .map(|id| collect_categories_from_div_id(client, id, company_id));
 
+    let trade_codes = trade_code_node_categories.len();
     categories.append(&mut trade_code_node_categories);
     categories.append(
         &mut try_join_all(category_calls)
@@ -48,43 +47,69 @@ pub async fn get_company_categories(
             .collect(),
     );
 
+    debug!(
+        divisions = div_ids.len(),
+        trade_codes,
+        categories = categories.len(),
+        "categories collected"
+    );
     Ok(categories)
 }
 
-async fn collect_categories_from_div_id(
-    client: &Client,
-    div_id: &str,
-    company_id: i32,
-) -> Result<Vec<Category>, Error> {
-    let meta_categories = get_categories_page(&client, company_id, div_id, None).await?;
-    let heading_and_div_ids: Vec<(&str, &str)> = meta_categories
+/// The divisions of the company page's trade codes, each with a page of
+/// its own. None when the company lists no trade codes.
+pub fn division_ids(page: &Document) -> Vec<String> {
+    page.find(Attr("id", "tradeCodeDiv").descendant(Name("a")))
+        .map(|node| node.attr("data-divid").unwrap_or("").to_string())
+        .collect()
+}
+
+/// The division and heading ids of the headings on a division's page, each
+/// with a page of its own.
+pub fn heading_ids(division_page: &Document) -> Vec<(String, String)> {
+    division_page
         .find(Name("a"))
         .map(|node| {
             (
-                node.attr("data-divid").unwrap_or(""),
-                node.attr("data-headid").unwrap_or(""),
+                node.attr("data-divid").unwrap_or("").to_string(),
+                node.attr("data-headid").unwrap_or("").to_string(),
             )
         })
-        .collect();
+        .collect()
+}
 
-    let mut categories_array = try_join_all(
-        heading_and_div_ids
-            .iter()
-            .map(|(did, hid)| collect_subcategories(&client, company_id, did, hid)),
-    )
-    .await?;
+async fn collect_categories_from_div_id(
+    client: &Client,
+    div_id: &str,
+    company_id: i32,
+) -> Result<Vec<Category>, Error> {
+    async {
+        let meta_categories = get_categories_page(client, company_id, div_id, None)
+            .await
+            .map_err(|e| Error::network_subpage(PARSER, company_id, e))?;
+        let heading_and_div_ids = heading_ids(&meta_categories);
 
-    let categories = categories_array
-        .iter()
-        .flat_map(|cat| cat.clone())
-        .collect();
+        let categories_array = try_join_all(
+            heading_and_div_ids
+                .iter()
+                .map(|(did, hid)| collect_subcategories(client, company_id, did, hid)),
+        )
+        .await?;
 
-    Ok(categories)
-}
+        let categories: Vec<Category> = categories_array
+            .iter()
+            .flat_map(|cat| cat.clone())
+            .collect();
 
-fn match_regex(regex: Regex, node: &Node) -> bool {
-    let text = node.text();
-    regex.is_match(&text)
+        debug!(
+            headings = heading_and_div_ids.len(),
+            categories = categories.len(),
+            "division collected"
+        );
+        Ok(categories)
+    }
+    .instrument(debug_span!("division", div_id))
+    .await
 }
 
 async fn collect_subcategories(
@@ -93,7 +118,10 @@ async fn collect_subcategories(
     div_id: &str,
     head_id: &str,
 ) -> Result<Vec<Category>, Error> {
-    let categories_page = get_categories_page(&client, company_id, div_id, Some(head_id)).await?;
+    let categories_page = get_categories_page(client, company_id, div_id, Some(head_id))
+        .instrument(debug_span!("heading", div_id, head_id))
+        .await
+        .map_err(|e| Error::network_subpage(PARSER, company_id, e))?;
     let categories = categories_page
         .find(ReText(&Regex::new(r"^.*\d{2} \d{2} \d{2}.*$").unwrap()))
         .map(|node| node.find(Name("strong")).next())
@@ -104,21 +132,33 @@ async fn collect_subcategories(
 
 #[cfg(test)]
 mod tests {
-    use crate::api::{client_factory, get_categories_page, get_company_page};
+    use crate::api::Client;
     use crate::parser::company_categories::*;
 
     #[tokio::test]
     async fn test_collect_subcategories() {
-        let client = client_factory().await;
+        let client = Client::fixtures("test-data");
         let categories = collect_subcategories(&client, 922369, "11", "1669")
             .await
             .unwrap();
         assert_eq!(categories, vec!["11 22 00.00.01"])
     }
 
+    #[tokio::test]
+    async fn test_company_without_categories() {
+        let client = Client::fixtures("no-such-dir");
+        let page = Document::from("<div id=\"tradeCodeDiv\"><div class=\"row\"></div></div>");
+        let categories = get_company_categories(&client, &page, 922369).await;
+        assert_eq!(categories.unwrap(), Vec::<Category>::new());
+
+        let page = Document::from("<div id=\"csiSection\"></div>");
+        let error = get_company_categories(&client, &page, 922369).await;
+        assert!(matches!(error, Err(Error::CannotFindNode { .. })));
+    }
+
     #[tokio::test]
     async fn test_get_company_categories() {
-        let client = client_factory().await;
+        let client = Client::fixtures("test-data");
         let page = Document::from(include_str!("../../test-data/company.html"));
         let categories = get_company_categories(&client, &page, 922369)
             .await
@@ -230,8 +270,8 @@ mod tests {
                 "43 11 13",
                 "43 11 13.13",
                 "43 11 13.16",
-                "43 11 23"
+                "43 11 23",
             ]
-        )
+        );
     }
 }
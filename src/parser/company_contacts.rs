@@ -1,34 +1,37 @@
 use crate::data::CompanyContact;
+use crate::normalize::parse_phone;
 use crate::parser::Error;
 use select::document::Document;
 use select::predicate::{Attr, Class, Name, Predicate};
 
+const PARSER: &str = "company_contacts";
+
 pub async fn get_contacts(page: &Document) -> Result<Vec<CompanyContact>, Error> {
     let mut contacts = Vec::new();
     let contacts_node = page
         .find(Attr("id", "keyContactSection"))
         .next()
-        .ok_or(Error::CannotFindNode)?;
+        .ok_or_else(|| Error::cannot_find(PARSER, "#keyContactSection", page))?;
 
     for node in contacts_node.find(Class("row").child(Name("div"))) {
         let data_node = node
             .find(Class("media-body"))
             .next()
-            .ok_or(Error::CannotFindNode)?;
+            .ok_or_else(|| Error::cannot_find(PARSER, ".media-body", &node))?;
         let name = data_node
             .find(Name("strong"))
             .next()
             .map(|n| n.text().trim().to_string())
-            .ok_or(Error::WrongFormatNode)?;
+            .ok_or_else(|| Error::wrong_format(PARSER, ".media-body strong", &data_node))?;
         let position = data_node
             .find(Name("small"))
             .next()
             .map(|n| n.text().trim().to_string())
-            .unwrap_or("".to_string());
+            .unwrap_or_default();
         let phone = data_node
             .find(Class("phoneDisp"))
             .next()
-            .ok_or(Error::CannotFindNode)?
+            .ok_or_else(|| Error::cannot_find(PARSER, ".phoneDisp", &data_node))?
             .text()
             .trim()
             .to_string();
@@ -36,6 +39,7 @@ pub async fn get_contacts(page: &Document) -> Result<Vec<CompanyContact>, Error>
         contacts.push(CompanyContact {
             name,
             position,
+            normalized_phone: parse_phone(&phone),
             phone,
         })
     }
@@ -45,12 +49,17 @@ pub async fn get_contacts(page: &Document) -> Result<Vec<CompanyContact>, Error>
 
 #[cfg(test)]
 mod tests {
-    use crate::data::CompanyContact;
+    use crate::data::{CompanyContact, PhoneKind, PhoneNumber};
     use crate::parser::company_contacts::*;
 
     #[tokio::test]
     async fn test_get_contacts() {
         let contacts_page = Document::from(include_str!("../../test-data/contacts.html"));
+        let phone = Some(PhoneNumber {
+            e164: "+18455623332".into(),
+            extension: None,
+            kind: PhoneKind::Geographic,
+        });
 
         let contacts = get_contacts(&contacts_page).await.unwrap();
         assert_eq!(
@@ -59,14 +68,34 @@ mod tests {
                     name: "Chris Hansen".into(),
                     position: "Pres.".into(),
                     phone: "(845) 562-3332".into(),
+                    normalized_phone: phone.clone(),
                 },
                 CompanyContact {
                     name: "Richard Rebusmen".into(),
                     position: "".into(),
                     phone: "(845) 562-3332".into(),
+                    normalized_phone: phone.clone(),
                 },
             ],
             contacts
         )
     }
+
+    #[tokio::test]
+    async fn test_get_contacts_without_section() {
+        let page = Document::from("<html><head><title>Company Not Found</title></head></html>");
+
+        match get_contacts(&page).await {
+            Err(Error::CannotFindNode {
+                parser,
+                selector,
+                excerpt,
+            }) => {
+                assert_eq!(parser, "company_contacts");
+                assert_eq!(selector, "#keyContactSection");
+                assert!(excerpt.contains("Company Not Found"))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
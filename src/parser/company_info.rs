@@ -1,24 +1,28 @@
 use crate::data::CompanyInfo;
+use crate::normalize::company_facts;
 use crate::parser::Error;
 use futures::join;
 use select::document::Document;
 use select::predicate::{Attr, Class, Name, Predicate};
 
+const PARSER: &str = "company_info";
+
 pub async fn get_company_info(page: &Document) -> Result<CompanyInfo, Error> {
-    let (name_result, rating, website_result, founded, size, duns, other) = join!(
-        get_company_name(&page),
-        get_company_rating(&page),
-        get_company_website(&page),
-        get_company_foundation_date(&page),
-        get_company_size(&page),
-        get_company_duns(&page),
-        get_company_other_info(&page)
+    let (name_result, rating, website_result, founded, size, duns, annual_vol, other) = join!(
+        get_company_name(page),
+        get_company_rating(page),
+        get_company_website(page),
+        get_company_foundation_date(page),
+        get_company_size(page),
+        get_company_duns(page),
+        get_company_annual_vol(page),
+        get_company_other_info(page)
     );
 
     let website = website_result?;
     let name = name_result?;
 
-    Ok(CompanyInfo {
+    let mut info = CompanyInfo {
         name,
         rating,
         website,
@@ -26,18 +30,25 @@ pub async fn get_company_info(page: &Document) -> Result<CompanyInfo, Error> {
         size,
         duns,
         other,
-    })
+        annual_vol,
+        facts: Default::default(),
+    };
+    info.facts = company_facts(&info);
+    Ok(info)
 }
 
 async fn get_company_name(page: &Document) -> Result<String, Error> {
-    let name_node = page.find(Name("h1")).next().ok_or(Error::CannotFindNode)?;
+    let name_node = page
+        .find(Name("h1"))
+        .next()
+        .ok_or_else(|| Error::cannot_find(PARSER, "h1", page))?;
 
     let name_node_text = name_node.text();
     let name = name_node_text
         .trim()
         .split_terminator("   ")
         .next()
-        .ok_or(Error::WrongFormatNode)?;
+        .ok_or_else(|| Error::wrong_format(PARSER, "h1", &name_node))?;
     Ok(name.into())
 }
 
@@ -58,43 +69,43 @@ async fn get_company_website(page: &Document) -> Result<String, Error> {
     let website_node = page
         .find(Class("company-2").descendant(Class("extTrk")))
         .next()
-        .ok_or(Error::CannotFindNode)?;
-    let website = website_node.attr("href").ok_or(Error::WrongFormatNode)?;
-    Ok(website.into())
+        .ok_or_else(|| Error::cannot_find(PARSER, ".company-2 .extTrk", page))?;
+    let website = website_node
+        .attr("href")
+        .ok_or_else(|| Error::wrong_format(PARSER, ".company-2 .extTrk[href]", &website_node))?;
+    Ok(website.to_string())
 }
 
-async fn get_company_foundation_date(page: &Document) -> Option<String> {
+/// The text after a `<strong>` label in the information section, with its
+/// whitespace collapsed.
+fn get_info_field(page: &Document, label: &str) -> Option<String> {
     for node in page.find(Attr("id", "infoSection").descendant(Name("strong"))) {
-        if node.text().starts_with("Founded") {
-            return node.next().map(|node| node.text().trim().into());
-        }
-    }
-    None
-}
-
-async fn get_company_size(page: &Document) -> Option<String> {
-    for node in page.find(Attr("id", "infoSection").descendant(Name("strong"))) {
-        if node.text().starts_with("Size") {
+        if node.text().starts_with(label) {
             return node.next().map(|node| {
                 node.text()
-                    .trim()
                     .split_whitespace()
                     .collect::<Vec<&str>>()
                     .join(" ")
-                    .into()
             });
         }
     }
     None
 }
 
+async fn get_company_foundation_date(page: &Document) -> Option<String> {
+    get_info_field(page, "Founded")
+}
+
+async fn get_company_size(page: &Document) -> Option<String> {
+    get_info_field(page, "Size")
+}
+
 async fn get_company_duns(page: &Document) -> Option<String> {
-    for node in page.find(Attr("id", "infoSection").descendant(Name("strong"))) {
-        if node.text().starts_with("DUNS") {
-            return node.next().map(|node| node.text().trim().into());
-        }
-    }
-    None
+    get_info_field(page, "DUNS")
+}
+
+async fn get_company_annual_vol(page: &Document) -> Option<String> {
+    get_info_field(page, "Annual Vol")
 }
 
 async fn get_company_other_info(page: &Document) -> Vec<String> {
@@ -111,7 +122,7 @@ async fn get_company_other_info(page: &Document) -> Vec<String> {
 
 #[cfg(test)]
 mod tests {
-    use crate::data::CompanyInfo;
+    use crate::data::{CompanyFacts, CompanyInfo, EmployeeRange, Website};
     use crate::parser::company_info::*;
     use select::document::Document;
 
@@ -144,6 +155,13 @@ mod tests {
         assert_eq!(duns, None)
     }
 
+    #[tokio::test]
+    async fn test_get_company_annual_vol() {
+        let page = Document::from(include_str!("../../test-data/company.html"));
+        let annual_vol = get_company_annual_vol(&page).await;
+        assert_eq!(annual_vol, Some("Undisclosed".to_string()))
+    }
+
     #[tokio::test]
     async fn test_get_company_website() {
         let page = Document::from(include_str!("../../test-data/company.html"));
@@ -178,7 +196,23 @@ mod tests {
                 founded: Some("1986".to_string()),
                 size: Some("1-4 Employees".to_string()),
                 duns: None,
-                other: vec![]
+                other: vec![],
+                annual_vol: Some("Undisclosed".to_string()),
+                facts: CompanyFacts {
+                    founded: Some(1986),
+                    employees: Some(EmployeeRange {
+                        min: 1,
+                        max: Some(4),
+                    }),
+                    annual_volume: None,
+                    duns: None,
+                    rating: None,
+                    website: Some(Website {
+                        url: "https://skyviewac.com/".to_string(),
+                        domain: Some("skyviewac.com".to_string()),
+                        issue: None,
+                    }),
+                },
             }
         )
     }
@@ -1,9 +1,12 @@
 use crate::data::CompanyLocation;
+use crate::normalize::{parse_address, parse_phone};
 use crate::parser::Error;
 use select::document::Document;
 use select::node::Node;
 use select::predicate::{Class, Name, Predicate};
 
+const PARSER: &str = "company_locations";
+
 pub async fn get_locations(page: &Document) -> Result<Vec<CompanyLocation>, Error> {
     let mut locations = Vec::new();
     let locations_node = page
@@ -14,28 +17,37 @@ pub async fn get_locations(page: &Document) -> Result<Vec<CompanyLocation>, Erro
         )
         .next()
         .and_then(|n| n.parent())
-        .ok_or(Error::CannotFindNode)?;
+        .ok_or_else(|| Error::cannot_find(PARSER, "h2.pageTitle", page))?;
 
     for node in locations_node.find(Class("row").child(Name("div").and(Class("col-12")))) {
-        let address = node
+        let address_node = node
             .find(Class("col-md"))
             .next()
             .and_then(|n| n.find(Name("div")).next())
-            .ok_or(Error::WrongFormatNode)?
-            .text()
-            .trim()
-            .to_string();
+            .ok_or_else(|| Error::wrong_format(PARSER, ".col-md div", &node))?;
+        let address = address_node.text().trim().to_string();
+        // The street and the city are on lines of their own.
+        let address_lines: String = address_node
+            .children()
+            .map(|n| match n.name() {
+                Some("br") => "\n".to_string(),
+                _ => n.text(),
+            })
+            .collect();
 
         let phone_number = node
             .find(Class("phoneDisp"))
             .next()
-            .ok_or(Error::WrongFormatNode)?
+            .ok_or_else(|| Error::wrong_format(PARSER, ".phoneDisp", &node))?
             .text()
             .trim()
             .to_string();
 
         locations.push(CompanyLocation {
+            postal_address: parse_address(&address_lines),
             address,
+            normalized_phone: parse_phone(&phone_number),
+            geocode: None,
             phone_number,
         })
     }
@@ -44,7 +56,7 @@ pub async fn get_locations(page: &Document) -> Result<Vec<CompanyLocation>, Erro
 
 #[cfg(test)]
 mod tests {
-    use crate::data::CompanyLocation;
+    use crate::data::{CompanyLocation, PhoneKind, PhoneNumber, PostalAddress};
     use crate::parser::company_locations::*;
 
     #[tokio::test]
@@ -56,7 +68,22 @@ mod tests {
             locations,
             vec![CompanyLocation {
                 address: "155 Park Ave. Amityville, NY 11701".into(),
-                phone_number: "(516) 379-1800".into()
+                postal_address: Some(PostalAddress {
+                    street: Some("155 Park Ave.".into()),
+                    suite: None,
+                    city: Some("Amityville".into()),
+                    state: Some("NY".into()),
+                    zip: Some("11701".into()),
+                    zip4: None,
+                    original: "155 Park Ave.\n Amityville, NY 11701".into(),
+                }),
+                phone_number: "(516) 379-1800".into(),
+                normalized_phone: Some(PhoneNumber {
+                    e164: "+15163791800".into(),
+                    extension: None,
+                    kind: PhoneKind::Geographic,
+                }),
+                geocode: None,
             }]
         )
     }
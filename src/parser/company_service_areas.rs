@@ -2,7 +2,9 @@ use crate::data::ServiceArea;
 use crate::parser::Error;
 use select::document::Document;
 use select::predicate::{Attr, Class, Name};
-use std::collections::HashSet;
+
+const PARSER: &str = "company_service_areas";
+
 /*
 states = page.select('#gsaCarousel')
 if states:
@@ -22,19 +24,21 @@ if states:
             areas[state].append(county.text.strip())
     return areas
 */
-async fn get_company_service_areas(page: &Document) -> Result<Vec<ServiceArea>, Error> {
+pub async fn get_company_service_areas(page: &Document) -> Result<Vec<ServiceArea>, Error> {
     let gsa_carousel = page
         .find(Attr("id", "gsaCarousel"))
         .next()
-        .ok_or(Error::CannotFindNode)?;
-    let states: HashSet<String> = gsa_carousel
-        .find(Name("strong"))
-        .filter_map(|node| {
-            let text = node.text();
-            let trimmed = text.split(":").next().map(|t| t.trim().to_string());
-            trimmed
-        })
-        .collect();
+        .ok_or_else(|| Error::cannot_find(PARSER, "#gsaCarousel", page))?;
+    // In the order of the page, each state is named on its slide as well.
+    let mut states: Vec<String> = Vec::new();
+    for node in gsa_carousel.find(Name("strong")) {
+        let text = node.text();
+        if let Some(state) = text.split(':').next().map(|t| t.trim().to_string()) {
+            if !states.contains(&state) {
+                states.push(state);
+            }
+        }
+    }
     let mut areas = Vec::new();
     for state in states.iter() {
         let selector = format!("{}Counties", state.replacen(" ", "_", 1));
@@ -49,6 +53,7 @@ async fn get_company_service_areas(page: &Document) -> Result<Vec<ServiceArea>,
         areas.push(ServiceArea {
             cities: counties,
             state_name: state.to_string(),
+            counties: Vec::new(),
         })
     }
     Ok(areas)
@@ -75,7 +80,20 @@ mod tests {
                     "Queens".to_string(),
                     "Richmond".to_string(),
                 ],
+                counties: Vec::new(),
             },]
         )
     }
+
+    #[tokio::test]
+    async fn test_states_in_page_order() {
+        let page = Document::from(include_str!("../../test-data/company-two-states.html"));
+        let service_areas = get_company_service_areas(&page).await.unwrap();
+        let states: Vec<&str> = service_areas
+            .iter()
+            .map(|area| area.state_name.as_str())
+            .collect();
+        assert_eq!(states, ["New York", "New Jersey"]);
+        assert_eq!(service_areas[1].cities, ["Bergen", "Hudson"]);
+    }
 }
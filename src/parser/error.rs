@@ -1,31 +1,189 @@
 use crate::api;
+use select::document::Document;
+use select::node::Node;
+use select::predicate::Name;
 use std::fmt;
 use std::num::ParseIntError;
 
+const EXCERPT_LEN: usize = 240;
+
 #[derive(Debug)]
 pub enum Error {
-    CannotFindNode,
-    WrongFormatNode,
-    DataConvertError,
-    NetworkSubpageError(api::Error),
+    /// A node the parser relies on is missing from the page.
+    CannotFindNode {
+        parser: &'static str,
+        selector: &'static str,
+        excerpt: String,
+    },
+    /// The node is there but its content doesn't have the expected shape.
+    WrongFormatNode {
+        parser: &'static str,
+        selector: &'static str,
+        excerpt: String,
+    },
+    /// Text extracted from a node can't be converted to the expected type.
+    DataConvert {
+        parser: &'static str,
+        value: String,
+        source: ParseIntError,
+    },
+    /// A page the parser had to request on its own couldn't be fetched.
+    NetworkSubpage {
+        parser: &'static str,
+        company_id: i32,
+        source: api::Error,
+    },
+}
+
+impl Error {
+    pub fn cannot_find(parser: &'static str, selector: &'static str, scope: &impl Excerpt) -> Self {
+        Error::CannotFindNode {
+            parser,
+            selector,
+            excerpt: scope.excerpt(),
+        }
+    }
+
+    pub fn wrong_format(
+        parser: &'static str,
+        selector: &'static str,
+        scope: &impl Excerpt,
+    ) -> Self {
+        Error::WrongFormatNode {
+            parser,
+            selector,
+            excerpt: scope.excerpt(),
+        }
+    }
+
+    pub fn data_convert(parser: &'static str, value: &str, source: ParseIntError) -> Self {
+        Error::DataConvert {
+            parser,
+            value: value.to_string(),
+            source,
+        }
+    }
+
+    pub fn network_subpage(parser: &'static str, company_id: i32, source: api::Error) -> Self {
+        Error::NetworkSubpage {
+            parser,
+            company_id,
+            source,
+        }
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Error: {:?}", self)
+        match self {
+            Error::CannotFindNode {
+                parser,
+                selector,
+                excerpt,
+            } => write!(f, "{}: cannot find `{}` in: {}", parser, selector, excerpt),
+            Error::WrongFormatNode {
+                parser,
+                selector,
+                excerpt,
+            } => write!(
+                f,
+                "{}: unexpected content of `{}`: {}",
+                parser, selector, excerpt
+            ),
+            Error::DataConvert { parser, value, .. } => {
+                write!(f, "{}: cannot convert {:?} to a number", parser, value)
+            }
+            Error::NetworkSubpage {
+                parser, company_id, ..
+            } => write!(
+                f,
+                "{}: cannot fetch a subpage of company {}",
+                parser, company_id
+            ),
+        }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::DataConvert { source, .. } => Some(source),
+            Error::NetworkSubpage { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
 
-impl From<ParseIntError> for Error {
-    fn from(_: ParseIntError) -> Self {
-        Error::DataConvertError
+/// A short, single-line piece of the HTML a parser was looking at, so a
+/// failure can be understood from the error alone.
+pub trait Excerpt {
+    fn excerpt(&self) -> String;
+}
+
+impl Excerpt for Document {
+    fn excerpt(&self) -> String {
+        let title = self.find(Name("title")).next().map(|n| n.html());
+        let body = self.find(Name("body")).next().map(|n| n.html());
+        let html = match (title, body) {
+            (Some(title), Some(body)) => format!("{} {}", title, body),
+            (title, body) => title
+                .or(body)
+                .unwrap_or_else(|| self.nth(0).map(|n| n.html()).unwrap_or_default()),
+        };
+        shorten(&html)
+    }
+}
+
+impl Excerpt for Node<'_> {
+    fn excerpt(&self) -> String {
+        shorten(&self.html())
+    }
+}
+
+fn shorten(html: &str) -> String {
+    let collapsed = html.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if collapsed.chars().count() > EXCERPT_LEN {
+        let mut excerpt: String = collapsed.chars().take(EXCERPT_LEN).collect();
+        excerpt.push('…');
+        excerpt
+    } else {
+        collapsed
     }
 }
 
-impl From<api::Error> for Error {
-    fn from(e: api::Error) -> Self {
-        Error::NetworkSubpageError(e)
+#[cfg(test)]
+mod tests {
+    use crate::parser::error::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_document_excerpt() {
+        let page = Document::from(
+            "<html><head><title>Access Denied</title></head>\
+             <body>\n  <p>You   don't have permission</p></body></html>",
+        );
+        assert_eq!(
+            page.excerpt(),
+            "<title>Access Denied</title> <body> <p>You don't have permission</p></body>"
+        )
+    }
+
+    #[test]
+    fn test_excerpt_is_shortened() {
+        let page = Document::from(format!("<p>{}</p>", "a".repeat(1000)).as_str());
+        let excerpt = page.excerpt();
+        assert_eq!(excerpt.chars().count(), EXCERPT_LEN + 1);
+        assert!(excerpt.ends_with('…'))
+    }
+
+    #[test]
+    fn test_display_and_source() {
+        let source = "1 Employees".parse::<i32>().unwrap_err();
+        let error = Error::data_convert("company_info", "1 Employees", source);
+        assert_eq!(
+            error.to_string(),
+            "company_info: cannot convert \"1 Employees\" to a number"
+        );
+        assert!(error.source().is_some())
     }
 }
@@ -1,16 +1,27 @@
-use crate::data::CompanyProfile;
+use crate::api::Client;
+use crate::data::{CompanyProfile, ProjectExperience};
+pub use crate::parser::class_catalog::get_classes;
+use crate::parser::company_categories::get_company_categories;
+pub use crate::parser::company_categories::{division_ids, heading_ids};
+use crate::parser::company_contacts::get_contacts;
+use crate::parser::company_info::get_company_info;
+use crate::parser::company_locations::get_locations;
+use crate::parser::company_service_areas::get_company_service_areas;
 pub use crate::parser::error::Error;
+pub use crate::parser::search_results::{get_search_suggestions, get_search_summaries};
 use regex::Regex;
 use select::document::Document;
 use select::node::Node;
 use select::predicate::{Attr, Class, Predicate};
 
+mod class_catalog;
 mod company_categories;
 mod company_contacts;
 mod company_info;
 mod company_locations;
 mod company_service_areas;
 pub mod error;
+mod search_results;
 
 #[derive(Copy, Clone, Debug)]
 struct ReText<'a>(&'a Regex);
@@ -25,40 +36,82 @@ impl<'a> Predicate for ReText<'a> {
 pub async fn get_page_ids(page: &Document) -> Result<Vec<i32>, Error> {
     let mut ids = Vec::new();
     for link_node in page.find(Class("cname")) {
-        let link = link_node.attr("href").ok_or(Error::WrongFormatNode)?;
+        let link = link_node
+            .attr("href")
+            .ok_or_else(|| Error::wrong_format("search_results", ".cname[href]", &link_node))?;
         let id_text = link
-            .split_terminator("/")
-            .last()
-            .ok_or(Error::WrongFormatNode)?;
-        let id = id_text.parse()?;
+            .split_terminator('/')
+            .next_back()
+            .ok_or_else(|| Error::wrong_format("search_results", ".cname[href]", &link_node))?;
+        let id = id_text
+            .parse()
+            .map_err(|e| Error::data_convert("search_results", id_text, e))?;
         ids.push(id)
     }
     Ok(ids)
 }
 
 pub async fn get_pages_count(page: &Document) -> Result<i32, Error> {
+    let selector = ".pager-outer-wrapper .dropdown";
     let count_node = page
         .find(Class("pager-outer-wrapper").descendant(Class("dropdown")))
         .next()
-        .ok_or(Error::CannotFindNode)?;
+        .ok_or_else(|| Error::cannot_find("search_results", selector, page))?;
     let count_node_text = count_node.text();
     let count_string = count_node_text
         .split_whitespace()
         .last()
-        .ok_or(Error::WrongFormatNode)?;
-    let count: i32 = count_string.parse()?;
+        .ok_or_else(|| Error::wrong_format("search_results", selector, &count_node))?;
+    let count: i32 = count_string
+        .parse()
+        .map_err(|e| Error::data_convert("search_results", count_string, e))?;
     Ok(count)
 }
 
-pub async fn get_company_profile(page: &Document) -> Result<CompanyProfile, Error> {
-    unimplemented!()
+/// Builds a profile from the company page and its locations and contacts
+/// page. Categories are fetched through `client`, which may be replaying an
+/// archive instead of going to the network.
+///
+/// Sections a company left empty come back empty, like a `#tradeCodeDiv`
+/// without divisions, but a section missing altogether is an error: that is
+/// what a change of the site's layout looks like.
+pub async fn get_company_profile(
+    client: &Client,
+    company_id: i32,
+    company_page: &Document,
+    locations_page: &Document,
+) -> Result<CompanyProfile, Error> {
+    let info = get_company_info(company_page).await?;
+    let story = get_company_story(company_page).await?;
+    let service_areas = get_company_service_areas(company_page).await?;
+    let categories = get_company_categories(client, company_page, company_id).await?;
+    let locations = get_locations(locations_page).await?;
+    let contacts = get_contacts(locations_page).await?;
+
+    Ok(CompanyProfile {
+        id: company_id,
+        info,
+        locations,
+        contacts,
+        story,
+        categories,
+        service_areas,
+        project_experience: ProjectExperience::default(),
+        emails: Vec::new(),
+        logo: None,
+        preferred_brands: Vec::new(),
+        social_media: Vec::new(),
+        qualifications: Vec::new(),
+        media_gallery: Vec::new(),
+        projects: Vec::new(),
+    })
 }
 
 async fn get_company_story(page: &Document) -> Result<String, Error> {
     let story = page
         .find(Attr("id", "proviewStory"))
         .next()
-        .ok_or(Error::CannotFindNode)?
+        .ok_or_else(|| Error::cannot_find("story", "#proviewStory", page))?
         .text()
         .trim()
         .to_string();
@@ -78,6 +131,25 @@ mod tests {
         assert_eq!(story, "TEST STORY")
     }
 
+    #[tokio::test]
+    async fn test_missing_section_is_an_error() {
+        let client = crate::api::Client::fixtures("test-data");
+        let company_page = include_str!("../../test-data/company.html")
+            .replace("id=\"proviewStory\"", "id=\"renamedStory\"");
+        let locations_page = Document::from(include_str!("../../test-data/locations.html"));
+        let error = get_company_profile(
+            &client,
+            922369,
+            &Document::from(company_page.as_str()),
+            &locations_page,
+        )
+        .await
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("story: cannot find `#proviewStory`"));
+    }
+
     #[tokio::test]
     async fn test_get_pages_count() {
         let page = Document::from(include_str!("../../test-data/search.html"));
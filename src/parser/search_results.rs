@@ -0,0 +1,168 @@
+use crate::data::{ClassEntry, SearchSummary};
+use crate::normalize::{address_from_parts, canonical_website};
+use crate::parser::Error;
+use regex::Regex;
+use select::document::Document;
+use select::node::Node;
+use select::predicate::{Attr, Class, Name, Predicate};
+
+const PARSER: &str = "search_results";
+
+pub async fn get_search_summaries(page: &Document) -> Result<Vec<SearchSummary>, Error> {
+    let mut summaries = Vec::new();
+    for node in page.find(Name("div").and(Class("single_result_wrapper"))) {
+        let id_text = node
+            .attr("data-proviewid")
+            .ok_or_else(|| Error::wrong_format(PARSER, "[data-proviewid]", &node))?;
+        let id = id_text
+            .parse()
+            .map_err(|e| Error::data_convert(PARSER, id_text, e))?;
+        let name = node
+            .find(Class("cname"))
+            .next()
+            .ok_or_else(|| Error::cannot_find(PARSER, ".cname", &node))?
+            .text()
+            .trim()
+            .to_string();
+
+        let phone = node
+            .find(Name("a"))
+            .filter_map(|n| n.attr("href"))
+            .filter_map(|href| href.strip_prefix("tel:"))
+            .find(|phone| !phone.is_empty())
+            .map(|phone| phone.to_string());
+
+        let website = node
+            .find(Class("website-link").and(Class("external-trigger")))
+            .next()
+            .and_then(|n| n.attr("href"))
+            .map(|href| href.to_string());
+
+        let keywords = node
+            .find(Class("result-keyword-item"))
+            .filter_map(|n| n.attr("title"))
+            .map(|title| title.trim().to_string())
+            .collect();
+
+        let locality = get_microdata(&node, "addressLocality");
+        let region = get_microdata(&node, "addressRegion");
+        let postal_code = get_microdata(&node, "postalCode");
+        let address = address_from_parts(
+            locality.as_deref(),
+            region.as_deref(),
+            postal_code.as_deref(),
+        );
+
+        summaries.push(SearchSummary {
+            id,
+            name,
+            locality,
+            region,
+            postal_code,
+            address,
+            phone,
+            canonical_website: website.as_deref().and_then(canonical_website),
+            website,
+            keywords,
+        })
+    }
+    Ok(summaries)
+}
+
+/// Classes offered by the "Did You Mean?" facet of a keyword search.
+pub async fn get_search_suggestions(page: &Document) -> Result<Vec<ClassEntry>, Error> {
+    let class_regex = Regex::new(r"[?&]class=(\d+)").unwrap();
+    let mut suggestions = Vec::new();
+    for node in page.find(Attr("id", "facet_suggest").descendant(Name("a"))) {
+        let id = match node
+            .attr("href")
+            .and_then(|href| class_regex.captures(href))
+            .and_then(|captures| captures.get(1))
+        {
+            Some(id) => id
+                .as_str()
+                .parse()
+                .map_err(|e| Error::data_convert(PARSER, id.as_str(), e))?,
+            None => continue,
+        };
+
+        suggestions.push(ClassEntry {
+            id,
+            name: Some(node.text().trim().to_string()),
+            group: None,
+        })
+    }
+    Ok(suggestions)
+}
+
+fn get_microdata(node: &Node, property: &str) -> Option<String> {
+    node.find(Attr("itemprop", property))
+        .next()
+        .map(|n| n.text().trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{ClassEntry, PostalAddress, SearchSummary, Website};
+    use crate::parser::search_results::*;
+
+    #[tokio::test]
+    async fn test_get_search_summaries() {
+        let page = Document::from(include_str!("../../test-data/search.html"));
+        let summaries = get_search_summaries(&page).await.unwrap();
+
+        assert_eq!(summaries.len(), 10);
+        assert_eq!(
+            summaries[0],
+            SearchSummary {
+                id: 1469248,
+                name: "Security First Alarm".to_string(),
+                locality: Some("Portland".to_string()),
+                region: Some("OR".to_string()),
+                postal_code: Some("97229".to_string()),
+                address: Some(PostalAddress {
+                    city: Some("Portland".to_string()),
+                    state: Some("OR".to_string()),
+                    zip: Some("97229".to_string()),
+                    original: "Portland, OR, 97229".to_string(),
+                    ..PostalAddress::default()
+                }),
+                phone: Some("503-296-9100".to_string()),
+                website: Some("http://www.securityfirstalarm.com".to_string()),
+                canonical_website: Some(Website {
+                    url: "https://securityfirstalarm.com/".to_string(),
+                    domain: Some("securityfirstalarm.com".to_string()),
+                    issue: None,
+                }),
+                keywords: vec![
+                    "Fire Alarm Systems".to_string(),
+                    "Fire Sprinkler Systems".to_string(),
+                    "Low Voltage Wiring Systems".to_string(),
+                    "Access Control Installation".to_string(),
+                    "Phone data".to_string(),
+                    "Home Security Systems".to_string(),
+                    "Security Access Systems".to_string(),
+                    "Commercial Surveillance".to_string(),
+                    "Commercial Security".to_string(),
+                ],
+            }
+        )
+    }
+
+    #[tokio::test]
+    async fn test_get_search_suggestions() {
+        let page = Document::from(include_str!("../../test-data/search.html"));
+        let suggestions = get_search_suggestions(&page).await.unwrap();
+
+        assert_eq!(suggestions.len(), 5);
+        assert_eq!(
+            suggestions[0],
+            ClassEntry {
+                id: 1870,
+                name: Some("Fire Protection Equipment & Systems".to_string()),
+                group: None,
+            }
+        )
+    }
+}
@@ -0,0 +1,585 @@
+//! A durable queue of the fetches a crawl is made of, so a crawl that runs
+//! for days can stop at any point and pick up where it left off.
+//!
+//! Every change to a job is appended to `jobs.jsonl` in the queue's
+//! directory as the job's whole new state, the last line of a job wins.
+//! Opening the queue compacts the file to one line per job. Jobs that were
+//! running when the process stopped are simply pending again.
+//!
+//! A job of a kind is only added once per generation: starting a new one,
+//! as `crawl --fresh` does, lets kinds that are done be queued again.
+
+use crate::error::Error;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Failed attempts after which a job goes to the dead letters.
+pub const MAX_ATTEMPTS: u32 = 5;
+/// Wait before the first retry, doubled with every further attempt.
+const RETRY_DELAY_SECONDS: i64 = 30;
+const MAX_RETRY_DELAY_SECONDS: i64 = 60 * 60;
+const JOURNAL: &str = "jobs.jsonl";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobKind {
+    /// A page of a class's listing in a city. The first page queues the
+    /// others.
+    SearchPage {
+        class_id: i32,
+        city: String,
+        page_number: i32,
+    },
+    /// Queues the company's locations page, its category divisions and
+    /// its assembly.
+    CompanyPage {
+        company_id: i32,
+    },
+    LocationsPage {
+        company_id: i32,
+    },
+    /// A division's page and the pages of its headings.
+    CategoryDivision {
+        company_id: i32,
+        div_id: String,
+    },
+    /// Builds the profile from the company's pages once they are all in.
+    Assemble {
+        company_id: i32,
+    },
+}
+
+impl JobKind {
+    pub fn company_id(&self) -> Option<i32> {
+        match self {
+            JobKind::SearchPage { .. } => None,
+            JobKind::CompanyPage { company_id }
+            | JobKind::LocationsPage { company_id }
+            | JobKind::CategoryDivision { company_id, .. }
+            | JobKind::Assemble { company_id } => Some(*company_id),
+        }
+    }
+
+    /// Companies under way come before new ones, so few are ever half done.
+    pub fn default_priority(&self) -> i32 {
+        match self {
+            JobKind::SearchPage { .. } => 0,
+            JobKind::CompanyPage { .. } => 10,
+            JobKind::LocationsPage { .. } | JobKind::CategoryDivision { .. } => 20,
+            JobKind::Assemble { .. } => 30,
+        }
+    }
+}
+
+impl fmt::Display for JobKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobKind::SearchPage {
+                class_id,
+                city,
+                page_number,
+            } => write!(
+                f,
+                "search class {:04} in {} page {}",
+                class_id, city, page_number
+            ),
+            JobKind::CompanyPage { company_id } => write!(f, "company {}", company_id),
+            JobKind::LocationsPage { company_id } => {
+                write!(f, "locations of company {}", company_id)
+            }
+            JobKind::CategoryDivision { company_id, div_id } => {
+                write!(f, "division {} of company {}", div_id, company_id)
+            }
+            JobKind::Assemble { company_id } => write!(f, "assemble company {}", company_id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Pending,
+    Done,
+    /// Failed [`MAX_ATTEMPTS`] times, waiting to be looked at and requeued.
+    Dead,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u64,
+    pub kind: JobKind,
+    /// Higher runs first.
+    pub priority: i32,
+    /// Failed attempts so far.
+    pub attempts: u32,
+    /// Not run before this time.
+    pub next_eligible: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub status: JobStatus,
+    /// The generation of the queue the job was last added in.
+    #[serde(default)]
+    pub generation: u32,
+}
+
+impl fmt::Display for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>6} {} ({} attempts)",
+            self.id, self.kind, self.attempts
+        )?;
+        if let Some(error) = &self.last_error {
+            write!(f, ": {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Jobs by status.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct QueueCounts {
+    pub pending: usize,
+    pub done: usize,
+    pub dead: usize,
+}
+
+impl fmt::Display for QueueCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} jobs pending, {} done, {} dead",
+            self.pending, self.done, self.dead
+        )
+    }
+}
+
+pub struct JobQueue {
+    jobs: HashMap<u64, Job>,
+    ids: HashMap<JobKind, u64>,
+    /// Pending jobs that aren't running, in the order they are taken.
+    ready: BTreeSet<(Reverse<i32>, u64)>,
+    running: BTreeSet<u64>,
+    /// Jobs of each company that aren't done, other than its assembly.
+    unfinished_parts: HashMap<i32, usize>,
+    generation: u32,
+    next_id: u64,
+    dir: PathBuf,
+    journal: BufWriter<File>,
+}
+
+impl JobQueue {
+    /// Opens the queue in `dir`, creating both if needed.
+    pub fn open(dir: &Path) -> Result<JobQueue, Error> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(JOURNAL);
+        let mut jobs: HashMap<u64, Job> = HashMap::new();
+        match File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let job: Job = serde_json::from_str(&line)?;
+                    jobs.insert(job.id, job);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let temp_path = dir.join(format!("{}.tmp", JOURNAL));
+        let mut ids: Vec<&u64> = jobs.keys().collect();
+        ids.sort();
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        for id in ids {
+            serde_json::to_writer(&mut writer, &jobs[id])?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        fs::rename(&temp_path, &path)?;
+
+        let journal = BufWriter::new(OpenOptions::new().append(true).open(&path)?);
+        let mut queue = JobQueue {
+            next_id: jobs.keys().max().map_or(1, |id| id + 1),
+            generation: jobs.values().map(|job| job.generation).max().unwrap_or(0),
+            jobs: HashMap::new(),
+            ids: HashMap::new(),
+            ready: BTreeSet::new(),
+            running: BTreeSet::new(),
+            unfinished_parts: HashMap::new(),
+            dir: dir.to_path_buf(),
+            journal,
+        };
+        for (_, job) in jobs {
+            queue.index(job);
+        }
+        Ok(queue)
+    }
+
+    /// Adds a job unless one of the same kind was added in this generation,
+    /// or is still pending or dead from an earlier one. A job done in an
+    /// earlier generation is pending again. `priority` is the kind's
+    /// default when `None`. Returns whether the job was added.
+    pub fn push(
+        &mut self,
+        kind: JobKind,
+        priority: Option<i32>,
+        now: DateTime<Utc>,
+    ) -> Result<bool, Error> {
+        let priority = priority.unwrap_or_else(|| kind.default_priority());
+        if let Some(&id) = self.ids.get(&kind) {
+            let job = &self.jobs[&id];
+            if job.status != JobStatus::Done || job.generation == self.generation {
+                return Ok(false);
+            }
+            let generation = self.generation;
+            self.finish(id, |job| {
+                job.priority = priority;
+                job.attempts = 0;
+                job.next_eligible = now;
+                job.last_error = None;
+                job.status = JobStatus::Pending;
+                job.generation = generation;
+            })?;
+            return Ok(true);
+        }
+        let job = Job {
+            id: self.next_id,
+            kind,
+            priority,
+            attempts: 0,
+            next_eligible: now,
+            last_error: None,
+            status: JobStatus::Pending,
+            generation: self.generation,
+        };
+        self.next_id += 1;
+        self.save(&job)?;
+        self.index(job);
+        Ok(true)
+    }
+
+    /// The first pending job by priority that is eligible at `now`, marked
+    /// as running until it is completed, failed or released.
+    pub fn take(&mut self, now: DateTime<Utc>) -> Option<Job> {
+        let key = *self.ready.iter().find(|(_, id)| {
+            let job = &self.jobs[id];
+            job.next_eligible <= now && self.can_run(job)
+        })?;
+        self.ready.remove(&key);
+        self.running.insert(key.1);
+        Some(self.jobs[&key.1].clone())
+    }
+
+    /// How long until a pending job becomes eligible, `None` when none that
+    /// aren't running can ever be taken as things stand.
+    pub fn wait(&self, now: DateTime<Utc>) -> Option<Duration> {
+        self.ready
+            .iter()
+            .map(|(_, id)| &self.jobs[id])
+            .filter(|job| self.can_run(job))
+            .map(|job| job.next_eligible - now)
+            .min()
+            .map(|wait| wait.max(Duration::zero()))
+    }
+
+    /// Where a company's pages are kept until it is assembled.
+    pub fn pages_dir(&self, company_id: i32) -> PathBuf {
+        self.dir.join("pages").join(company_id.to_string())
+    }
+
+    pub fn complete(&mut self, id: u64) -> Result<(), Error> {
+        self.finish(id, |job| job.status = JobStatus::Done)
+    }
+
+    /// Counts a failed attempt. The job is tried again after a delay that
+    /// doubles every time, until it has failed [`MAX_ATTEMPTS`] times.
+    /// Returns whether that was the last attempt.
+    pub fn fail(&mut self, id: u64, error: String, now: DateTime<Utc>) -> Result<bool, Error> {
+        let mut dead = false;
+        self.finish(id, |job| {
+            job.attempts += 1;
+            job.last_error = Some(error);
+            if job.attempts >= MAX_ATTEMPTS {
+                job.status = JobStatus::Dead;
+                dead = true;
+            } else {
+                let backoff = RETRY_DELAY_SECONDS << (job.attempts - 1).min(16);
+                let delay = Duration::seconds(backoff.min(MAX_RETRY_DELAY_SECONDS));
+                job.next_eligible = now + delay;
+            }
+        })?;
+        Ok(dead)
+    }
+
+    /// Puts a running job back as it was, e.g. when the crawl stops before
+    /// it could finish.
+    pub fn release(&mut self, id: u64) {
+        let job = &self.jobs[&id];
+        if self.running.remove(&id) {
+            self.ready.insert((Reverse(job.priority), id));
+        }
+    }
+
+    /// Puts every running job back, e.g. when the crawl is blocked and
+    /// stops without waiting for them.
+    pub fn release_running(&mut self) {
+        let running: Vec<u64> = self.running.iter().copied().collect();
+        for id in running {
+            self.release(id);
+        }
+    }
+
+    /// Starts a new generation, in which jobs that are done can be added
+    /// again.
+    pub fn start_generation(&mut self) {
+        self.generation += 1;
+    }
+
+    /// A company is only assembled once all its pages are in, which may
+    /// take a requeue if one of them is dead.
+    fn can_run(&self, job: &Job) -> bool {
+        match job.kind {
+            JobKind::Assemble { company_id } => !self.unfinished_parts.contains_key(&company_id),
+            _ => true,
+        }
+    }
+
+    /// The dead letters, by id.
+    pub fn dead(&self) -> Vec<&Job> {
+        let mut dead: Vec<&Job> = self
+            .jobs
+            .values()
+            .filter(|job| job.status == JobStatus::Dead)
+            .collect();
+        dead.sort_by_key(|job| job.id);
+        dead
+    }
+
+    /// Makes dead jobs pending again with a clean slate, those in `ids` or
+    /// all of them. Returns how many were requeued.
+    pub fn requeue(&mut self, ids: &[u64], now: DateTime<Utc>) -> Result<usize, Error> {
+        let requeued: Vec<Job> = self
+            .dead()
+            .into_iter()
+            .filter(|job| ids.is_empty() || ids.contains(&job.id))
+            .cloned()
+            .collect();
+        for mut job in requeued.iter().cloned() {
+            self.unindex(job.id);
+            job.status = JobStatus::Pending;
+            job.attempts = 0;
+            job.next_eligible = now;
+            self.save(&job)?;
+            self.index(job);
+        }
+        Ok(requeued.len())
+    }
+
+    pub fn counts(&self) -> QueueCounts {
+        let mut counts = QueueCounts::default();
+        for job in self.jobs.values() {
+            match job.status {
+                JobStatus::Pending => counts.pending += 1,
+                JobStatus::Done => counts.done += 1,
+                JobStatus::Dead => counts.dead += 1,
+            }
+        }
+        counts
+    }
+
+    fn finish(&mut self, id: u64, update: impl FnOnce(&mut Job)) -> Result<(), Error> {
+        let mut job = self.jobs[&id].clone();
+        self.unindex(id);
+        update(&mut job);
+        self.save(&job)?;
+        self.index(job);
+        Ok(())
+    }
+
+    fn save(&mut self, job: &Job) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.journal, job)?;
+        self.journal.write_all(b"\n")?;
+        self.journal.flush()?;
+        Ok(())
+    }
+
+    fn index(&mut self, job: Job) {
+        if job.status == JobStatus::Pending {
+            self.ready.insert((Reverse(job.priority), job.id));
+        }
+        if job.status != JobStatus::Done {
+            self.count_part(&job.kind, true);
+        }
+        self.ids.insert(job.kind.clone(), job.id);
+        self.jobs.insert(job.id, job);
+    }
+
+    fn unindex(&mut self, id: u64) {
+        let job = &self.jobs[&id];
+        let (status, kind) = (job.status, job.kind.clone());
+        if status == JobStatus::Pending {
+            self.ready.remove(&(Reverse(job.priority), id));
+            self.running.remove(&id);
+        }
+        if status != JobStatus::Done {
+            self.count_part(&kind, false);
+        }
+    }
+
+    fn count_part(&mut self, kind: &JobKind, add: bool) {
+        let company_id = match kind {
+            JobKind::Assemble { .. } => return,
+            kind => match kind.company_id() {
+                Some(company_id) => company_id,
+                None => return,
+            },
+        };
+        let count = self.unfinished_parts.entry(company_id).or_insert(0);
+        if add {
+            *count += 1;
+        } else {
+            *count -= 1;
+        }
+        if *count == 0 {
+            self.unfinished_parts.remove(&company_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::queue::*;
+
+    fn company(company_id: i32) -> JobKind {
+        JobKind::CompanyPage { company_id }
+    }
+
+    #[test]
+    fn test_priorities_and_retries() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("queue");
+        let now = Utc::now();
+        let mut queue = JobQueue::open(&dir).unwrap();
+        let search = JobKind::SearchPage {
+            class_id: 4030,
+            city: "New York, NY".to_string(),
+            page_number: 1,
+        };
+        let locations = JobKind::LocationsPage { company_id: 1 };
+        assert!(queue.push(search.clone(), None, now).unwrap());
+        assert!(queue.push(company(1), None, now).unwrap());
+        assert!(!queue.push(company(1), None, now).unwrap());
+        assert!(queue
+            .push(JobKind::Assemble { company_id: 1 }, None, now)
+            .unwrap());
+        assert!(queue.push(locations.clone(), None, now).unwrap());
+
+        // The assembly waits for the company's pages.
+        let job = queue.take(now).unwrap();
+        assert_eq!(job.kind, locations);
+        queue.complete(job.id).unwrap();
+        let job = queue.take(now).unwrap();
+        assert_eq!(job.kind, company(1));
+        assert!(!queue.fail(job.id, "timed out".to_string(), now).unwrap());
+        assert_eq!(queue.take(now).unwrap().kind, search);
+        assert_eq!(queue.take(now), None);
+        assert_eq!(queue.wait(now), Some(Duration::seconds(30)));
+
+        // Reopening forgets what was running and keeps the rest.
+        let mut queue = JobQueue::open(&dir).unwrap();
+        assert_eq!(
+            queue.counts(),
+            QueueCounts {
+                pending: 3,
+                done: 1,
+                dead: 0
+            }
+        );
+        assert!(!queue.push(locations, None, now).unwrap());
+        assert_eq!(queue.take(now).unwrap().kind, search);
+        let later = now + Duration::seconds(30);
+        let job = queue.take(later).unwrap();
+        assert_eq!((job.kind.clone(), job.attempts), (company(1), 1));
+        assert_eq!(job.last_error.as_deref(), Some("timed out"));
+        queue.complete(job.id).unwrap();
+        let job = queue.take(later).unwrap();
+        assert_eq!(job.kind, JobKind::Assemble { company_id: 1 });
+        queue.release(job.id);
+        assert_eq!(queue.take(later).unwrap().id, job.id);
+    }
+
+    #[test]
+    fn test_generations_and_release() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("generations");
+        let now = Utc::now();
+        let mut queue = JobQueue::open(&dir).unwrap();
+        assert!(queue.push(company(1), None, now).unwrap());
+        assert!(queue.push(company(2), Some(50), now).unwrap());
+
+        let first = queue.take(now).unwrap();
+        assert_eq!(first.kind, company(2));
+        let second = queue.take(now).unwrap();
+        queue.release_running();
+        assert_eq!(queue.take(now).unwrap().id, first.id);
+        assert_eq!(queue.take(now).unwrap().id, second.id);
+        queue.complete(first.id).unwrap();
+        queue.complete(second.id).unwrap();
+
+        // Done for this generation, then queued again in the next.
+        assert!(!queue.push(company(1), None, now).unwrap());
+        queue.start_generation();
+        assert!(queue.push(company(1), None, now).unwrap());
+        assert!(!queue.push(company(1), None, now).unwrap());
+        let job = queue.take(now).unwrap();
+        assert_eq!((job.id, job.priority, job.generation), (second.id, 10, 1));
+
+        let mut queue = JobQueue::open(&dir).unwrap();
+        assert!(!queue.push(company(1), None, now).unwrap());
+        assert!(queue.push(company(2), None, now).unwrap());
+        assert_eq!(queue.counts().pending, 2);
+    }
+
+    #[test]
+    fn test_dead_letters() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("dead-letters");
+        let mut now = Utc::now();
+        let mut queue = JobQueue::open(&dir).unwrap();
+        queue.push(company(7), None, now).unwrap();
+        queue
+            .push(JobKind::Assemble { company_id: 7 }, None, now)
+            .unwrap();
+        for attempt in 1..=MAX_ATTEMPTS {
+            let job = queue.take(now).unwrap();
+            assert_eq!((job.kind, job.attempts), (company(7), attempt - 1));
+            let dead = queue.fail(job.id, "503".to_string(), now).unwrap();
+            assert_eq!(dead, attempt == MAX_ATTEMPTS);
+            now += Duration::hours(1);
+        }
+        // Nothing left that could run.
+        assert_eq!(queue.take(now), None);
+        assert_eq!(queue.wait(now), None);
+        assert_eq!(queue.dead().len(), 1);
+        assert_eq!(
+            queue.dead()[0].to_string(),
+            "     1 company 7 (5 attempts): 503"
+        );
+
+        let mut queue = JobQueue::open(&dir).unwrap();
+        assert_eq!(queue.requeue(&[2], now).unwrap(), 0);
+        assert_eq!(queue.requeue(&[], now).unwrap(), 1);
+        assert!(queue.dead().is_empty());
+        let job = queue.take(now).unwrap();
+        assert_eq!((job.attempts, job.status), (0, JobStatus::Pending));
+        assert_eq!(job.last_error.as_deref(), Some("503"));
+    }
+}
@@ -0,0 +1,456 @@
+//! Keeping stored profiles current without scraping every company again.
+//!
+//! A state file next to the profiles remembers when each company was last
+//! listed and last scraped, and what its listing looked like then. A refresh
+//! lists the classes again and scrapes new companies first, then those whose
+//! listing changed, then those scraped longer ago than the TTL.
+
+use crate::api::Client;
+use crate::crawler::{scrape_companies, CrawlReport};
+use crate::data::SearchSummary;
+use crate::error::Error;
+use crate::search::{search_listings, SearchMode};
+use crate::sink::JsonLinesSink;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use tracing::{info_span, warn, Instrument};
+
+/// What a search listing shows of a company, compared between runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Listing {
+    pub name: String,
+    pub locality: Option<String>,
+    pub region: Option<String>,
+    pub postal_code: Option<String>,
+    /// Sorted, the chips move around.
+    pub keywords: Vec<String>,
+}
+
+impl From<&SearchSummary> for Listing {
+    fn from(summary: &SearchSummary) -> Self {
+        let mut keywords = summary.keywords.clone();
+        keywords.sort();
+        Listing {
+            name: summary.name.clone(),
+            locality: summary.locality.clone(),
+            region: summary.region.clone(),
+            postal_code: summary.postal_code.clone(),
+            keywords,
+        }
+    }
+}
+
+/// One line of the state file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompanyState {
+    pub id: i32,
+    /// When a listing last showed the company.
+    pub last_seen: Option<DateTime<Utc>>,
+    /// When its profile was last scraped successfully. `None` for profiles
+    /// stored before there was a state file.
+    pub last_scraped: Option<DateTime<Utc>>,
+    /// Its listing as of `last_scraped`.
+    pub listing: Option<Listing>,
+    /// Since when the site answers that the company doesn't exist. Such
+    /// companies are left alone until a listing shows them again.
+    pub gone_since: Option<DateTime<Utc>>,
+}
+
+impl CompanyState {
+    /// A company with nothing known about it yet, or only a stored profile.
+    pub fn new(id: i32) -> Self {
+        CompanyState {
+            id,
+            last_seen: None,
+            last_scraped: None,
+            listing: None,
+            gone_since: None,
+        }
+    }
+}
+
+/// Why a company is scraped again, in the order they are scraped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Reason {
+    /// Listed for the first time.
+    New,
+    /// Its listing differs from the one it was last scraped with.
+    Changed,
+    /// Last scraped longer ago than the TTL, or never.
+    Stale,
+}
+
+/// How a refresh went.
+#[derive(Debug, Default)]
+pub struct RefreshReport {
+    /// Companies to scrape, in order.
+    pub queue: Vec<(i32, Reason)>,
+    /// Companies listed or stored that were left alone.
+    pub fresh: usize,
+    pub crawl: CrawlReport,
+}
+
+impl fmt::Display for RefreshReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = |reason| self.queue.iter().filter(|(_, r)| *r == reason).count();
+        writeln!(
+            f,
+            "{} new, {} changed, {} stale, {} fresh",
+            count(Reason::New),
+            count(Reason::Changed),
+            count(Reason::Stale),
+            self.fresh
+        )?;
+        write!(f, "{}", self.crawl)
+    }
+}
+
+/// The state file next to the profiles at `output`.
+pub fn state_path(output: &str) -> String {
+    format!("{}.state.jsonl", output)
+}
+
+/// Companies' states by id. A missing file just means there weren't any.
+pub fn read_state(path: impl AsRef<Path>) -> Result<HashMap<i32, CompanyState>, Error> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut states = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let state: CompanyState = serde_json::from_str(&line)?;
+        states.insert(state.id, state);
+    }
+    Ok(states)
+}
+
+/// Replaces the state file, by way of a temporary file so an interrupted
+/// write leaves the previous one.
+pub fn write_state(
+    path: impl AsRef<Path>,
+    states: &HashMap<i32, CompanyState>,
+) -> Result<(), Error> {
+    let path = path.as_ref();
+    let temp_path = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    let mut ids: Vec<&i32> = states.keys().collect();
+    ids.sort();
+    for id in ids {
+        serde_json::to_writer(&mut writer, &states[id])?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+/// The companies to scrape and why, new ones in listing order first, then
+/// changed ones, then stale ones from the longest unscraped. Companies with
+/// a state are known, listed or not, except gone ones that no listing shows
+/// anymore. Returns the number of companies left alone too.
+pub fn plan_refresh(
+    listings: &[(i32, Option<SearchSummary>)],
+    states: &HashMap<i32, CompanyState>,
+    now: DateTime<Utc>,
+    ttl: Duration,
+) -> (Vec<(i32, Reason)>, usize) {
+    let listed: HashMap<i32, Option<&SearchSummary>> = listings
+        .iter()
+        .map(|(id, summary)| (*id, summary.as_ref()))
+        .collect();
+    let mut ids: Vec<i32> = listings.iter().map(|(id, _)| *id).collect();
+    let mut rest: Vec<i32> = states
+        .iter()
+        .filter(|(id, state)| !listed.contains_key(id) && state.gone_since.is_none())
+        .map(|(id, _)| *id)
+        .collect();
+    rest.sort_unstable();
+    ids.extend(rest);
+
+    let mut queue = Vec::new();
+    let mut fresh = 0;
+    for id in ids {
+        let reason = match states.get(&id) {
+            None => Some(Reason::New),
+            Some(state) => {
+                let changed = match (listed.get(&id).copied().flatten(), &state.listing) {
+                    (Some(summary), Some(listing)) => Listing::from(summary) != *listing,
+                    _ => false,
+                };
+                if changed {
+                    Some(Reason::Changed)
+                } else if !matches!(state.last_scraped, Some(scraped) if now - scraped < ttl) {
+                    Some(Reason::Stale)
+                } else {
+                    None
+                }
+            }
+        };
+        match reason {
+            Some(reason) => queue.push((id, reason)),
+            None => fresh += 1,
+        }
+    }
+
+    let epoch = DateTime::<Utc>::MIN_UTC;
+    queue.sort_by_key(|(id, reason)| {
+        let scraped = match reason {
+            Reason::Stale => states.get(id).and_then(|s| s.last_scraped).unwrap_or(epoch),
+            _ => epoch,
+        };
+        (*reason, scraped)
+    });
+    (queue, fresh)
+}
+
+/// Lists every company of `class_ids` in `cities` and scrapes the ones
+/// [`plan_refresh`] picks, updating `states` with what happened.
+pub async fn refresh(
+    client: &Client,
+    class_ids: &[i32],
+    cities: &[&str],
+    states: &mut HashMap<i32, CompanyState>,
+    ttl: Duration,
+    concurrency: usize,
+    sink: &mut JsonLinesSink,
+) -> Result<RefreshReport, Error> {
+    let mut report = RefreshReport::default();
+    let mut listings = Vec::new();
+    let mut seen = HashSet::new();
+    for &class_id in class_ids {
+        let span = info_span!("class", class_id);
+        match search_listings(client, SearchMode::Class(class_id), cities, concurrency)
+            .instrument(span.clone())
+            .await
+        {
+            Ok(found) => listings.extend(found.into_iter().filter(|(id, _)| seen.insert(*id))),
+            Err(e) if matches!(e.api_error(), Some(api_error) if api_error.is_block()) => {
+                span.in_scope(|| warn!(error = %e.report(), "blocked"));
+                report.crawl.blocked = Some(e.report());
+                return Ok(report);
+            }
+            Err(e) => {
+                span.in_scope(|| warn!(error = %e.report(), "class failed"));
+                report.crawl.failed_classes.push((class_id, e.report()))
+            }
+        }
+    }
+
+    let now = Utc::now();
+    let (queue, fresh) = plan_refresh(&listings, states, now, ttl);
+    for (id, _) in &listings {
+        let state = states.entry(*id).or_insert_with(|| CompanyState::new(*id));
+        state.last_seen = Some(now);
+        state.gone_since = None;
+    }
+
+    let ids: Vec<i32> = queue.iter().map(|(id, _)| *id).collect();
+    let scraped = scrape_companies(client, &ids, concurrency, sink).await?;
+    let summaries: HashMap<i32, &SearchSummary> = listings
+        .iter()
+        .filter_map(|(id, summary)| summary.as_ref().map(|summary| (*id, summary)))
+        .collect();
+    for id in &scraped.scraped {
+        let state = states.entry(*id).or_insert_with(|| CompanyState::new(*id));
+        state.last_scraped = Some(now);
+        if let Some(summary) = summaries.get(id) {
+            state.listing = Some(Listing::from(*summary));
+        }
+    }
+    for id in &scraped.not_found {
+        let state = states.entry(*id).or_insert_with(|| CompanyState::new(*id));
+        state.gone_since = state.gone_since.or(Some(now));
+    }
+
+    Ok(RefreshReport {
+        queue,
+        fresh,
+        crawl: CrawlReport {
+            failed_classes: report.crawl.failed_classes,
+            ..scraped
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{client_factory, Client, Endpoints, HeaderProfile, SessionConfig};
+    use crate::mock::MockServer;
+    use crate::refresh::*;
+    use crate::sink::read_profiles;
+
+    fn summary(id: i32, name: &str, keywords: &[&str]) -> SearchSummary {
+        SearchSummary {
+            id,
+            name: name.to_string(),
+            locality: Some("Amityville".to_string()),
+            region: Some("NY".to_string()),
+            postal_code: Some("11701".to_string()),
+            address: None,
+            phone: None,
+            website: None,
+            canonical_website: None,
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+        }
+    }
+
+    fn state(id: i32, days_ago: i64, listing: Option<&SearchSummary>) -> CompanyState {
+        CompanyState {
+            id,
+            last_seen: None,
+            last_scraped: Some(Utc::now() - Duration::days(days_ago)),
+            listing: listing.map(Listing::from),
+            gone_since: None,
+        }
+    }
+
+    #[test]
+    fn test_plan_refresh() {
+        let listings = vec![
+            (1, Some(summary(1, "Fresh", &["HVAC", "Boilers"]))),
+            (2, Some(summary(2, "Renamed Inc.", &[]))),
+            (3, Some(summary(3, "Old", &[]))),
+            (4, None),
+            (5, Some(summary(5, "Older", &[]))),
+        ];
+        let states: HashMap<i32, CompanyState> = vec![
+            // Only the order of the chips changed.
+            state(1, 1, Some(&summary(1, "Fresh", &["Boilers", "HVAC"]))),
+            state(2, 1, Some(&summary(2, "Name Inc.", &[]))),
+            state(3, 40, Some(&summary(3, "Old", &[]))),
+            state(5, 90, None),
+            // No longer listed.
+            state(6, 60, None),
+            state(7, 2, None),
+        ]
+        .into_iter()
+        // Stored before there was a state file.
+        .chain(vec![CompanyState::new(8)])
+        // Not found last time and not listed since.
+        .chain(vec![CompanyState {
+            gone_since: Some(Utc::now() - Duration::days(20)),
+            ..state(9, 60, None)
+        }])
+        .map(|state| (state.id, state))
+        .collect();
+
+        let (queue, fresh) = plan_refresh(&listings, &states, Utc::now(), Duration::days(30));
+        assert_eq!(
+            queue,
+            vec![
+                (4, Reason::New),
+                (2, Reason::Changed),
+                (8, Reason::Stale),
+                (5, Reason::Stale),
+                (6, Reason::Stale),
+                (3, Reason::Stale),
+            ]
+        );
+        assert_eq!(fresh, 2);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_from_fixtures() {
+        let temp = tempfile::tempdir().unwrap();
+        let client = Client::fixtures("test-data");
+        let path = temp.path().join("refresh.jsonl");
+        let ttl = Duration::days(30);
+        let mut states = HashMap::new();
+
+        let mut sink = JsonLinesSink::append(&path).unwrap();
+        let report = refresh(
+            &client,
+            &[1],
+            &["New York, NY"],
+            &mut states,
+            ttl,
+            4,
+            &mut sink,
+        )
+        .await
+        .unwrap();
+        sink.finish().unwrap();
+        assert_eq!(report.queue.len(), 10);
+        assert!(report
+            .queue
+            .iter()
+            .all(|(_, reason)| *reason == Reason::New));
+        assert_eq!(report.crawl.scraped.len(), 10);
+        assert!(states.values().all(|state| state.listing.is_some()));
+
+        let state_file = temp.path().join("refresh.jsonl.state.jsonl");
+        write_state(&state_file, &states).unwrap();
+        let mut states = read_state(&state_file).unwrap();
+        assert_eq!(states.len(), 10);
+
+        assert_eq!(read_profiles(&path).unwrap().len(), 10);
+        let mut sink = JsonLinesSink::append(&path).unwrap();
+        let report = refresh(
+            &client,
+            &[1],
+            &["New York, NY"],
+            &mut states,
+            ttl,
+            4,
+            &mut sink,
+        )
+        .await
+        .unwrap();
+        sink.finish().unwrap();
+        assert!(report.queue.is_empty());
+        assert_eq!(report.fresh, 10);
+    }
+
+    #[tokio::test]
+    async fn test_gone_company_is_not_scraped_again() {
+        let server = MockServer::start().await;
+        let endpoints = Endpoints::for_base(&server.base_url()).unwrap();
+        let client = client_factory(None, HeaderProfile::default(), SessionConfig::default())
+            .await
+            .unwrap()
+            .with_endpoints(endpoints);
+        server.template(
+            "company-2.html",
+            "<html><head><title>Page Not Found</title></head></html>",
+        );
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("refresh.jsonl");
+        // Stored by an earlier crawl, no longer listed.
+        let mut states: HashMap<i32, CompanyState> =
+            vec![(2, CompanyState::new(2))].into_iter().collect();
+
+        let mut reports = Vec::new();
+        for _ in 0..2 {
+            let mut sink = JsonLinesSink::append(&path).unwrap();
+            let report = refresh(
+                &client,
+                &[1],
+                &["New York, NY"],
+                &mut states,
+                Duration::days(30),
+                4,
+                &mut sink,
+            )
+            .await
+            .unwrap();
+            sink.finish().unwrap();
+            reports.push(report);
+        }
+
+        assert!(reports[0].queue.contains(&(2, Reason::Stale)));
+        assert_eq!(reports[0].crawl.not_found, vec![2]);
+        assert!(reports[1].queue.is_empty());
+        assert!(reports[1].crawl.not_found.is_empty());
+        assert!(states[&2].gone_since.is_some());
+    }
+}
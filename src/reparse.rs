@@ -0,0 +1,238 @@
+use crate::api::Client;
+use crate::archive::ArchiveReader;
+use crate::crawler::scrape_company;
+use crate::data::CompanyProfile;
+use crate::error::Error;
+use crate::sink::JsonLinesSink;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// What a re-parse did compared with the previously stored profiles.
+#[derive(Debug, Default, PartialEq)]
+pub struct ReparseReport {
+    pub added: Vec<i32>,
+    pub changed: Vec<i32>,
+    pub unchanged: usize,
+    /// Companies that couldn't be rebuilt, with the error report. Their
+    /// previous profile, if any, is kept.
+    pub failed: Vec<(i32, String)>,
+}
+
+impl fmt::Display for ReparseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for id in &self.added {
+            writeln!(f, "+ {}", id)?;
+        }
+        for id in &self.changed {
+            writeln!(f, "~ {}", id)?;
+        }
+        for (id, report) in &self.failed {
+            writeln!(f, "! {}: {}", id, report)?;
+        }
+        write!(
+            f,
+            "{} added, {} changed, {} unchanged, {} failed",
+            self.added.len(),
+            self.changed.len(),
+            self.unchanged,
+            self.failed.len()
+        )
+    }
+}
+
+/// Rebuilds profiles from archived pages only, through the same code as a
+/// live crawl. `company_ids` limits the run, otherwise every company in the
+/// archive is re-parsed. Previous profiles that weren't rebuilt are written
+/// to the sink unchanged, so it always ends up with every known company.
+pub async fn reparse(
+    reader: Arc<ArchiveReader>,
+    company_ids: &[i32],
+    previous: &HashMap<i32, CompanyProfile>,
+    sink: &mut JsonLinesSink,
+) -> Result<ReparseReport, Error> {
+    let ids = if company_ids.is_empty() {
+        reader.company_ids()
+    } else {
+        company_ids.to_vec()
+    };
+    let client = Client::replay(reader);
+
+    let mut report = ReparseReport::default();
+    let mut rebuilt = HashMap::new();
+    for id in ids {
+        match scrape_company(&client, id).await {
+            Ok(mut profile) => {
+                sink.prepare(&mut profile);
+                match previous.get(&id) {
+                    None => report.added.push(id),
+                    Some(old) if *old != profile => report.changed.push(id),
+                    Some(_) => report.unchanged += 1,
+                }
+                rebuilt.insert(id, profile);
+            }
+            Err(e) => report.failed.push((id, e.report())),
+        }
+    }
+
+    let mut all_ids: Vec<i32> = rebuilt.keys().chain(previous.keys()).copied().collect();
+    all_ids.sort_unstable();
+    all_ids.dedup();
+    for id in all_ids {
+        if let Some(profile) = rebuilt.get(&id).or_else(|| previous.get(&id)) {
+            sink.write(profile)?;
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::PageType;
+    use crate::archive::{Archiver, Exchange, DEFAULT_MAX_FILE_SIZE};
+    use crate::reparse::*;
+    use crate::sink::read_profiles;
+    use chrono::Utc;
+    use select::document::Document;
+    use select::predicate::Name;
+    use std::fs;
+
+    fn exchange<'a>(
+        page_type: PageType,
+        company_id: i32,
+        form: Option<&'a [u8]>,
+        body: &'a str,
+    ) -> Exchange<'a> {
+        Exchange {
+            page_type,
+            company_id: Some(company_id),
+            method: if form.is_some() { "POST" } else { "GET" },
+            url: "http://www.thebluebook.com/",
+            request_headers: Vec::new(),
+            request_body: form,
+            status: 200,
+            reason: "OK",
+            response_headers: Vec::new(),
+            body,
+            timestamp: Utc::now(),
+            redirect: None,
+        }
+    }
+
+    /// Archives the pages of the fixture company as those of `company_id`,
+    /// with `company` for its profile page.
+    fn archive_company(archiver: &Archiver, company_id: i32, company: &str) {
+        let locations = include_str!("../test-data/locations.html");
+        archiver
+            .record(&exchange(PageType::Company, company_id, None, company))
+            .unwrap();
+        archiver
+            .record(&exchange(PageType::Locations, company_id, None, locations))
+            .unwrap();
+        for div_id in &["7", "11", "19", "24", "29", "30", "36"] {
+            let category =
+                fs::read_to_string(format!("test-data/category-{}.html", div_id)).unwrap();
+            let form = format!("qp={}&id={}&v=7", company_id, div_id);
+            archiver
+                .record(&exchange(
+                    PageType::Categories,
+                    company_id,
+                    Some(form.as_bytes()),
+                    &category,
+                ))
+                .unwrap();
+            for link in Document::from(category.as_str()).find(Name("a")) {
+                let head_id = link.attr("data-headid").unwrap_or("");
+                let subcategory = fs::read_to_string(format!(
+                    "test-data/subcategory-{}-{}.html",
+                    div_id, head_id
+                ))
+                .unwrap();
+                let form = format!("qp={}&id={}&v=7&headingID={}", company_id, div_id, head_id);
+                archiver
+                    .record(&exchange(
+                        PageType::Categories,
+                        company_id,
+                        Some(form.as_bytes()),
+                        &subcategory,
+                    ))
+                    .unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reparse_archive() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("reparse");
+        let archiver = Archiver::open(&dir, DEFAULT_MAX_FILE_SIZE).unwrap();
+        let company = include_str!("../test-data/company.html");
+        archive_company(&archiver, 922369, company);
+        // Locations page was never fetched for this one.
+        archiver
+            .record(&exchange(PageType::Company, 400516, None, company))
+            .unwrap();
+
+        let reader = Arc::new(ArchiveReader::open(&dir).unwrap());
+        let output = dir.join("profiles.jsonl");
+        let mut sink = JsonLinesSink::create(&output).unwrap();
+        let report = reparse(reader.clone(), &[], &HashMap::new(), &mut sink)
+            .await
+            .unwrap();
+        sink.finish().unwrap();
+        assert_eq!(report.added, vec![922369]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, 400516);
+
+        let mut previous = read_profiles(&output).unwrap();
+        let profile = &previous[&922369];
+        assert_eq!(profile.story, "TEST STORY");
+        assert!(!profile.locations.is_empty());
+        assert_eq!(profile.categories.len(), 104);
+
+        let mut sink = JsonLinesSink::create(&output).unwrap();
+        let report = reparse(reader.clone(), &[922369], &previous, &mut sink)
+            .await
+            .unwrap();
+        sink.finish().unwrap();
+        assert_eq!(report.unchanged, 1);
+
+        previous.get_mut(&922369).unwrap().story = "OLD STORY".to_string();
+        let mut sink = JsonLinesSink::create(&output).unwrap();
+        let report = reparse(reader, &[922369], &previous, &mut sink)
+            .await
+            .unwrap();
+        sink.finish().unwrap();
+        assert_eq!(report.changed, vec![922369]);
+        assert_eq!(read_profiles(&output).unwrap()[&922369].story, "TEST STORY");
+    }
+    #[tokio::test]
+    async fn test_reparse_is_stable_for_several_states() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("reparse-states");
+        let archiver = Archiver::open(&dir, DEFAULT_MAX_FILE_SIZE).unwrap();
+        let company = include_str!("../test-data/company-two-states.html");
+        archive_company(&archiver, 922369, company);
+
+        let reader = Arc::new(ArchiveReader::open(&dir).unwrap());
+        let output = dir.join("profiles.jsonl");
+        let mut sink = JsonLinesSink::create(&output).unwrap();
+        reparse(reader.clone(), &[], &HashMap::new(), &mut sink)
+            .await
+            .unwrap();
+        sink.finish().unwrap();
+        let previous = read_profiles(&output).unwrap();
+        assert_eq!(previous[&922369].service_areas.len(), 2);
+
+        for _ in 0..5 {
+            let mut sink = JsonLinesSink::create(dir.join("again.jsonl")).unwrap();
+            let report = reparse(reader.clone(), &[], &previous, &mut sink)
+                .await
+                .unwrap();
+            sink.finish().unwrap();
+            assert_eq!(report.unchanged, 1);
+            assert!(report.changed.is_empty());
+        }
+    }
+}
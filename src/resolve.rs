@@ -0,0 +1,580 @@
+use crate::data::{CompanyProfile, PostalAddress};
+use crate::error::Error;
+use crate::normalize::{company_domain, parse_address, parse_phone};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/// Pairs scoring at least this are merged unless a decision says otherwise.
+pub const DEFAULT_THRESHOLD: f64 = 0.7;
+/// Names less alike than this are no evidence at all.
+const MIN_NAME_SIMILARITY: f64 = 0.6;
+/// Keys shared by more companies than this, like a common name word, are
+/// too weak to pair companies on.
+const MAX_BLOCK_SIZE: usize = 100;
+/// Words that say nothing about which business a name is.
+const NAME_STOP_WORDS: &[&str] = &[
+    "the",
+    "and",
+    "of",
+    "inc",
+    "incorporated",
+    "llc",
+    "ltd",
+    "corp",
+    "corporation",
+    "co",
+    "company",
+    "pc",
+    "pllc",
+    "lp",
+    "llp",
+];
+const STREET_ABBREVIATIONS: &[(&str, &str)] = &[
+    ("avenue", "ave"),
+    ("street", "st"),
+    ("road", "rd"),
+    ("drive", "dr"),
+    ("boulevard", "blvd"),
+    ("lane", "ln"),
+    ("place", "pl"),
+    ("court", "ct"),
+    ("parkway", "pkwy"),
+    ("highway", "hwy"),
+    ("north", "n"),
+    ("south", "s"),
+    ("east", "e"),
+    ("west", "w"),
+];
+
+/// One reason two companies may be the same business.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Evidence {
+    Domain { domain: String },
+    Phone { e164: String },
+    Address { address: String },
+    Name { similarity: f64 },
+}
+
+impl Evidence {
+    /// How likely the evidence alone makes a match.
+    fn weight(&self) -> f64 {
+        match self {
+            Evidence::Domain { .. } => 0.6,
+            Evidence::Phone { .. } => 0.5,
+            Evidence::Address { .. } => 0.5,
+            Evidence::Name { similarity } => 0.6 * similarity,
+        }
+    }
+}
+
+impl fmt::Display for Evidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Evidence::Domain { domain } => write!(f, "same domain {}", domain),
+            Evidence::Phone { e164 } => write!(f, "same phone {}", e164),
+            Evidence::Address { address } => write!(f, "same address {}", address),
+            Evidence::Name { similarity } => write!(f, "names {:.0}% alike", similarity * 100.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Merge,
+    Split,
+}
+
+/// A reviewer's call on a pair, overriding its score. Deleting the line
+/// undoes it, the profiles themselves are never changed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Decision {
+    pub a: i32,
+    pub b: i32,
+    pub verdict: Verdict,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// Two companies that may be the same business, with `a < b`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PairMatch {
+    pub a: i32,
+    pub b: i32,
+    /// Between 0 and 1, from the weights of `evidence`.
+    pub score: f64,
+    pub evidence: Vec<Evidence>,
+    pub decision: Option<Verdict>,
+    /// Whether the pair ended up in one cluster through this match.
+    pub merged: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClusterAssignment {
+    pub company_id: i32,
+    /// The lowest company id in the cluster.
+    pub cluster_id: i32,
+}
+
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub assignments: Vec<ClusterAssignment>,
+    /// Scored pairs, best first, for review.
+    pub pairs: Vec<PairMatch>,
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for pair in &self.pairs {
+            let sign = match (pair.merged, pair.decision) {
+                (true, Some(Verdict::Merge)) => "=!",
+                (true, _) => "= ",
+                (false, Some(Verdict::Split)) => "/!",
+                (false, _) => "? ",
+            };
+            let reasons: Vec<String> = pair.evidence.iter().map(ToString::to_string).collect();
+            writeln!(
+                f,
+                "{} {} {} {:.2}: {}",
+                sign,
+                pair.a,
+                pair.b,
+                pair.score,
+                reasons.join(", ")
+            )?;
+        }
+        let clusters: BTreeSet<i32> = self.assignments.iter().map(|a| a.cluster_id).collect();
+        write!(
+            f,
+            "{} companies in {} clusters, {} pairs merged",
+            self.assignments.len(),
+            clusters.len(),
+            self.pairs.iter().filter(|pair| pair.merged).count()
+        )
+    }
+}
+
+/// What a profile is matched on, normalized.
+struct Keys {
+    id: i32,
+    domain: Option<String>,
+    phones: BTreeSet<String>,
+    addresses: BTreeSet<String>,
+    name: Vec<String>,
+}
+
+impl Keys {
+    /// Profiles stored before the fields were normalized are normalized on
+    /// the fly.
+    fn new(profile: &CompanyProfile) -> Keys {
+        let domain = company_domain(&profile.info);
+        let phones = profile
+            .locations
+            .iter()
+            .filter_map(|location| {
+                location
+                    .normalized_phone
+                    .clone()
+                    .or_else(|| parse_phone(&location.phone_number))
+            })
+            .map(|phone| phone.e164)
+            .collect();
+        let addresses = profile
+            .locations
+            .iter()
+            .filter_map(|location| match &location.postal_address {
+                Some(address) => address_key(address),
+                None => parse_address(&location.address).and_then(|a| address_key(&a)),
+            })
+            .collect();
+        Keys {
+            id: profile.id,
+            domain,
+            phones,
+            addresses,
+            name: name_words(&profile.info.name),
+        }
+    }
+
+    /// The blocks a company is put in, only companies sharing one are
+    /// compared.
+    fn blocks(&self) -> Vec<String> {
+        let mut blocks = Vec::new();
+        blocks.extend(self.domain.iter().map(|domain| format!("d:{}", domain)));
+        blocks.extend(self.phones.iter().map(|phone| format!("p:{}", phone)));
+        blocks.extend(
+            self.addresses
+                .iter()
+                .map(|address| format!("a:{}", address)),
+        );
+        blocks.extend(
+            self.name
+                .iter()
+                .filter(|word| word.len() >= 3)
+                .take(2)
+                .map(|word| format!("n:{}", word)),
+        );
+        blocks
+    }
+
+    fn evidence(&self, other: &Keys) -> Vec<Evidence> {
+        let mut evidence = Vec::new();
+        if let (Some(domain), Some(other_domain)) = (&self.domain, &other.domain) {
+            if domain == other_domain {
+                evidence.push(Evidence::Domain {
+                    domain: domain.clone(),
+                });
+            }
+        }
+        if let Some(e164) = self.phones.intersection(&other.phones).next() {
+            evidence.push(Evidence::Phone { e164: e164.clone() });
+        }
+        if let Some(address) = self.addresses.intersection(&other.addresses).next() {
+            evidence.push(Evidence::Address {
+                address: address.clone(),
+            });
+        }
+        let similarity = name_similarity(&self.name, &other.name);
+        if similarity >= MIN_NAME_SIMILARITY {
+            evidence.push(Evidence::Name {
+                similarity: (similarity * 100.0).round() / 100.0,
+            });
+        }
+        evidence
+    }
+}
+
+/// Clusters `profiles` that are likely one business. Pairs are merged from
+/// the best score down, never joining clusters a `Split` decision keeps
+/// apart. `Merge` decisions are applied first, whatever their score.
+pub fn resolve(profiles: &[CompanyProfile], decisions: &[Decision], threshold: f64) -> Resolution {
+    let keys: Vec<Keys> = profiles.iter().map(Keys::new).collect();
+    let index: HashMap<i32, usize> = keys.iter().enumerate().map(|(i, k)| (k.id, i)).collect();
+
+    let mut blocks: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, key) in keys.iter().enumerate() {
+        for block in key.blocks() {
+            blocks.entry(block).or_default().push(i);
+        }
+    }
+    let mut candidates: BTreeSet<(usize, usize)> = BTreeSet::new();
+    for members in blocks.values().filter(|m| m.len() <= MAX_BLOCK_SIZE) {
+        for (n, &i) in members.iter().enumerate() {
+            for &j in &members[n + 1..] {
+                if i != j {
+                    candidates.insert((i.min(j), i.max(j)));
+                }
+            }
+        }
+    }
+
+    let verdicts: HashMap<(i32, i32), Verdict> = decisions
+        .iter()
+        .map(|d| ((d.a.min(d.b), d.a.max(d.b)), d.verdict))
+        .collect();
+    for (a, b) in verdicts.keys() {
+        if let (Some(&i), Some(&j)) = (index.get(a), index.get(b)) {
+            candidates.insert((i.min(j), i.max(j)));
+        }
+    }
+
+    let mut pairs: Vec<PairMatch> = candidates
+        .into_iter()
+        .filter_map(|(i, j)| {
+            let (a, b) = (keys[i].id.min(keys[j].id), keys[i].id.max(keys[j].id));
+            let evidence = keys[i].evidence(&keys[j]);
+            let decision = verdicts.get(&(a, b)).copied();
+            if evidence.is_empty() && decision.is_none() {
+                return None;
+            }
+            let miss: f64 = evidence.iter().map(|e| 1.0 - e.weight()).product();
+            Some(PairMatch {
+                a,
+                b,
+                score: ((1.0 - miss) * 100.0).round() / 100.0,
+                evidence,
+                decision,
+                merged: false,
+            })
+        })
+        .collect();
+    pairs.sort_by(|x, y| {
+        let forced = |pair: &PairMatch| pair.decision == Some(Verdict::Merge);
+        forced(y)
+            .cmp(&forced(x))
+            .then(
+                y.score
+                    .partial_cmp(&x.score)
+                    .unwrap_or(std::cmp::Ordering::Equal),
+            )
+            .then((x.a, x.b).cmp(&(y.a, y.b)))
+    });
+
+    let mut clusters = Clusters::new(keys.len());
+    let splits: Vec<(usize, usize)> = verdicts
+        .iter()
+        .filter(|(_, verdict)| **verdict == Verdict::Split)
+        .filter_map(|((a, b), _)| Some((*index.get(a)?, *index.get(b)?)))
+        .collect();
+    for pair in &mut pairs {
+        let wanted = match pair.decision {
+            Some(Verdict::Merge) => true,
+            Some(Verdict::Split) => false,
+            None => pair.score >= threshold,
+        };
+        if wanted {
+            pair.merged = clusters.union(index[&pair.a], index[&pair.b], &splits);
+        }
+    }
+
+    let mut members: BTreeMap<usize, Vec<i32>> = BTreeMap::new();
+    for (i, key) in keys.iter().enumerate() {
+        members.entry(clusters.find(i)).or_default().push(key.id);
+    }
+    let mut assignments: Vec<ClusterAssignment> = members
+        .values()
+        .flat_map(|ids| {
+            let cluster_id = *ids.iter().min().expect("Clusters have members");
+            ids.iter().map(move |&company_id| ClusterAssignment {
+                company_id,
+                cluster_id,
+            })
+        })
+        .collect();
+    assignments.sort_by_key(|assignment| assignment.company_id);
+    Resolution { assignments, pairs }
+}
+
+/// Decisions from a JSON lines file, one [`Decision`] per line. A missing
+/// file just means none were made yet.
+pub fn read_decisions(path: impl AsRef<Path>) -> Result<Vec<Decision>, Error> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut decisions = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        decisions.push(serde_json::from_str(&line)?);
+    }
+    Ok(decisions)
+}
+
+/// Union-find over profile indices.
+struct Clusters {
+    parents: Vec<usize>,
+}
+
+impl Clusters {
+    fn new(size: usize) -> Clusters {
+        Clusters {
+            parents: (0..size).collect(),
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    /// Joins the clusters of `i` and `j` unless a pair of `splits` would end
+    /// up in one. Returns whether it did.
+    fn union(&mut self, i: usize, j: usize, splits: &[(usize, usize)]) -> bool {
+        let (root_i, root_j) = (self.find(i), self.find(j));
+        if root_i == root_j {
+            return false;
+        }
+        for &(x, y) in splits {
+            let (root_x, root_y) = (self.find(x), self.find(y));
+            if (root_x == root_i && root_y == root_j) || (root_x == root_j && root_y == root_i) {
+                return false;
+            }
+        }
+        self.parents[root_j] = root_i;
+        true
+    }
+}
+
+fn name_words(name: &str) -> Vec<String> {
+    name.to_lowercase()
+        .replace('&', " and ")
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !NAME_STOP_WORDS.contains(word))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Dice coefficient of the character pairs of both names, so word order
+/// and small spelling differences matter little.
+fn name_similarity(a: &[String], b: &[String]) -> f64 {
+    let bigrams = |words: &[String]| -> Vec<(char, char)> {
+        let mut pairs: Vec<(char, char)> = words
+            .iter()
+            .flat_map(|word| {
+                let chars: Vec<char> = word.chars().collect();
+                chars.windows(2).map(|w| (w[0], w[1])).collect::<Vec<_>>()
+            })
+            .collect();
+        pairs.sort_unstable();
+        pairs
+    };
+    let (a, b) = (bigrams(a), bigrams(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let mut remaining = b.clone();
+    let mut shared = 0;
+    for pair in &a {
+        if let Ok(position) = remaining.binary_search(pair) {
+            remaining.remove(position);
+            shared += 1;
+        }
+    }
+    2.0 * shared as f64 / (a.len() + b.len()) as f64
+}
+
+/// Street and ZIP code, the parts that tell buildings apart. `None` without
+/// a street, a city alone would put whole towns in one cluster.
+fn address_key(address: &PostalAddress) -> Option<String> {
+    let street = address.street.as_ref()?;
+    let zip = address.zip.as_ref()?;
+    let words: Vec<&str> = street
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    let words: Vec<String> = words
+        .iter()
+        .map(|word| {
+            let word = word.to_lowercase();
+            STREET_ABBREVIATIONS
+                .iter()
+                .find(|(long, _)| *long == word)
+                .map(|(_, short)| short.to_string())
+                .unwrap_or(word)
+        })
+        .collect();
+    Some(format!("{} {}", words.join(" "), zip))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::resolve::*;
+    use crate::test_support::{fixture_profile, variant};
+
+    /// The fixture company, a branch of it under another id and an
+    /// unrelated company.
+    async fn profiles() -> Vec<CompanyProfile> {
+        let skyview = fixture_profile().await;
+        let branch = variant(&skyview, 922400, |branch| {
+            branch.info.name = "Skyview A/C & Heating Corporation".to_string();
+            branch.info.website = "http://skyviewac.com/".to_string();
+            branch.info.facts.website = None;
+            branch.locations[0].address = "20 Main St. Hempstead, NY 11550".to_string();
+            branch.locations[0].postal_address = None;
+            branch.locations[0].phone_number = "(516) 555-0199".to_string();
+            branch.locations[0].normalized_phone = None;
+        });
+        let other = variant(&skyview, 100, |other| {
+            other.info.name = "Acme Plumbing LLC".to_string();
+            other.info.website = "https://www.facebook.com/acmeplumbing".to_string();
+            other.info.facts.website = None;
+            other.locations[0].address = "1 Elm St. Albany, NY 12207".to_string();
+            other.locations[0].postal_address = None;
+            other.locations[0].phone_number = "(518) 555-0100".to_string();
+            other.locations[0].normalized_phone = None;
+        });
+
+        vec![skyview, branch, other]
+    }
+
+    fn clusters(resolution: &Resolution) -> Vec<(i32, i32)> {
+        resolution
+            .assignments
+            .iter()
+            .map(|a| (a.company_id, a.cluster_id))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_resolve() {
+        let profiles = profiles().await;
+        let resolution = resolve(&profiles, &[], DEFAULT_THRESHOLD);
+        assert_eq!(
+            clusters(&resolution),
+            vec![(100, 100), (922369, 922369), (922400, 922369)]
+        );
+        assert_eq!(resolution.pairs.len(), 1);
+        let pair = &resolution.pairs[0];
+        assert_eq!((pair.a, pair.b), (922369, 922400));
+        assert!(pair.merged);
+        assert_eq!(
+            pair.evidence[0],
+            Evidence::Domain {
+                domain: "skyviewac.com".to_string()
+            }
+        );
+        assert!(matches!(pair.evidence[1], Evidence::Name { similarity } if similarity > 0.6));
+        assert!(resolution
+            .to_string()
+            .starts_with("=  922369 922400 0.76: same domain skyviewac.com, names 65% alike\n"));
+
+        // A shared phone and the same address, without the website or a
+        // similar name, is a match as well.
+        let mut moved = profiles.clone();
+        moved[1].info.website = String::new();
+        moved[1].info.name = "SAC Holdings".to_string();
+        moved[1].locations = moved[0].locations.clone();
+        let resolution = resolve(&moved, &[], DEFAULT_THRESHOLD);
+        assert_eq!(resolution.pairs[0].score, 0.75);
+        assert!(resolution.pairs[0].merged);
+    }
+
+    #[tokio::test]
+    async fn test_decisions() {
+        let profiles = profiles().await;
+        let split = Decision {
+            a: 922400,
+            b: 922369,
+            verdict: Verdict::Split,
+            note: Some("different owners".to_string()),
+        };
+        let merge = Decision {
+            a: 100,
+            b: 922400,
+            verdict: Verdict::Merge,
+            note: None,
+        };
+
+        let resolution = resolve(&profiles, std::slice::from_ref(&split), DEFAULT_THRESHOLD);
+        assert_eq!(
+            clusters(&resolution),
+            vec![(100, 100), (922369, 922369), (922400, 922400)]
+        );
+        assert!(resolution.to_string().starts_with("/! 922369 922400"));
+
+        let resolution = resolve(&profiles, std::slice::from_ref(&merge), DEFAULT_THRESHOLD);
+        assert_eq!(
+            clusters(&resolution),
+            vec![(100, 100), (922369, 100), (922400, 100)]
+        );
+
+        // Both apply together.
+        let resolution = resolve(&profiles, &[split, merge], DEFAULT_THRESHOLD);
+        assert_eq!(
+            clusters(&resolution),
+            vec![(100, 100), (922369, 922369), (922400, 100)]
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use crate::api::{get_keyword_search_page, get_search_page, ApiResult, Client};
+use crate::data::SearchSummary;
+use crate::error::Error;
+use crate::parser::{self, get_page_ids, get_pages_count, get_search_summaries};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use select::document::Document;
+use std::collections::{HashMap, HashSet};
+use tracing::{info_span, Instrument};
+
+#[derive(Debug, Clone, Copy)]
+pub enum SearchMode<'a> {
+    Class(i32),
+    /// A free-text search, optionally narrowed down by a second term the way
+    /// the site's "search within results" box does.
+    Keyword {
+        term: &'a str,
+        within: Option<&'a str>,
+    },
+}
+
+async fn get_results_page(
+    client: &Client,
+    mode: SearchMode<'_>,
+    page_number: i32,
+    city: &str,
+) -> ApiResult {
+    let span = info_span!("search_page", page_number, city);
+    match mode {
+        SearchMode::Class(class_id) => {
+            get_search_page(client, class_id, page_number, city)
+                .instrument(span)
+                .await
+        }
+        SearchMode::Keyword { term, within } => {
+            get_keyword_search_page(client, term, within, page_number, city)
+                .instrument(span)
+                .await
+        }
+    }
+}
+
+/// How many pages a listing has, from its first page. Listings with a
+/// single page have no pager at all, but a pager that doesn't parse is an
+/// error rather than a listing cut short.
+pub async fn pages_count(first_page: &Document) -> Result<i32, Error> {
+    match get_pages_count(first_page).await {
+        Ok(count) => Ok(count),
+        Err(parser::Error::CannotFindNode { .. }) => Ok(1),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Fetches the first results page to learn the page count and then the rest
+/// of the pages, up to `concurrency` at a time.
+async fn get_all_results_pages(
+    client: &Client,
+    mode: SearchMode<'_>,
+    city: &str,
+    concurrency: usize,
+) -> Result<Vec<Document>, Error> {
+    let first_page = get_results_page(client, mode, 1, city).await?;
+    let pages_count = pages_count(&first_page).await?;
+
+    let mut pages = vec![first_page];
+    pages.append(
+        &mut stream::iter(2..=pages_count)
+            .map(|n| get_results_page(client, mode, n, city))
+            .buffered(concurrency.max(1))
+            .try_collect()
+            .await?,
+    );
+    Ok(pages)
+}
+
+/// Every results page of a search in each of `cities`, in order. The first
+/// is also where the site puts its suggestions.
+pub async fn search_pages(
+    client: &Client,
+    mode: SearchMode<'_>,
+    cities: &[&str],
+    concurrency: usize,
+) -> Result<Vec<Document>, Error> {
+    let mut pages = Vec::new();
+    for city in cities {
+        pages.append(&mut get_all_results_pages(client, mode, city, concurrency).await?);
+    }
+    Ok(pages)
+}
+
+/// The companies listed on `pages`, each once.
+pub async fn page_ids(pages: &[Document]) -> Result<Vec<i32>, Error> {
+    let mut seen = HashSet::new();
+    let mut ids = Vec::new();
+    for page in pages {
+        for id in get_page_ids(page).await? {
+            if seen.insert(id) {
+                ids.push(id)
+            }
+        }
+    }
+    Ok(ids)
+}
+
+/// The summaries of the companies listed on `pages`, each once.
+pub async fn page_summaries(pages: &[Document]) -> Result<Vec<SearchSummary>, Error> {
+    let mut seen = HashSet::new();
+    let mut summaries = Vec::new();
+    for page in pages {
+        for summary in get_search_summaries(page).await? {
+            if seen.insert(summary.id) {
+                summaries.push(summary)
+            }
+        }
+    }
+    Ok(summaries)
+}
+
+pub async fn search_ids(
+    client: &Client,
+    mode: SearchMode<'_>,
+    cities: &[&str],
+    concurrency: usize,
+) -> Result<Vec<i32>, Error> {
+    page_ids(&search_pages(client, mode, cities, concurrency).await?).await
+}
+
+/// The ids [`search_ids`] finds, each with its summary where the listing
+/// has one that parses.
+pub async fn search_listings(
+    client: &Client,
+    mode: SearchMode<'_>,
+    cities: &[&str],
+    concurrency: usize,
+) -> Result<Vec<(i32, Option<SearchSummary>)>, Error> {
+    let mut seen = HashSet::new();
+    let mut listings = Vec::new();
+    for page in search_pages(client, mode, cities, concurrency).await? {
+        let mut summaries: HashMap<i32, SearchSummary> = get_search_summaries(&page)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|summary| (summary.id, summary))
+            .collect();
+        for id in get_page_ids(&page).await? {
+            if seen.insert(id) {
+                listings.push((id, summaries.remove(&id)))
+            }
+        }
+    }
+    Ok(listings)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{client_factory, Client, Endpoints, HeaderProfile, SessionConfig};
+    use crate::mock::MockServer;
+    use crate::search::*;
+
+    #[tokio::test]
+    async fn test_search_ids_by_keyword() {
+        let client = Client::fixtures("test-data");
+        let mode = SearchMode::Keyword {
+            term: "curtain wall",
+            within: None,
+        };
+        let ids = search_ids(&client, mode, &["New York, NY"], 2)
+            .await
+            .unwrap();
+        // All six pages come from the same fixture, so only its ten
+        // companies remain after deduplication.
+        assert_eq!(ids.len(), 10)
+    }
+
+    #[tokio::test]
+    async fn test_search_within() {
+        let server = MockServer::start().await;
+        let endpoints = Endpoints::for_base(&server.base_url()).unwrap();
+        let client = client_factory(None, HeaderProfile::default(), SessionConfig::default())
+            .await
+            .unwrap()
+            .with_endpoints(endpoints);
+        let mode = SearchMode::Keyword {
+            term: "curtain wall",
+            within: Some("glazing"),
+        };
+        let pages = search_pages(&client, mode, &["New York, NY"], 2)
+            .await
+            .unwrap();
+
+        assert_eq!(pages.len(), 6);
+        let queries = server.queries("/search.html");
+        assert_eq!(queries.len(), 6);
+        assert!(queries[0].starts_with("page=1&searchTerm=curtain+wall&"));
+        assert!(queries
+            .iter()
+            .all(|query| query.ends_with("&pSeachinp=glazing")));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_pager_is_an_error() {
+        let single = Document::from("<div class=\"cname\"></div>");
+        assert_eq!(pages_count(&single).await.unwrap(), 1);
+        let malformed = Document::from(
+            "<div class=\"pager-outer-wrapper\"><span class=\"dropdown\">of many</span></div>",
+        );
+        assert!(pages_count(&malformed).await.is_err());
+    }
+}
@@ -0,0 +1,287 @@
+use crate::data::CompanyProfile;
+use crate::error::Error;
+use crate::gazetteer::Gazetteer;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Writes one profile per line, to a file or to stdout. A new file is
+/// written next to its final path until [`JsonLinesSink::finish`], so an
+/// interrupted run never leaves a half-written file in place of the previous
+/// one. Appending goes straight to the file, so a resumed crawl keeps
+/// whatever was written before it stopped.
+pub struct JsonLinesSink {
+    writer: BufWriter<Box<dyn Write + Send>>,
+    rename: Option<(PathBuf, PathBuf)>,
+    gazetteer: Option<Gazetteer>,
+}
+
+impl JsonLinesSink {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+        let file = File::create(&temp_path)?;
+        Ok(JsonLinesSink {
+            writer: BufWriter::new(Box::new(file)),
+            rename: Some((temp_path, path)),
+            gazetteer: None,
+        })
+    }
+
+    pub fn append(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(JsonLinesSink {
+            writer: BufWriter::new(Box::new(file)),
+            rename: None,
+            gazetteer: None,
+        })
+    }
+
+    pub fn stdout() -> Self {
+        JsonLinesSink {
+            writer: BufWriter::new(Box::new(io::stdout())),
+            rename: None,
+            gazetteer: None,
+        }
+    }
+
+    /// Geocodes every profile before it is written.
+    pub fn with_gazetteer(mut self, gazetteer: Gazetteer) -> Self {
+        self.gazetteer = Some(gazetteer);
+        self
+    }
+
+    /// Adds what the sink adds to profiles it writes, for comparing one with
+    /// a profile written earlier.
+    pub fn prepare(&self, profile: &mut CompanyProfile) {
+        if let Some(gazetteer) = &self.gazetteer {
+            gazetteer.geocode_profile(profile);
+        }
+    }
+
+    pub fn write(&mut self, profile: &CompanyProfile) -> Result<(), Error> {
+        match &self.gazetteer {
+            Some(gazetteer) => {
+                let mut profile = profile.clone();
+                gazetteer.geocode_profile(&mut profile);
+                serde_json::to_writer(&mut self.writer, &profile)?;
+            }
+            None => serde_json::to_writer(&mut self.writer, profile)?,
+        }
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        match &self.rename {
+            Some((temp_path, path)) => fs::rename(temp_path, path),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Profiles written by an earlier run, by company id. A missing file just
+/// means there weren't any.
+pub fn read_profiles(path: impl AsRef<Path>) -> Result<HashMap<i32, CompanyProfile>, Error> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut profiles = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let profile: CompanyProfile = serde_json::from_str(&line)?;
+        profiles.insert(profile.id, profile);
+    }
+    Ok(profiles)
+}
+
+/// Like [`read_profiles`], but in id order and one profile at a time, so the
+/// file needn't fit in memory. Opening it notes where the last line of each
+/// id starts.
+pub fn stream_profiles(path: impl AsRef<Path>) -> Result<ProfileStream, Error> {
+    let mut reader = match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(ProfileStream {
+                reader: None,
+                offsets: Vec::new().into_iter(),
+            })
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    #[derive(Deserialize)]
+    struct Id {
+        id: i32,
+    }
+    let mut offsets = HashMap::new();
+    let mut offset = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        if read == 0 {
+            break;
+        }
+        if !line.trim().is_empty() {
+            let Id { id } = serde_json::from_str(&line)?;
+            offsets.insert(id, offset);
+        }
+        offset += read as u64;
+    }
+    let mut offsets: Vec<(i32, u64)> = offsets.into_iter().collect();
+    offsets.sort_unstable();
+    Ok(ProfileStream {
+        reader: Some(reader),
+        offsets: offsets.into_iter(),
+    })
+}
+
+/// Rewrites the profiles at `path` with only the last line of each company,
+/// in id order, for files appended to run after run.
+pub fn compact_profiles(path: impl AsRef<Path>) -> Result<(), Error> {
+    let path = path.as_ref();
+    let mut sink = JsonLinesSink::create(path)?;
+    for profile in stream_profiles(path)? {
+        sink.write(&profile?)?;
+    }
+    sink.finish()?;
+    Ok(())
+}
+
+/// See [`stream_profiles`].
+pub struct ProfileStream {
+    reader: Option<BufReader<File>>,
+    /// Company ids and where their lines start, by id.
+    offsets: std::vec::IntoIter<(i32, u64)>,
+}
+
+impl ProfileStream {
+    /// The ids of the profiles still to come, without reading them.
+    pub fn company_ids(&self) -> impl Iterator<Item = i32> + '_ {
+        self.offsets.as_slice().iter().map(|(id, _)| *id)
+    }
+
+    fn read_at(&mut self, offset: u64) -> Result<CompanyProfile, Error> {
+        let reader = match &mut self.reader {
+            Some(reader) => reader,
+            None => unreachable!("no offsets without a file"),
+        };
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+impl Iterator for ProfileStream {
+    type Item = Result<CompanyProfile, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (_, offset) = self.offsets.next()?;
+        Some(self.read_at(offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sink::*;
+
+    fn profile(id: i32) -> CompanyProfile {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "info": {
+                "name": "Test", "rating": null, "website": "", "founded": null,
+                "size": null, "duns": null, "other": [], "annual_vol": null
+            },
+            "locations": [], "contacts": [], "story": "", "categories": [],
+            "service_areas": [],
+            "project_experience": { "general": [], "commercial": [] },
+            "emails": [], "logo": null, "preferred_brands": [], "social_media": [],
+            "qualifications": [], "media_gallery": [], "projects": []
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_create_and_append() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("sink.jsonl");
+
+        let mut sink = JsonLinesSink::create(&path).unwrap();
+        sink.write(&profile(1)).unwrap();
+        assert!(read_profiles(&path).unwrap().is_empty());
+        sink.finish().unwrap();
+
+        let mut sink = JsonLinesSink::append(&path).unwrap();
+        sink.write(&profile(2)).unwrap();
+        sink.finish().unwrap();
+
+        let mut ids: Vec<i32> = read_profiles(&path).unwrap().into_keys().collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_stream_profiles() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("stream.jsonl");
+        let mut sink = JsonLinesSink::create(&path).unwrap();
+        for id in &[3, 1, 2] {
+            sink.write(&profile(*id)).unwrap();
+        }
+        let mut renamed = profile(3);
+        renamed.info.name = "Renamed".to_string();
+        sink.write(&renamed).unwrap();
+        sink.finish().unwrap();
+
+        let profiles: Vec<CompanyProfile> = stream_profiles(&path)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let ids: Vec<i32> = profiles.iter().map(|profile| profile.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(profiles[2].info.name, "Renamed");
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(stream_profiles(&path).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_compact_profiles() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("compact.jsonl");
+        for name in &["First", "Second"] {
+            let mut sink = JsonLinesSink::append(&path).unwrap();
+            for id in &[2, 1] {
+                let mut profile = profile(*id);
+                profile.info.name = name.to_string();
+                sink.write(&profile).unwrap();
+            }
+            sink.finish().unwrap();
+        }
+
+        compact_profiles(&path).unwrap();
+        let lines: Vec<CompanyProfile> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let lines: Vec<(i32, &str)> = lines
+            .iter()
+            .map(|profile| (profile.id, profile.info.name.as_str()))
+            .collect();
+        assert_eq!(lines, vec![(1, "Second"), (2, "Second")]);
+    }
+}
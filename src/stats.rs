@@ -0,0 +1,92 @@
+use crate::data::CompanyProfile;
+use crate::normalize::shared_numbers;
+use std::fmt;
+
+/// How complete a set of profiles is, field by field.
+#[derive(Debug, Default, PartialEq)]
+pub struct ProfileStats {
+    pub companies: usize,
+    pub with_website: usize,
+    pub with_rating: usize,
+    pub with_story: usize,
+    pub with_locations: usize,
+    pub with_contacts: usize,
+    pub with_categories: usize,
+    pub with_service_areas: usize,
+    /// Companies where a contact is listed with a location's number.
+    pub with_shared_phones: usize,
+    pub locations: usize,
+    pub contacts: usize,
+    pub categories: usize,
+}
+
+pub fn profile_stats<'a>(profiles: impl IntoIterator<Item = &'a CompanyProfile>) -> ProfileStats {
+    let mut stats = ProfileStats::default();
+    for profile in profiles {
+        let count = |present: bool| if present { 1 } else { 0 };
+        stats.companies += 1;
+        stats.with_website += count(!profile.info.website.is_empty());
+        stats.with_rating += count(profile.info.rating.is_some());
+        stats.with_story += count(!profile.story.is_empty());
+        stats.with_locations += count(!profile.locations.is_empty());
+        stats.with_contacts += count(!profile.contacts.is_empty());
+        stats.with_categories += count(!profile.categories.is_empty());
+        stats.with_service_areas += count(!profile.service_areas.is_empty());
+        stats.with_shared_phones += count(!shared_numbers(profile).is_empty());
+        stats.locations += profile.locations.len();
+        stats.contacts += profile.contacts.len();
+        stats.categories += profile.categories.len();
+    }
+    stats
+}
+
+impl fmt::Display for ProfileStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "companies:          {}", self.companies)?;
+        let rows = [
+            ("website", self.with_website, None),
+            ("rating", self.with_rating, None),
+            ("story", self.with_story, None),
+            ("locations", self.with_locations, Some(self.locations)),
+            ("contacts", self.with_contacts, Some(self.contacts)),
+            ("categories", self.with_categories, Some(self.categories)),
+            ("service areas", self.with_service_areas, None),
+            ("shared phones", self.with_shared_phones, None),
+        ];
+        for (name, with, total) in rows.iter() {
+            let share = if self.companies == 0 {
+                0.0
+            } else {
+                *with as f64 * 100.0 / self.companies as f64
+            };
+            write!(f, "{:<20}{} ({:.1}%)", format!("{}:", name), with, share)?;
+            if let Some(total) = total {
+                write!(f, ", {} in total", total)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::*;
+    use crate::test_support::{fixture_profile, variant};
+
+    #[tokio::test]
+    async fn test_profile_stats() {
+        let profile = fixture_profile().await;
+        let empty = variant(&profile, 2, |empty| {
+            empty.story.clear();
+            empty.categories.clear();
+        });
+
+        let stats = profile_stats(vec![&profile, &empty]);
+        assert_eq!(stats.companies, 2);
+        assert_eq!(stats.with_story, 1);
+        assert_eq!(stats.with_categories, 1);
+        assert_eq!(stats.categories, profile.categories.len());
+        assert!(stats.to_string().contains("story:              1 (50.0%)"));
+    }
+}
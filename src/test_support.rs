@@ -0,0 +1,24 @@
+//! Profiles for the tests of modules that work on scraped companies.
+
+use crate::api::Client;
+use crate::crawler::scrape_company;
+use crate::data::CompanyProfile;
+
+/// The fixture company, 922369, as scraped from `test-data/`.
+pub async fn fixture_profile() -> CompanyProfile {
+    scrape_company(&Client::fixtures("test-data"), 922369)
+        .await
+        .unwrap()
+}
+
+/// A copy of `profile` as another company, `id`, changed by `edit`.
+pub fn variant(
+    profile: &CompanyProfile,
+    id: i32,
+    edit: impl FnOnce(&mut CompanyProfile),
+) -> CompanyProfile {
+    let mut variant = profile.clone();
+    variant.id = id;
+    edit(&mut variant);
+    variant
+}